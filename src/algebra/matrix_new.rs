@@ -93,6 +93,12 @@ impl<const N: usize> Matrix<N> {
         m.data_iter_mut().for_each(|v| *v = 1.0);
         m
     }
+
+    pub fn identity() -> Self {
+        let mut m = Self::new();
+        (0..N).for_each(|i| m.0[i][i] = 1.0);
+        m
+    }
 }
 
 impl<const N: usize> Matrix<N> {
@@ -101,60 +107,48 @@ impl<const N: usize> Matrix<N> {
         (self.cofactor().transpose()) * (1.0 / det)
     }
 
-    fn minor_iter<'a>(
-        iter: impl Iterator<Item = &'a f32> + Clone,
-        i: usize,
-        n: usize,
-    ) -> impl Iterator<Item = &'a f32> + Clone {
-        iter.enumerate().filter_map(move |(idx, v)| {
-            if idx / n != i / n && idx % n != i % n {
-                Some(v)
-            } else {
-                None
-            }
-        })
+    // Remove the row and the column of the flat index `i` from an `n` by `n` matrix.
+    fn minor(data: &[f32], i: usize, n: usize) -> Vec<f32> {
+        data.iter()
+            .enumerate()
+            .filter(|(idx, _)| idx / n != i / n && idx % n != i % n)
+            .map(|(_, &v)| v)
+            .collect()
     }
 
     fn cofactor(&self) -> Self {
+        let data = self.data_iter().copied().collect::<Vec<_>>();
         let mut m = Self::new();
-        for i in 0..N {
-            let minor = Self::minor_iter(self.data_iter(), i, N);
-            let det: f32 = Self::determinant_iter(minor, N - 1);
-            let v = if i % 2 == 0 { det } else { -det };
+        for i in 0..N * N {
+            let det = Self::determinant_of(&Self::minor(&data, i, N), N - 1);
+            let v = (-1f32).powi((i / N + i % N) as i32) * det;
             unsafe {
-                m.set_unchecked(i, 0, v);
+                m.set_unchecked(i / N, i % N, v);
             }
         }
         m
     }
 
     pub fn determinant(&self) -> f32 {
-        let iter = self.data_iter();
-        Self::determinant_iter(iter, N)
+        let data = self.data_iter().copied().collect::<Vec<_>>();
+        Self::determinant_of(&data, N)
     }
 
-    fn determinant_iter<'a>(mut iter: impl Iterator<Item = &'a f32> + Clone, size: usize) -> f32 {
+    // Laplace expansion along the first row.
+    fn determinant_of(data: &[f32], size: usize) -> f32 {
         match size {
-            1 => *iter.next().unwrap(),
-            2 => {
-                let a = *iter.next().unwrap();
-                let b = *iter.next().unwrap();
-                let c = *iter.next().unwrap();
-                let d = *iter.next().unwrap();
-                a * d - b * c
-            }
-            _ => {
-                let mut det = 0.0;
-                let data_iter = iter.clone().take(size);
-                for (i, v) in (0..size).zip(data_iter) {
-                    let it = iter.clone();
-                    let minor_iter = Self::minor_iter(it, i, size);
-                    let minor_det = Self::determinant_iter(minor_iter, size - 1);
-                    let d = minor_det * *v;
-                    det += if i % 2 == 0 { d } else { -d };
-                }
-                det
-            }
+            1 => data[0],
+            2 => data[0] * data[3] - data[1] * data[2],
+            _ => (0..size)
+                .map(|i| {
+                    let d = data[i] * Self::determinant_of(&Self::minor(data, i, size), size - 1);
+                    if i % 2 == 0 {
+                        d
+                    } else {
+                        -d
+                    }
+                })
+                .sum(),
         }
     }
 }
@@ -48,4 +48,28 @@ impl Matrix4 {
             [0.0, 0.0, 0.0, 1.0],
         ])
     }
+    /// Rotation of a unit quaternion `[x, y, z, w]`.
+    pub fn quaternion_matrix([x, y, z, w]: [f32; 4]) -> Matrix4 {
+        matrix4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
 }
@@ -0,0 +1,770 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    pipeline::{
+        camera::{Bokeh, Camera, Lens, Projection},
+        fragment_shader::SHADER_NAMES,
+        framebuffer::{ToneMapping, ToneMappingOperator},
+        light::Light,
+        material::MaterialNew,
+        model::Model,
+        rasterizer::Multisample,
+        shadow_map::ShadowMapSettings,
+    },
+    ray_tracing::{
+        adaptive::AdaptiveSampling,
+        bvh::BVHTree,
+        filter::Filter,
+        path_tracing::RayTracer,
+        sampler::{make_sampler, SAMPLER_NAMES},
+    },
+    renderer::Renderer,
+    scene::Scene,
+};
+use std::{path::Path, time::Duration};
+
+pub const USAGE: &str = "\
+Usage: tiny-renderer <command> [options]
+
+Commands:
+  view <model>                 Open the model in a window
+  render <model> -o <file>     Render the model to an image file
+  info <model>                 Print the meshes and materials of the model
+  convert <model> <file.obj>   Write the model's geometry as Wavefront OBJ
+
+Models are `.obj`, `.gltf` or `.glb` files, or `.json` scene files.
+
+Options:
+  -s, --shader <name>          z, color, texture, phong-color, phong-texture, pbr-raster
+                               or pbr (phong-color for `.obj`, pbr for glTF by default)
+  -t, --texture <file>         Texture image (.jpg/.png) for the texture shaders
+  -o, --output <file>          Output image, the format (.png/.jpg/.ppm) follows the extension
+      --size <width>x<height>  Resolution, 800x800 by default
+      --spp <n>                Samples per pixel of the pbr shader, 8 by default
+      --sampler <name>         independent, stratified, halton or sobol (the default)
+      --seed <n>               Seed of the sampler, the same seed renders the same image
+      --filter <name>          box (the default), tent, gaussian, mitchell or lanczos
+      --filter-radius <pixels> Radius of the pixel filter
+      --adaptive <error>       Keep sampling noisy pixels until this relative error (e.g. 0.02),
+                               every pixel takes `--spp` samples first
+      --max-spp <n>            Samples of a pixel at most with `--adaptive`, 1024 by default
+      --time-budget <seconds>  Stop adaptive sampling after this long
+      --sample-budget <spp>    Stop adaptive sampling at this mean spp over the image
+      --heatmap <file>         Write the samples taken in every pixel with `--adaptive`
+      --eye <x,y,z>            Camera position
+      --target <x,y,z>         Point the camera looks at
+      --fov <degrees>          Vertical field of view
+      --camera <index|name>    glTF camera to use, the first one by default
+      --aperture <radius>      Lens radius of the pbr shader, enables depth of field
+      --focus <distance>       Focal distance, the surface at the image center by default
+      --focus-pixel <x,y>      Focus on the surface seen through this pixel
+      --bokeh <circle|blades>  Circular aperture, or a polygon with that many blades
+      --light <x,y,z>          Light position
+      --light-intensity <f>    Light intensity
+      --msaa <1|2|4|8>         Rasterizer samples per pixel
+      --no-shadow              Disable shadow mapping of the phong shaders
+      --tone-mapping <name>    exposure, reinhard or aces
+      --exposure <f>           Exposure multiplier, 1.0 by default
+  -h, --help                   Print this help";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    View(RenderOptions),
+    Render(RenderOptions),
+    Info { model: String },
+    Convert { input: String, output: String },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub model: String,
+    pub shader: String,
+    pub texture: Option<String>,
+    pub output: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    pub spp: usize,
+    pub sampler: String,
+    pub seed: u64,
+    // Pixel reconstruction filter of the path tracer.
+    pub filter: Filter,
+    pub filter_radius: Option<f32>,
+    // Target relative error of adaptive sampling, and when to stop before.
+    pub adaptive: Option<f32>,
+    pub max_spp: Option<usize>,
+    pub time_budget: Option<f32>,
+    pub sample_budget: Option<f32>,
+    pub heatmap: Option<String>,
+    pub eye: Option<Vector3>,
+    pub target: Option<Vector3>,
+    pub fov: Option<f32>,
+    // glTF camera chosen by index or name.
+    pub camera: Option<String>,
+    // Thin lens of the path tracer.
+    pub aperture: Option<f32>,
+    pub focus: Option<f32>,
+    pub focus_pixel: Option<(usize, usize)>,
+    pub bokeh: Bokeh,
+    pub light: Option<Vector3>,
+    pub light_intensity: Option<f32>,
+    pub multisample: Multisample,
+    pub shadow: bool,
+    pub tone_mapping: ToneMapping,
+}
+
+impl RenderOptions {
+    fn new(model: String) -> Self {
+        let shader = match extension(&model).as_deref() {
+            Some("gltf") | Some("glb") => "pbr",
+            _ => "phong-color",
+        };
+        Self {
+            model,
+            shader: shader.to_string(),
+            texture: None,
+            output: None,
+            width: 800,
+            height: 800,
+            spp: 8,
+            sampler: "sobol".to_string(),
+            seed: 0,
+            filter: Filter::default(),
+            filter_radius: None,
+            adaptive: None,
+            max_spp: None,
+            time_budget: None,
+            sample_budget: None,
+            heatmap: None,
+            eye: None,
+            target: None,
+            fov: None,
+            camera: None,
+            aperture: None,
+            focus: None,
+            focus_pixel: None,
+            bokeh: Bokeh::Circle,
+            light: None,
+            light_intensity: None,
+            multisample: Multisample::X1,
+            shadow: true,
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+
+    fn is_path_tracing(&self) -> bool {
+        self.shader == "pbr"
+    }
+
+    /// Apply the camera options on top of the scene's camera.
+    pub fn camera(&self, camera: Camera) -> Camera {
+        let camera = camera.aspect_ratio(self.width as f32 / self.height as f32);
+        let camera = match &self.eye {
+            Some(eye) => camera.eye_position(eye.clone()),
+            None => camera,
+        };
+        let camera = match &self.target {
+            Some(target) => {
+                let gaze = target - &camera.eye_position;
+                camera.gaze_direct(gaze.normalized())
+            }
+            None => camera,
+        };
+        match self.fov {
+            Some(fov) => camera.eye_fov(fov.to_radians()),
+            None => camera,
+        }
+    }
+
+    /// Thin lens of the path tracer, focused on the image center unless told otherwise.
+    pub fn lens(&self, ray_tracer: &RayTracer) -> Result<Option<Lens>, String> {
+        let aperture = match self.aperture {
+            Some(aperture) => aperture,
+            None => return Ok(None),
+        };
+        let focal_distance = match self.focus {
+            Some(focus) => focus,
+            None => {
+                let (x, y) = self
+                    .focus_pixel
+                    .unwrap_or((self.width / 2, self.height / 2));
+                ray_tracer.focus_distance(x, y).ok_or_else(|| {
+                    format!(
+                        "Nothing to focus on at pixel ({}, {}), set `--focus`.",
+                        x, y
+                    )
+                })?
+            }
+        };
+        Ok(Some(
+            Lens::default()
+                .aperture(aperture)
+                .focal_distance(focal_distance)
+                .bokeh(self.bokeh.clone()),
+        ))
+    }
+
+    /// The chosen pixel filter, over `--filter-radius` if set.
+    pub fn filter(&self) -> Filter {
+        match self.filter_radius {
+            Some(radius) => self.filter.clone().with_radius(radius),
+            None => self.filter.clone(),
+        }
+    }
+
+    /// Adaptive sampling from `--spp` samples per pixel, if `--adaptive` is set.
+    pub fn adaptive(&self) -> Option<AdaptiveSampling> {
+        let target_error = self.adaptive?;
+        let max_spp = self
+            .max_spp
+            .unwrap_or(AdaptiveSampling::default().max_spp.max(self.spp));
+        let adaptive = AdaptiveSampling::default()
+            .target_error(target_error)
+            .min_spp(self.spp)
+            .max_spp(max_spp);
+        let adaptive = match self.time_budget {
+            Some(seconds) => adaptive.time_budget(Duration::from_secs_f32(seconds)),
+            None => adaptive,
+        };
+        match self.sample_budget {
+            Some(spp) => Some(adaptive.sample_budget(spp)),
+            None => Some(adaptive),
+        }
+    }
+
+    pub fn light(&self, light: Light) -> Light {
+        let light = match &self.light {
+            Some(position) => light.position(position.clone()),
+            None => light,
+        };
+        match self.light_intensity {
+            Some(intensity) => light.intensity(intensity),
+            None => light,
+        }
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--no-shadow" => options.push((arg.as_str(), None)),
+            _ if arg.starts_with('-') => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for `{}`.", arg))?;
+                options.push((arg.as_str(), Some(value.as_str())));
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let command = positional
+        .first()
+        .ok_or_else(|| "Missing command.".to_string())?;
+    let operands = &positional[1..];
+    let model = || -> Result<String, String> {
+        let model = operands
+            .first()
+            .ok_or_else(|| format!("`{}` needs a model file.", command))?;
+        check_extension(model, "model", &["obj", "gltf", "glb", "json"])?;
+        Ok(model.clone())
+    };
+    let expect_operands = |count: usize| {
+        if operands.len() > count {
+            Err(format!("Unexpected argument `{}`.", operands[count]))
+        } else {
+            Ok(())
+        }
+    };
+
+    match command.as_str() {
+        "view" | "render" => {
+            expect_operands(1)?;
+            let mut render_options = RenderOptions::new(model()?);
+            for &(name, value) in options.iter() {
+                render_options.set(name, value)?;
+            }
+            render_options.validate()?;
+            if command == "view" {
+                if render_options.output.is_some() {
+                    return Err("`view` doesn't write files, use `render` instead.".to_string());
+                }
+                let adaptive = [
+                    ("--adaptive", render_options.adaptive.is_some()),
+                    ("--heatmap", render_options.heatmap.is_some()),
+                ];
+                if let Some((name, _)) = adaptive.iter().find(|(_, set)| *set) {
+                    return Err(format!(
+                        "`{}` needs `render` and `--output`, the window samples every pixel alike.",
+                        name
+                    ));
+                }
+                Ok(Command::View(render_options))
+            } else {
+                if render_options.output.is_none() {
+                    return Err(
+                        "`render` needs an output file, set it with `--output`.".to_string()
+                    );
+                }
+                Ok(Command::Render(render_options))
+            }
+        }
+        "info" | "convert" => {
+            if let Some((name, _)) = options.first() {
+                return Err(format!("`{}` doesn't take option `{}`.", command, name));
+            }
+            if command == "info" {
+                expect_operands(1)?;
+                return Ok(Command::Info { model: model()? });
+            }
+            expect_operands(2)?;
+            let input = model()?;
+            let output = operands
+                .get(1)
+                .ok_or_else(|| "`convert` needs an output file.".to_string())?;
+            check_extension(output, "output", &["obj"])?;
+            Ok(Command::Convert {
+                input,
+                output: output.clone(),
+            })
+        }
+        _ => Err(format!(
+            "Unknown command `{}`, expected `view`, `render`, `info` or `convert`.",
+            command
+        )),
+    }
+}
+
+impl RenderOptions {
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let value = value.unwrap_or_default();
+        match name {
+            "-s" | "--shader" => self.shader = value.to_lowercase(),
+            "-t" | "--texture" => {
+                check_extension(value, "texture", &["jpg", "jpeg", "png"])?;
+                self.texture = Some(value.to_string());
+            }
+            "-o" | "--output" => {
+                check_extension(value, "output", &["png", "jpg", "jpeg", "ppm"])?;
+                self.output = Some(value.to_string());
+            }
+            "--size" => {
+                let size = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|&(w, h)| w > 0 && h > 0);
+                (self.width, self.height) = size.ok_or_else(|| {
+                    format!("Invalid size `{}`, expected <width>x<height>.", value)
+                })?;
+            }
+            "--spp" => {
+                self.spp = parse_number(name, value)?;
+                if self.spp == 0 {
+                    return Err("`--spp` must be at least 1.".to_string());
+                }
+            }
+            "--sampler" => self.sampler = value.to_lowercase(),
+            "--seed" => self.seed = parse_number(name, value)?,
+            "--filter" => self.filter = value.parse()?,
+            "--filter-radius" => {
+                let radius: f32 = parse_number(name, value)?;
+                if !(radius.is_finite() && radius >= 0.5) {
+                    return Err(
+                        "`--filter-radius` must be a finite number of at least 0.5.".to_string()
+                    );
+                }
+                self.filter_radius = Some(radius);
+            }
+            "--adaptive" => {
+                let error: f32 = parse_number(name, value)?;
+                if error.is_nan() || error <= 0.0 {
+                    return Err("`--adaptive` must be a positive relative error.".to_string());
+                }
+                self.adaptive = Some(error);
+            }
+            "--max-spp" => self.max_spp = Some(parse_number(name, value)?),
+            "--time-budget" => {
+                let seconds: f32 = parse_number(name, value)?;
+                if seconds.is_nan() || seconds <= 0.0 {
+                    return Err("`--time-budget` must be positive.".to_string());
+                }
+                self.time_budget = Some(seconds);
+            }
+            "--sample-budget" => {
+                let spp: f32 = parse_number(name, value)?;
+                if spp.is_nan() || spp <= 0.0 {
+                    return Err("`--sample-budget` must be positive.".to_string());
+                }
+                self.sample_budget = Some(spp);
+            }
+            "--heatmap" => {
+                check_extension(value, "heatmap", &["png", "jpg", "jpeg", "ppm"])?;
+                self.heatmap = Some(value.to_string());
+            }
+            "--eye" => self.eye = Some(parse_vector(name, value)?),
+            "--target" => self.target = Some(parse_vector(name, value)?),
+            "--fov" => {
+                let fov: f32 = parse_number(name, value)?;
+                if !(fov > 0.0 && fov < 180.0) {
+                    return Err("`--fov` must be between 0 and 180 degrees.".to_string());
+                }
+                self.fov = Some(fov);
+            }
+            "--camera" => self.camera = Some(value.to_string()),
+            "--aperture" => {
+                let aperture: f32 = parse_number(name, value)?;
+                if !(aperture.is_finite() && aperture >= 0.0) {
+                    return Err("`--aperture` must be a finite, non-negative number.".to_string());
+                }
+                self.aperture = Some(aperture);
+            }
+            "--focus" => {
+                let focus: f32 = parse_number(name, value)?;
+                if !(focus.is_finite() && focus > 0.0) {
+                    return Err("`--focus` must be a finite, positive number.".to_string());
+                }
+                self.focus = Some(focus);
+            }
+            "--focus-pixel" => {
+                let pixel = value
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+                self.focus_pixel = Some(pixel.ok_or_else(|| {
+                    format!("Invalid value `{}` for `{}`, expected x,y.", value, name)
+                })?);
+            }
+            "--bokeh" => {
+                self.bokeh = match value {
+                    "circle" => Bokeh::Circle,
+                    _ => match value.parse::<usize>() {
+                        Ok(blades) if blades >= 3 => Bokeh::Polygon {
+                            blades,
+                            rotation: 0.0,
+                        },
+                        _ => {
+                            return Err(format!(
+                                "Invalid bokeh `{}`, expected circle or at least 3 blades.",
+                                value
+                            ))
+                        }
+                    },
+                }
+            }
+            "--light" => self.light = Some(parse_vector(name, value)?),
+            "--light-intensity" => {
+                let intensity: f32 = parse_number(name, value)?;
+                if !(intensity.is_finite() && intensity >= 0.0) {
+                    return Err(
+                        "`--light-intensity` must be a finite, non-negative number.".to_string()
+                    );
+                }
+                self.light_intensity = Some(intensity);
+            }
+            "--msaa" => {
+                self.multisample = Multisample::try_from(parse_number::<usize>(name, value)?)?
+            }
+            "--no-shadow" => self.shadow = false,
+            "--tone-mapping" => {
+                let operator = value.parse::<ToneMappingOperator>()?;
+                self.tone_mapping = ToneMapping::new(operator).exposure(self.tone_mapping.exposure);
+            }
+            "--exposure" => {
+                let exposure: f32 = parse_number(name, value)?;
+                if !(exposure.is_finite() && exposure > 0.0) {
+                    return Err("`--exposure` must be a finite, positive number.".to_string());
+                }
+                self.tone_mapping.exposure = exposure;
+            }
+            _ => return Err(format!("Unknown option `{}`.", name)),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.is_path_tracing() {
+            if extension(&self.model) == Some("obj".to_string()) {
+                return Err("The pbr shader needs a glTF model or a scene file.".to_string());
+            }
+            let rasterizer_only = [
+                ("--light", self.light.is_some()),
+                ("--light-intensity", self.light_intensity.is_some()),
+                ("--msaa", self.multisample != Multisample::X1),
+            ];
+            if let Some((name, _)) = rasterizer_only.iter().find(|(_, set)| *set) {
+                return Err(format!("`{}` isn't supported by the pbr shader.", name));
+            }
+            if !SAMPLER_NAMES.contains(&self.sampler.as_str()) {
+                return Err(format!(
+                    "Unknown sampler `{}`, expected one of {}.",
+                    self.sampler,
+                    SAMPLER_NAMES.join(", ")
+                ));
+            }
+        } else if self.aperture.is_some() {
+            return Err("`--aperture` needs the pbr shader.".to_string());
+        } else if self.adaptive.is_some() {
+            return Err("`--adaptive` needs the pbr shader.".to_string());
+        } else if !SHADER_NAMES.contains(&self.shader.as_str()) {
+            return Err(format!(
+                "Unknown shader `{}`, expected one of {} or pbr.",
+                self.shader,
+                SHADER_NAMES.join(", ")
+            ));
+        } else if self.shader.ends_with("texture") && self.texture.is_none() {
+            return Err(format!(
+                "The {} shader needs a texture, set it with `--texture`.",
+                self.shader
+            ));
+        }
+        let lens_only = [
+            ("--focus", self.focus.is_some()),
+            ("--focus-pixel", self.focus_pixel.is_some()),
+            ("--bokeh", self.bokeh != Bokeh::Circle),
+        ];
+        if let Some((name, _)) = lens_only.iter().find(|(_, set)| *set) {
+            if self.aperture.is_none() {
+                return Err(format!("`{}` needs `--aperture`.", name));
+            }
+        }
+        if self.focus.is_some() && self.focus_pixel.is_some() {
+            return Err("Set either `--focus` or `--focus-pixel`.".to_string());
+        }
+        let adaptive_only = [
+            ("--max-spp", self.max_spp.is_some()),
+            ("--time-budget", self.time_budget.is_some()),
+            ("--sample-budget", self.sample_budget.is_some()),
+            ("--heatmap", self.heatmap.is_some()),
+        ];
+        if let Some((name, _)) = adaptive_only.iter().find(|(_, set)| *set) {
+            if self.adaptive.is_none() {
+                return Err(format!("`{}` needs `--adaptive`.", name));
+            }
+        }
+        if self.max_spp.is_some_and(|max_spp| max_spp < self.spp) {
+            return Err("`--max-spp` can't be below `--spp`.".to_string());
+        }
+        if let Some((x, y)) = self.focus_pixel {
+            if x >= self.width || y >= self.height {
+                return Err(format!(
+                    "Focus pixel ({}, {}) is outside the {}x{} image.",
+                    x, y, self.width, self.height
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::View(options) | Command::Render(options) => render(options)?,
+        Command::Info { model } => {
+            let scene = load_scene(&model)?;
+            print!("{}", info(&model, &scene));
+        }
+        Command::Convert { input, output } => {
+            let models = load_scene(&input)?.models;
+            std::fs::write(&output, Model::to_obj(&models))
+                .map_err(|err| format!("Write `{}` failed: {}", output, err))?;
+            println!("Wrote {} models to `{}`.", models.len(), output);
+        }
+    }
+    Ok(())
+}
+
+fn render(options: RenderOptions) -> Result<(), String> {
+    if let Some(texture) = &options.texture {
+        if !Path::new(texture).is_file() {
+            return Err(format!("Cannot find texture file `{}`.", texture));
+        }
+    }
+    let mut scene = load_scene(&options.model)?;
+    if let Some(camera) = &options.camera {
+        scene.select_camera(camera)?;
+    }
+    let save_error = |err| {
+        format!(
+            "Write `{}` failed: {}",
+            options.output.as_ref().unwrap(),
+            err
+        )
+    };
+
+    if options.is_path_tracing() {
+        let mut ray_tracer =
+            RayTracer::from_scene(&scene, options.width, options.height, options.spp);
+        ray_tracer.camera = options.camera(ray_tracer.camera);
+        ray_tracer.camera.lens = options.lens(&ray_tracer)?;
+        ray_tracer.tone_mapping = options.tone_mapping.clone();
+        ray_tracer.sampler = make_sampler(&options.sampler, options.spp, options.seed)
+            .ok_or_else(|| format!("Unknown sampler `{}`.", options.sampler))?;
+        ray_tracer.filter = options.filter();
+        ray_tracer.adaptive = options.adaptive();
+        match &options.output {
+            Some(output) => {
+                ray_tracer.render();
+                ray_tracer.save(output).map_err(save_error)?;
+                if let Some(heatmap) = &options.heatmap {
+                    ray_tracer
+                        .sample_heatmap()
+                        .save(heatmap, &ToneMapping::default())
+                        .map_err(|err| format!("Write `{}` failed: {}", heatmap, err))?;
+                }
+            }
+            None => ray_tracer.run(),
+        }
+    } else {
+        let renderer = Renderer::default()
+            .size(options.width, options.height)
+            .scene(&scene);
+        let camera = options.camera(renderer.camera.clone().unwrap_or_default());
+        let light = options.light(renderer.light.clone().unwrap_or_default());
+        let mut renderer = renderer
+            .camera(camera)
+            .light(light)
+            .multisample(options.multisample)
+            .tone_mapping(options.tone_mapping.clone())
+            .shader(
+                &options.shader,
+                options.texture.as_deref().unwrap_or_default(),
+            )?;
+        if options.shadow && (options.shader.starts_with("phong") || options.shader == "pbr-raster")
+        {
+            renderer = renderer.shadow(ShadowMapSettings::default());
+        }
+        match &options.output {
+            Some(output) => renderer.save(output).map_err(save_error)?,
+            None => renderer.run(),
+        }
+    }
+    Ok(())
+}
+
+pub fn info(path: &str, scene: &Scene) -> String {
+    let models = &scene.models;
+    let mut info = format!("{}: {} models\n", path, models.len());
+    let (mut min, mut max) = (vector3([f32::MAX; 3]), vector3([f32::MIN; 3]));
+    for (i, model) in models.iter().enumerate() {
+        let material = match model.material.as_deref() {
+            Some(MaterialNew::Phong(_)) => "phong",
+            Some(MaterialNew::PBR(_)) => "pbr",
+            Some(MaterialNew::Emissive(_)) => "emissive",
+            None => "none",
+        };
+        info += &format!(
+            "  #{}: {} vertices, {} triangles, {} instances, material: {}\n",
+            i,
+            model.vertexs.len(),
+            model.indices.len(),
+            model.instances.len(),
+            material
+        );
+        for matrix in model.instances.iter() {
+            for v in model.instance_vertexs(matrix).iter() {
+                let p = &v.world_position;
+                min = vector3([min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())]);
+                max = vector3([max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())]);
+            }
+        }
+    }
+    let triangles: usize = models
+        .iter()
+        .map(|m| m.indices.len() * m.instances.len())
+        .sum();
+    info += &format!("Triangles: {}\n", triangles);
+    if triangles > 0 {
+        info += &format!(
+            "Bounds: ({}, {}, {}) - ({}, {}, {})\n",
+            min.x(),
+            min.y(),
+            min.z(),
+            max.x(),
+            max.y(),
+            max.z()
+        );
+        // Every instance placed in the world, as the path tracer sees the scene.
+        let triangles = models
+            .iter()
+            .flat_map(|model| {
+                model.instances.iter().flat_map(|matrix| {
+                    Model {
+                        vertexs: model.instance_vertexs(matrix),
+                        ..model.clone()
+                    }
+                    .triangles()
+                })
+            })
+            .collect::<Vec<_>>();
+        let tree = BVHTree::from_triangles(&triangles);
+        info += &format!(
+            "BVH: {} nodes, depth {}, SAH cost {:.2}\n",
+            tree.node_count(),
+            tree.depth(),
+            tree.sah_cost()
+        );
+    }
+    for (i, camera) in scene.cameras.iter().enumerate() {
+        let projection = match camera.projection {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+        };
+        info += &format!(
+            "Camera #{}: {}, {}\n",
+            i,
+            camera.name.as_deref().unwrap_or("unnamed"),
+            projection
+        );
+    }
+    info
+}
+
+fn load_scene(path: &str) -> Result<Scene, String> {
+    if extension(path).as_deref() == Some("json") {
+        return Scene::load(path);
+    }
+    Scene::from_model_file(path)
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+fn check_extension(path: &str, kind: &str, expected: &[&str]) -> Result<(), String> {
+    match extension(path) {
+        Some(e) if expected.contains(&e.as_str()) => Ok(()),
+        _ => Err(format!(
+            "Unsupported {} file `{}`, expected {}.",
+            kind,
+            path,
+            expected
+                .iter()
+                .map(|e| format!("`.{}`", e))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value `{}` for `{}`.", value, name))
+}
+
+fn parse_vector(name: &str, value: &str) -> Result<Vector3, String> {
+    let xyz = value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|v| v.len() == 3)
+        .ok_or_else(|| format!("Invalid value `{}` for `{}`, expected x,y,z.", value, name))?;
+    Ok(vector3([xyz[0], xyz[1], xyz[2]]))
+}
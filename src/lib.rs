@@ -2,9 +2,11 @@
 pub use pipeline::color::Color;
 
 pub mod algebra;
+pub mod cli;
 pub mod pipeline;
 pub mod ray_tracing;
 pub mod renderer;
+pub mod scene;
 pub mod window;
 
 #[cfg(test)]
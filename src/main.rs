@@ -1,26 +1,14 @@
-use std::env;
-use tiny_renderer::{
-    pipeline::model::Model, ray_tracing::path_tracing::RayTracer, renderer::Renderer,
-};
+use std::{env, process};
+use tiny_renderer::cli;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let shader_name = &args[1];
-    let path = &args[2];
-
-    if shader_name == "pbr" {
-        let model_path = format!("{}.gltf", path);
-        const DEFAULT_SPP: usize = 8;
-        let spp = args
-            .get(3)
-            .map_or(DEFAULT_SPP, |s| s.parse::<usize>().unwrap_or(DEFAULT_SPP));
-        RayTracer::render(&model_path, spp);
-    } else {
-        let model_path = format!("{}.obj", path);
-        // let shader = make_shader(shader_name, path);
-        Renderer::default()
-            .models(Model::from_obj(&model_path))
-            .shader(shader_name, path)
-            .run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if let Err(err) = cli::parse(&args).and_then(cli::run) {
+        eprintln!("error: {}\n\nRun `tiny-renderer --help` for usage.", err);
+        process::exit(1);
     }
 }
@@ -1,9 +1,13 @@
 pub mod camera;
 pub mod color;
 pub mod fragment_shader;
+pub mod framebuffer;
 pub mod light;
 pub mod model;
 pub mod rasterizer;
+pub mod scene_graph;
+pub mod shadow_map;
 pub mod texture;
 pub mod transformation;
+pub mod vertex_shader;
 pub mod material;
@@ -1,5 +1,98 @@
-use crate::algebra::vector_new::{vector3, Vector3};
+use crate::algebra::{
+    matrix_new::Matrix4,
+    vector_new::{vector3, Vector3, Vector4},
+};
 use std::f32::consts::PI;
+
+/// How the view volume is mapped to the image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Half height of the view volume, its width follows the aspect ratio.
+    Orthographic { ymag: f32 },
+}
+
+/// Shape of the aperture, out of focus highlights take it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bokeh {
+    Circle,
+    // Regular polygon inscribed in the aperture, `rotation` in radians.
+    Polygon { blades: usize, rotation: f32 },
+}
+
+/// Thin lens of the path tracer, the rasterizer keeps a pinhole camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lens {
+    // Radius of the aperture, in world units.
+    pub aperture: f32,
+    // Distance of the sharp plane along the gaze direction.
+    pub focal_distance: f32,
+    pub bokeh: Bokeh,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            aperture: 0.05,
+            focal_distance: 1.0,
+            bokeh: Bokeh::Circle,
+        }
+    }
+}
+
+impl Lens {
+    pub fn aperture(mut self, aperture: f32) -> Self {
+        self.aperture = aperture;
+        self
+    }
+    pub fn focal_distance(mut self, focal_distance: f32) -> Self {
+        self.focal_distance = focal_distance;
+        self
+    }
+    pub fn bokeh(mut self, bokeh: Bokeh) -> Self {
+        self.bokeh = bokeh;
+        self
+    }
+
+    /// Map a point of the unit square to the aperture, uniformly over its area.
+    pub fn sample(&self, (s, t): (f32, f32)) -> (f32, f32) {
+        let (x, y) = match self.bokeh {
+            Bokeh::Circle => concentric_disk(s, t),
+            Bokeh::Polygon { blades, rotation } => {
+                // Pick a triangle of the fan around the center, then a point inside it.
+                let blades = blades.max(3);
+                let k = ((s * blades as f32) as usize).min(blades - 1);
+                let s = s * blades as f32 - k as f32;
+                let corner = |i: usize| {
+                    let angle = rotation + 2.0 * PI * i as f32 / blades as f32;
+                    (angle.cos(), angle.sin())
+                };
+                let ((x0, y0), (x1, y1)) = (corner(k), corner(k + 1));
+                let a = s.sqrt();
+                (
+                    a * (1.0 - t) * x0 + a * t * x1,
+                    a * (1.0 - t) * y0 + a * t * y1,
+                )
+            }
+        };
+        (x * self.aperture, y * self.aperture)
+    }
+}
+
+// Shirley's concentric mapping keeps strata of the square compact on the disk.
+fn concentric_disk(s: f32, t: f32) -> (f32, f32) {
+    let (a, b) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     pub gaze_direct: Vector3,
@@ -9,6 +102,10 @@ pub struct Camera {
     pub aspect_ratio: f32,
     pub near: f32,
     pub far: f32,
+    pub projection: Projection,
+    pub name: Option<String>,
+    // A pinhole camera without one.
+    pub lens: Option<Lens>,
 }
 
 #[allow(dead_code)]
@@ -22,6 +119,9 @@ impl Camera {
             aspect_ratio: 0.0,
             near: 0.0,
             far: 0.0,
+            projection: Projection::Perspective,
+            name: None,
+            lens: None,
         }
     }
 
@@ -54,6 +154,69 @@ impl Camera {
         self.far = far;
         self
     }
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+    pub fn name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+    pub fn lens(mut self, lens: Option<Lens>) -> Self {
+        self.lens = lens;
+        self
+    }
+
+    /// Place the camera with a modeling matrix, it looks down its local -Z like in glTF.
+    pub fn transform(&self, matrix: &Matrix4) -> Self {
+        let eye = matrix * &Vector4::point_from(&self.eye_position);
+        let gaze = matrix * &Vector4::vector_from(&self.gaze_direct);
+        let up = matrix * &Vector4::vector_from(&self.up_direct);
+        Self {
+            eye_position: Vector3::from(&eye),
+            gaze_direct: Vector3::from(&gaze).normalized(),
+            up_direct: Vector3::from(&up).normalized(),
+            ..self.clone()
+        }
+    }
+
+    /// Orbit around the world Y axis through the origin.
+    pub fn yaw(&self, angle: f32) -> Self {
+        let p = Matrix4::rotate_around_y_matrix(angle) * Vector4::point_from(&self.eye_position);
+        let y_axis = vector3([0.0, 1.0, 0.0]);
+        Self {
+            eye_position: Vector3::from(&p),
+            gaze_direct: rotate_around_axis(&self.gaze_direct, &y_axis, angle),
+            up_direct: rotate_around_axis(&self.up_direct, &y_axis, angle),
+            ..self.clone()
+        }
+    }
+
+    /// Orbit around the camera's horizontal axis through the origin.
+    pub fn pitch(&self, angle: f32) -> Self {
+        let axis = self.up_direct.cross(&self.gaze_direct).normalized();
+        Self {
+            eye_position: rotate_around_axis(&self.eye_position, &axis, angle),
+            gaze_direct: rotate_around_axis(&self.gaze_direct, &axis, angle),
+            up_direct: rotate_around_axis(&self.up_direct, &axis, angle),
+            ..self.clone()
+        }
+    }
+
+    /// Move along the gaze.
+    pub fn zoom(&self, length: f32) -> Self {
+        let g = Vector4::vector_from(&self.gaze_direct);
+        let p: Vector4 = Vector4::point_from(&self.eye_position) + (g * length);
+        Self {
+            eye_position: Vector3::from(&p),
+            ..self.clone()
+        }
+    }
+}
+
+/// Rodrigues' rotation of `v` by `angle` around the unit vector `axis`.
+pub(crate) fn rotate_around_axis(v: &Vector3, axis: &Vector3, angle: f32) -> Vector3 {
+    v * angle.cos() + axis.cross(v) * angle.sin() + axis * axis.dot(v) * (1.0 - angle.cos())
 }
 
 impl Default for Camera {
@@ -71,6 +234,9 @@ impl Default for Camera {
             aspect_ratio: 1.0,
             near: 0.1,
             far: 50.0,
+            projection: Projection::Perspective,
+            name: None,
+            lens: None,
         }
     }
 }
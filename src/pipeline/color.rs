@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Mul};
 
-use crate::algebra::vector_new::{Vector3, Vector4};
+use crate::algebra::vector_new::{vector4, Vector3, Vector4};
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -23,6 +23,25 @@ impl Color {
     pub fn rgb(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b, a: 255 }
     }
+    // Decode the sRGB channels to linear RGBA in [0, 1].
+    pub fn to_linear(&self) -> Vector4 {
+        let decode = |c: u8| srgb_to_linear(c as f32 / 255.0);
+        vector4([
+            decode(self.r),
+            decode(self.g),
+            decode(self.b),
+            self.a as f32 / 255.0,
+        ])
+    }
+    // Channels in [0, 1] as stored, for data like normals or roughness.
+    pub fn to_unorm(&self) -> Vector4 {
+        vector4([
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+            self.a as f32 / 255.0,
+        ])
+    }
     pub fn add(&mut self, rhs: &Color) {
         self.r += rhs.r;
         self.g += rhs.g;
@@ -93,3 +112,19 @@ impl From<&Vector4> for Color {
         }
     }
 }
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
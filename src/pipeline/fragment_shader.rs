@@ -2,20 +2,26 @@ use super::{
     camera::Camera,
     light::Light,
     model::{Triangle, TriangulatedModel},
+    shadow_map::ShadowMap,
+    vertex_shader::Varyings,
 };
-use crate::{renderer::Renderer, Color};
+use crate::{algebra::vector_new::Vector4, renderer::Renderer};
+use std::sync::Arc;
 pub mod color_shader;
+pub mod pbr_shader;
 pub mod phong_shader;
 pub mod texture_shader;
 pub mod z_shader;
 
-pub trait FragmentShader {
+pub trait FragmentShader: Send + Sync {
+    // Linear RGBA.
     fn shade(
         &self,
         payload: &FragmentShaderPayload
-    ) -> Color;
+    ) -> Vector4;
     fn update_camera(&mut self, camera: &Camera);
     fn update_light(&mut self, light: &Light);
+    fn update_shadow_map(&mut self, shadow_map: Option<Arc<ShadowMap>>);
 }
 
 
@@ -25,23 +31,42 @@ pub struct FragmentShaderPayload<'a> {
     pub triangle: &'a Triangle,
     pub barycenter: (f32, f32, f32),
     pub z: f32,
+    pub varyings: Varyings,
 }
 
 
 pub use color_shader::ColorShader;
+pub use pbr_shader::PBRShader;
 pub use phong_shader::PhongShader;
 pub use texture_shader::TextureShader;
 pub use z_shader::ZShader;
 
-pub fn make_shader(name: &str, path: &str, renderer: &Renderer) -> Option<Box<dyn FragmentShader>> {
-    match name.to_lowercase().as_ref() {
-        "z" => Some(Box::new(ZShader {})),
-        "color" => Some(Box::new(ColorShader {})),
-        "texture" => Some(Box::new(TextureShader::new(path))),
-        "phong-color" => Some(Box::new(PhongShader::color_shader(renderer))),
-        "phong-texture" => Some(Box::new(PhongShader::texture_shader(renderer, path))),
-        _ => None,
-    }
+/// Names accepted by `make_shader`, `texture` and `phong-texture` need a texture path.
+pub const SHADER_NAMES: [&str; 6] = [
+    "z",
+    "color",
+    "texture",
+    "phong-color",
+    "phong-texture",
+    "pbr-raster",
+];
+
+/// `None` for an unknown name, an error if the texture can't be loaded.
+pub fn make_shader(
+    name: &str,
+    texture_path: &str,
+    renderer: &Renderer,
+) -> Result<Option<Box<dyn FragmentShader>>, String> {
+    let shader: Box<dyn FragmentShader> = match name.to_lowercase().as_ref() {
+        "z" => Box::new(ZShader {}),
+        "color" => Box::new(ColorShader {}),
+        "texture" => Box::new(TextureShader::new(texture_path)?),
+        "phong-color" => Box::new(PhongShader::color_shader(renderer)),
+        "phong-texture" => Box::new(PhongShader::texture_shader(renderer, texture_path)?),
+        "pbr-raster" => Box::new(PBRShader::new(renderer)),
+        _ => return Ok(None),
+    };
+    Ok(Some(shader))
 }
 
 #[macro_export]
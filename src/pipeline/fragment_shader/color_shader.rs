@@ -1,12 +1,12 @@
 use super::{FragmentShader, FragmentShaderPayload};
-use crate::algebra::vector_new::{vector3, Vector3};
+use crate::algebra::vector_new::{vector3, Vector3, Vector4};
 use crate::pipeline::material::MaterialNew;
-use crate::pipeline::{camera::Camera, light::Light};
-use crate::Color;
+use crate::pipeline::{camera::Camera, light::Light, shadow_map::ShadowMap};
+use std::sync::Arc;
 
 pub struct ColorShader;
 
-const DEFAULT_COLOR: Vector3 = vector3([127.0, 127.0, 127.0]);
+const DEFAULT_COLOR: Vector3 = vector3([0.5, 0.5, 0.5]);
 
 impl FragmentShader for ColorShader {
     fn shade(
@@ -14,15 +14,17 @@ impl FragmentShader for ColorShader {
         FragmentShaderPayload {
             model, ..
         }: &FragmentShaderPayload,
-    ) -> Color {
-        model
+    ) -> Vector4 {
+        let color = model
             .material
             .as_ref()
-            .map_or(Color::from(&DEFAULT_COLOR), |m| match m.as_ref() {
-                MaterialNew::Phong(m) => Color::from(&m.diffuse_color),
-                _ => Color::from(&DEFAULT_COLOR),
-            })
+            .map_or(&DEFAULT_COLOR, |m| match m.as_ref() {
+                MaterialNew::Phong(m) => &m.diffuse_color,
+                _ => &DEFAULT_COLOR,
+            });
+        Vector4::point_from(color)
     }
     fn update_camera(&mut self, _: &Camera) {}
     fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
 }
@@ -0,0 +1,102 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3, Vector4},
+    pipeline::{
+        camera::Camera,
+        light::Light,
+        material::{IlluminateType, MaterialNew, PBRMaterial},
+        shadow_map::ShadowMap,
+    },
+    renderer::Renderer,
+    *,
+};
+
+use super::{FragmentShader, FragmentShaderPayload};
+use std::sync::Arc;
+
+/// Cook-Torrance shading of the point light, with the glTF textures of PBR materials.
+pub struct PBRShader {
+    pub eye_position: Vector3,
+    pub light: Light,
+    pub fill_lights: Vec<Light>,
+    pub shadow_map: Option<Arc<ShadowMap>>,
+}
+
+impl PBRShader {
+    pub fn new(renderer: &Renderer) -> Self {
+        let e = &renderer.camera.as_ref().unwrap().eye_position;
+        let l = renderer.light.as_ref().unwrap();
+        Self {
+            eye_position: e.clone(),
+            light: l.clone(),
+            fill_lights: renderer.fill_lights.clone(),
+            shadow_map: None,
+        }
+    }
+}
+
+const AMBIENT_INTENSITY: Vector3 = vector3([0.2, 0.2, 0.2]);
+
+impl FragmentShader for PBRShader {
+    fn shade(
+        &self,
+        FragmentShaderPayload {
+            model,
+            triangle,
+            barycenter,
+            ..
+        }: &FragmentShaderPayload,
+    ) -> Vector4 {
+        let barycenter = *barycenter;
+        let position = Vector3::from(&interpolate_triangle!(triangle, world_position; barycenter));
+        let normal = Vector3::from(&interpolate!(triangle, normal; barycenter)).normalized();
+
+        // Phong materials are approximated, emissive ones only show their color.
+        let material = match model.material.as_deref() {
+            Some(MaterialNew::PBR(m)) => m.clone(),
+            Some(MaterialNew::Phong(m)) => PBRMaterial::from(m),
+            Some(MaterialNew::Emissive(m)) => {
+                return Vector4::point_from(&(&m.base_color * m.intensity))
+            }
+            None => PBRMaterial::new(Vector3::from(0.8), 0.0, 0.8, 1.5),
+        };
+        let uv = triangle.vertexs[0]
+            .texture_coordinate
+            .map(|_| interpolate_uv!(triangle, texture_coordinate; barycenter));
+        let (material, n, occlusion) = match uv {
+            Some(uv) => (
+                material.textured(uv),
+                material.shading_normal(uv, &normal, triangle.tangents().as_ref()),
+                material.occlusion(uv),
+            ),
+            None => (material, normal, 1.0),
+        };
+
+        let v = (&self.eye_position - &position).normalized();
+
+        // Only the key light is shadowed.
+        let visibility = self
+            .shadow_map
+            .as_ref()
+            .map_or(1.0, |shadow_map| shadow_map.visibility(&position));
+        let lights = std::iter::once((&self.light, visibility))
+            .chain(self.fill_lights.iter().map(|light| (light, 1.0)));
+
+        let mut color = material.albedo.cwise_product(&AMBIENT_INTENSITY) * occlusion;
+        for (light, visibility) in lights {
+            let (l, _, radiance) = light.incident(&position);
+            let fr = material.eval(&l, &v, &n, IlluminateType::Direct);
+            color += fr.cwise_product(&radiance) * (n.dot(&l).max(0.0) * visibility);
+        }
+
+        Vector4::point_from(&(color + material.emission))
+    }
+    fn update_camera(&mut self, camera: &Camera) {
+        self.eye_position = camera.eye_position.clone();
+    }
+    fn update_light(&mut self, light: &Light) {
+        self.light = light.clone();
+    }
+    fn update_shadow_map(&mut self, shadow_map: Option<Arc<ShadowMap>>) {
+        self.shadow_map = shadow_map;
+    }
+}
@@ -1,20 +1,25 @@
 use crate::{
-    algebra::vector_new::{vector3, Vector3},
+    algebra::vector_new::{vector3, Vector3, Vector4},
     pipeline::{
         camera::Camera,
         light::Light,
+        material::PhongMaterial,
+        shadow_map::ShadowMap,
         texture::Texture,
     },
     renderer::Renderer,
-    Color, *,
+    *,
 };
 
 use super::{FragmentShader, FragmentShaderPayload};
+use std::sync::Arc;
 
 pub struct PhongShader {
     pub eye_position: Vector3,
     pub light: Light,
+    pub fill_lights: Vec<Light>,
     pub texture: Option<Texture>,
+    pub shadow_map: Option<Arc<ShadowMap>>,
 }
 
 impl PhongShader {
@@ -24,23 +29,15 @@ impl PhongShader {
         Self {
             eye_position: e.clone(),
             light: l.clone(),
+            fill_lights: renderer.fill_lights.clone(),
             texture: None,
+            shadow_map: None,
         }
     }
-    pub fn texture_shader(renderer: &Renderer, path: &str) -> Self {
+    pub fn texture_shader(renderer: &Renderer, path: &str) -> Result<Self, String> {
         let mut shader = PhongShader::color_shader(renderer);
-        let jpg_texture = Texture::from_path(&format!("{}.jpg", path));
-        let png_texture = Texture::from_path(&format!("{}.png", path));
-        let texture = jpg_texture.or(png_texture);
-        shader.texture = match texture {
-            Ok(texture) => Some(texture),
-            Err(err) => {
-                println!("Load texture failed: {}", err);
-                println!("Use phong-color shader.");
-                None
-            }
-        };
-        shader
+        shader.texture = Some(Texture::from_path(path)?);
+        Ok(shader)
     }
 }
 
@@ -58,14 +55,23 @@ impl FragmentShader for PhongShader {
             barycenter,
             ..
         }: &FragmentShaderPayload,
-    ) -> Color {
-        let material = model.material.as_ref().and_then(|m| m.phong_material());
+    ) -> Vector4 {
+        // PBR materials are approximated.
+        let pbr = model
+            .material
+            .as_ref()
+            .and_then(|m| m.pbr_material())
+            .map(PhongMaterial::from);
+        let material = model
+            .material
+            .as_ref()
+            .and_then(|m| m.phong_material())
+            .or(pbr.as_ref());
         let barycenter = barycenter.clone();
 
         let kd = if let Some(texture) = &self.texture {
             let (u, v) = interpolate_uv!(triangle, texture_coordinate; barycenter);
-            let color = texture.get(u, v);
-            vector3([color.r as f32, color.g as f32, color.b as f32]) / 255.0
+            Vector3::from(&texture.get(u, v).to_linear())
         } else {
             material.map_or(DEFAULT_KD, |m| m.diffuse_color.clone())
         };
@@ -76,30 +82,28 @@ impl FragmentShader for PhongShader {
         let p = 150;
 
         let eye_positon = &self.eye_position;
-        let Light {
-            position: light_position,
-            intensity,
-        } = &self.light;
-
         let n = normal.normalized();
-        let l = (light_position - &position).normalized();
         let v = (eye_positon - &position).normalized();
-        let h = (&l + &v).normalized();
-
-        let r = (light_position - &position).norm();
 
-        let ambient = ka.cwise_product(&AMBIENT_INTENSITY);
-        let diffuse = kd * (*intensity / (r * r) * max(0.0, n.dot(&l)));
-        let specular = ks * (*intensity / (r * r) * max(0.0, n.dot(&h)).powi(p));
+        // Only the key light is shadowed.
+        let visibility = self
+            .shadow_map
+            .as_ref()
+            .map_or(1.0, |shadow_map| shadow_map.visibility(&position));
+        let lights = std::iter::once((&self.light, visibility))
+            .chain(self.fill_lights.iter().map(|light| (light, 1.0)));
 
-        let result_color = (ambient + diffuse + specular) * 255.0;
+        let mut color = ka.cwise_product(&AMBIENT_INTENSITY);
+        for (light, visibility) in lights {
+            let (l, _, radiance) = light.incident(&position);
+            let h = (&l + &v).normalized();
+            let radiance = radiance * visibility;
+            let diffuse = kd.cwise_product(&radiance) * max(0.0, n.dot(&l));
+            let specular = ks.cwise_product(&radiance) * max(0.0, n.dot(&h)).powi(p);
+            color = color + diffuse + specular;
+        }
 
-        let (r, g, b) = (
-            result_color.x() as u8,
-            result_color.y() as u8,
-            result_color.z() as u8,
-        );
-        Color::rgb(r, g, b)
+        Vector4::point_from(&color)
     }
     fn update_camera(&mut self, camera: &Camera) {
         self.eye_position = camera.eye_position.clone();
@@ -107,6 +111,9 @@ impl FragmentShader for PhongShader {
     fn update_light(&mut self, light: &Light) {
         self.light = light.clone();
     }
+    fn update_shadow_map(&mut self, shadow_map: Option<Arc<ShadowMap>>) {
+        self.shadow_map = shadow_map;
+    }
 }
 
 fn max(a: f32, b: f32) -> f32 {
@@ -1,28 +1,21 @@
 use super::{FragmentShader, FragmentShaderPayload};
 use crate::pipeline::texture::Texture;
 use crate::{
+    algebra::vector_new::Vector4,
     interpolate_uv,
-    pipeline::{camera::Camera, light::Light},
-    Color,
+    pipeline::{camera::Camera, light::Light, shadow_map::ShadowMap},
 };
+use std::sync::Arc;
 
 pub struct TextureShader {
     texture: Texture,
 }
 
 impl TextureShader {
-    pub fn new(path: &str) -> Self {
-        //Try jpg
-        if let Ok(texture) = Texture::from_path(&format!("{}.jpg", path)) {
-            TextureShader { texture }
-        } else {
-            //Try png
-            if let Ok(texture) = Texture::from_path(&format!("{}.png", path)) {
-                TextureShader { texture }
-            } else {
-                panic!("Cannot find texture file `{0}.jpg` or `{0}.png`.", path)
-            }
-        }
+    pub fn new(path: &str) -> Result<Self, String> {
+        Ok(TextureShader {
+            texture: Texture::from_path(path)?,
+        })
     }
 }
 
@@ -34,10 +27,11 @@ impl FragmentShader for TextureShader {
             barycenter,
             ..
         }: &FragmentShaderPayload,
-    ) -> Color {
+    ) -> Vector4 {
         let (u, v) = interpolate_uv!(triangle, texture_coordinate; barycenter);
-        self.texture.get(u, v)
+        self.texture.get(u, v).to_linear()
     }
     fn update_camera(&mut self, _: &Camera) {}
     fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
 }
@@ -1,19 +1,23 @@
 use super::{FragmentShader, FragmentShaderPayload};
 use crate::{
+    algebra::vector_new::{vector4, Vector4},
     pipeline::{
         camera::Camera,
         light::Light,
+        shadow_map::ShadowMap,
     },
-    Color,
 };
+use std::sync::Arc;
 
 pub struct ZShader;
 
 #[allow(unused_variables)]
 impl FragmentShader for ZShader {
-    fn shade(&self, FragmentShaderPayload { z, .. }: &FragmentShaderPayload) -> Color {
-        &Color::rgb(255, 255, 255) * (z * 5.0 - 4.0)
+    fn shade(&self, FragmentShaderPayload { z, .. }: &FragmentShaderPayload) -> Vector4 {
+        let v = z * 5.0 - 4.0;
+        vector4([v, v, v, 1.0])
     }
     fn update_camera(&mut self, _: &Camera) {}
     fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
 }
@@ -0,0 +1,139 @@
+use super::color::linear_to_srgb;
+use crate::algebra::vector_new::{vector3, vector4, Vector3, Vector4};
+use crate::Color;
+use image::{ImageResult, RgbImage};
+use std::{path::Path, str::FromStr};
+
+/// Linear floating-point RGBA image, rows from top to bottom.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<Vector4>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, clear_color: &Vector4) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![clear_color.clone(); width * height],
+        }
+    }
+
+    pub fn from_rgb(width: usize, height: usize, data: &[Vector3]) -> Self {
+        Self {
+            width,
+            height,
+            data: data
+                .iter()
+                .map(|c| vector4([c.x(), c.y(), c.z(), 1.0]))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Vector4 {
+        &self.data[y * self.width + x]
+    }
+
+    /// Tone map and sRGB-encode every pixel into 8-bit colors.
+    pub fn to_colors(&self, tone_mapping: &ToneMapping) -> Vec<Color> {
+        self.data
+            .iter()
+            .map(|c| {
+                let c = tone_mapping.map(&Vector3::from(c));
+                let encode = |v: f32| (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8;
+                Color::rgb(encode(c.x()), encode(c.y()), encode(c.z()))
+            })
+            .collect()
+    }
+
+    /// Pixels packed as `0RGB` for the window.
+    pub fn to_bitmap(&self, tone_mapping: &ToneMapping) -> Vec<u32> {
+        self.to_colors(tone_mapping)
+            .iter()
+            .map(|c| ((c.r as u32) << 16) | ((c.g as u32) << 8) | c.b as u32)
+            .collect()
+    }
+
+    pub fn to_image(&self, tone_mapping: &ToneMapping) -> RgbImage {
+        let pixels = self
+            .to_colors(tone_mapping)
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b])
+            .collect();
+        RgbImage::from_raw(self.width as u32, self.height as u32, pixels)
+            .expect("Framebuffer size doesn't match its data.")
+    }
+
+    /// Write the image to `path`, the format (PNG, JPEG, PPM...) follows the extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P, tone_mapping: &ToneMapping) -> ImageResult<()> {
+        self.to_image(tone_mapping).save(path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMappingOperator {
+    // Only scale by the exposure, values above 1.0 are clipped.
+    Exposure,
+    Reinhard,
+    AcesFilmic,
+}
+
+impl FromStr for ToneMappingOperator {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "exposure" => Ok(ToneMappingOperator::Exposure),
+            "reinhard" => Ok(ToneMappingOperator::Reinhard),
+            "aces" => Ok(ToneMappingOperator::AcesFilmic),
+            _ => Err(format!(
+                "Unknown tone mapping `{}`, expected `exposure`, `reinhard` or `aces`.",
+                s
+            )),
+        }
+    }
+}
+
+/// Maps linear HDR radiance to `[0, 1]` before display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMappingOperator,
+    pub exposure: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMappingOperator::Exposure,
+            exposure: 1.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMappingOperator) -> Self {
+        Self {
+            operator,
+            ..Self::default()
+        }
+    }
+
+    pub fn exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn map(&self, c: &Vector3) -> Vector3 {
+        let c = c * self.exposure;
+        let map_channel: fn(f32) -> f32 = match self.operator {
+            ToneMappingOperator::Exposure => |x| x,
+            ToneMappingOperator::Reinhard => |x| x / (1.0 + x),
+            // Krzysztof Narkowicz's fit of the ACES reference curve.
+            ToneMappingOperator::AcesFilmic => {
+                |x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }
+        };
+        vector3([map_channel(c.x()), map_channel(c.y()), map_channel(c.z())])
+    }
+}
@@ -1,9 +1,31 @@
-use crate::algebra::vector_new::{vector3, Vector3};
+use crate::algebra::{
+    matrix_new::Matrix4,
+    vector_new::{vector3, Vector3, Vector4},
+};
+
+/// Punctual light types of `KHR_lights_punctual`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LightKind {
+    Point,
+    // Cone angles in radians, measured from the light's direction.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+    Directional,
+}
 
 #[derive(Debug, Clone)]
 pub struct Light {
     pub position: Vector3,
+    // Luminous intensity of point and spot lights, illuminance of directional ones.
     pub intensity: f32,
+    pub color: Vector3,
+    pub kind: LightKind,
+    // Where spot and directional lights point to.
+    pub direction: Vector3,
+    // Point and spot lights fade out to zero at `range`.
+    pub range: Option<f32>,
 }
 
 impl Default for Light {
@@ -11,6 +33,10 @@ impl Default for Light {
         Self {
             position: vector3([10.0, 10.0, -10.0]),
             intensity: 300.0,
+            color: Vector3::from(1.0),
+            kind: LightKind::Point,
+            direction: vector3([0.0, 0.0, -1.0]),
+            range: None,
         }
     }
 }
@@ -20,4 +46,69 @@ impl Light {
         self.position = position;
         self
     }
+    pub fn intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+    pub fn color(mut self, color: Vector3) -> Self {
+        self.color = color;
+        self
+    }
+    pub fn kind(mut self, kind: LightKind) -> Self {
+        self.kind = kind;
+        self
+    }
+    pub fn direction(mut self, direction: Vector3) -> Self {
+        self.direction = direction.normalized();
+        self
+    }
+    pub fn range(mut self, range: Option<f32>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Place the light with a modeling matrix, it points down its local -Z like in glTF.
+    pub fn transform(&self, matrix: &Matrix4) -> Self {
+        let position = matrix * &Vector4::point_from(&self.position);
+        let direction = matrix * &Vector4::vector_from(&self.direction);
+        Self {
+            position: Vector3::from(&position),
+            direction: Vector3::from(&direction).normalized(),
+            ..self.clone()
+        }
+    }
+
+    /// Direction to the light, its distance and the radiance arriving at `position`.
+    pub fn incident(&self, position: &Vector3) -> (Vector3, f32, Vector3) {
+        if self.kind == LightKind::Directional {
+            return (
+                -&self.direction,
+                f32::INFINITY,
+                &self.color * self.intensity,
+            );
+        }
+        let to_light = &self.position - position;
+        let distance = to_light.norm();
+        let l = to_light / distance;
+
+        // Smooth window to zero at the range, as recommended by KHR_lights_punctual.
+        let window = self.range.map_or(1.0, |range| {
+            (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0).powi(2)
+        });
+        let cone = match self.kind {
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let (cos_outer, cos_inner) = (outer_cone_angle.cos(), inner_cone_angle.cos());
+                let cos_theta = self.direction.dot(&-&l);
+                let t =
+                    ((cos_theta - cos_outer) / (cos_inner - cos_outer).max(1e-4)).clamp(0.0, 1.0);
+                t * t
+            }
+            _ => 1.0,
+        };
+        let attenuation = self.intensity * window * cone / (distance * distance);
+        (l, distance, &self.color * attenuation)
+    }
 }
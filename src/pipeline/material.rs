@@ -1,7 +1,7 @@
 use crate::algebra::vector_new::{vector3, Vector3};
+use crate::pipeline::texture::Texture;
 use gltf::Material as GLTFMaterial;
-use rand::Rng;
-use std::f32::consts::PI;
+use std::{f32::consts::PI, sync::Arc};
 use tobj::Material as ObjMaterial;
 
 #[derive(Debug, Clone, Copy)]
@@ -23,9 +23,66 @@ pub struct PBRMaterial {
     pub metalness: f32,
     pub roughness: f32,
     pub refraction: f32,
+    // Fraction of light going through the dielectric part, the rest is reflected or diffused.
+    pub transmission: f32,
+    // Radiance emitted by the surface itself.
+    pub emission: Vector3,
+    pub textures: PBRTextures,
     f0: Vector3,
 }
 
+/// A direction sampled by `PBRMaterial::sample`.
+#[derive(Debug, Clone)]
+pub struct BSDFSample {
+    pub wi: Vector3,
+    // f * |cos| / pdf, what the radiance from `wi` is multiplied by.
+    pub weight: Vector3,
+    pub pdf: f32,
+    // Smooth reflection or refraction, `eval` and `pdf` are zero for it.
+    pub delta: bool,
+}
+
+/// glTF metallic-roughness textures, each one scales the matching factor of the material.
+#[derive(Debug, Clone)]
+pub struct PBRTextures {
+    // sRGB.
+    pub base_color: Option<Arc<Texture>>,
+    // Roughness in the green channel, metalness in the blue channel.
+    pub metallic_roughness: Option<Arc<Texture>>,
+    // Tangent space normals.
+    pub normal: Option<Arc<Texture>>,
+    pub normal_scale: f32,
+    // Ambient occlusion in the red channel, only the rasterizer uses it.
+    pub occlusion: Option<Arc<Texture>>,
+    pub occlusion_strength: f32,
+    // sRGB.
+    pub emissive: Option<Arc<Texture>>,
+}
+
+impl PBRTextures {
+    pub fn is_empty(&self) -> bool {
+        self.base_color.is_none()
+            && self.metallic_roughness.is_none()
+            && self.normal.is_none()
+            && self.occlusion.is_none()
+            && self.emissive.is_none()
+    }
+}
+
+impl Default for PBRTextures {
+    fn default() -> Self {
+        Self {
+            base_color: None,
+            metallic_roughness: None,
+            normal: None,
+            normal_scale: 1.0,
+            occlusion: None,
+            occlusion_strength: 1.0,
+            emissive: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EmissiveMaterial {
     pub base_color: Vector3,
@@ -97,16 +154,149 @@ impl<'a> From<&GLTFMaterial<'a>> for PBRMaterial {
         let pbr = material.pbr_metallic_roughness();
         let albedo = vector3(pbr.base_color_factor()[..3].try_into().unwrap());
         let metalness = pbr.metallic_factor();
-        let roughness = pbr.roughness_factor().clamp(0.01, 1.0);
+        let roughness = pbr.roughness_factor();
         let refraction = material.ior().unwrap_or(DEFAULT_REFRACTION);
+        let transmission = material
+            .transmission()
+            .map_or(0.0, |t| t.transmission_factor());
+        Self::new(albedo, metalness, roughness, refraction)
+            .emission(vector3(material.emissive_factor()))
+            .transmission(transmission)
+    }
+}
+
+impl PBRMaterial {
+    /// Factors and textures of a glTF material, `textures` are indexed like the glTF images.
+    pub fn from_gltf(material: &GLTFMaterial, textures: &[Arc<Texture>]) -> Self {
+        let image = |texture: gltf::Texture| textures.get(texture.source().index()).cloned();
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        Self::from(material).textures(PBRTextures {
+            base_color: pbr.base_color_texture().and_then(|t| image(t.texture())),
+            metallic_roughness: pbr
+                .metallic_roughness_texture()
+                .and_then(|t| image(t.texture())),
+            normal_scale: normal.as_ref().map_or(1.0, |t| t.scale()),
+            normal: normal.and_then(|t| image(t.texture())),
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |t| t.strength()),
+            occlusion: occlusion.and_then(|t| image(t.texture())),
+            emissive: material.emissive_texture().and_then(|t| image(t.texture())),
+        })
+    }
+}
+
+// Approximations so that every model can be drawn by both pipelines.
+impl From<&PhongMaterial> for PBRMaterial {
+    fn from(material: &PhongMaterial) -> Self {
+        Self::new(material.diffuse_color.clone(), 0.0, 0.8, 1.5)
+    }
+}
+
+impl From<&PBRMaterial> for PhongMaterial {
+    fn from(material: &PBRMaterial) -> Self {
+        Self {
+            ambient_color: material.albedo.clone(),
+            diffuse_color: &material.albedo * (1.0 - material.metalness),
+            specular_color: material.f0.clone(),
+        }
+    }
+}
+
+impl PBRMaterial {
+    pub fn new(albedo: Vector3, metalness: f32, roughness: f32, refraction: f32) -> Self {
         Self {
             f0: pbr_material_f0(refraction, &albedo, metalness),
             metalness,
-            roughness,
+            roughness: roughness.clamp(0.01, 1.0),
             refraction,
+            transmission: 0.0,
+            emission: Vector3::new(),
+            textures: PBRTextures::default(),
             albedo,
         }
     }
+
+    pub fn emission(mut self, emission: Vector3) -> Self {
+        self.emission = emission;
+        self
+    }
+
+    pub fn textures(mut self, textures: PBRTextures) -> Self {
+        self.textures = textures;
+        self
+    }
+
+    pub fn transmission(mut self, transmission: f32) -> Self {
+        self.transmission = transmission.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The material at `uv` with its textures applied to the factors.
+    pub fn textured(&self, (u, v): (f32, f32)) -> Self {
+        let textures = &self.textures;
+        let albedo = textures
+            .base_color
+            .as_ref()
+            .map_or(self.albedo.clone(), |t| {
+                self.albedo
+                    .cwise_product(&Vector3::from(&t.get_repeat(u, v).to_linear()))
+            });
+        let (metalness, roughness) =
+            textures
+                .metallic_roughness
+                .as_ref()
+                .map_or((self.metalness, self.roughness), |t| {
+                    let c = t.get_repeat(u, v).to_unorm();
+                    (self.metalness * c.z(), self.roughness * c.y())
+                });
+        let emission = textures
+            .emissive
+            .as_ref()
+            .map_or(self.emission.clone(), |t| {
+                self.emission
+                    .cwise_product(&Vector3::from(&t.get_repeat(u, v).to_linear()))
+            });
+        Self::new(albedo, metalness, roughness, self.refraction)
+            .emission(emission)
+            .transmission(self.transmission)
+    }
+
+    /// Ambient occlusion at `uv`, 1.0 means unoccluded.
+    pub fn occlusion(&self, (u, v): (f32, f32)) -> f32 {
+        self.textures.occlusion.as_ref().map_or(1.0, |t| {
+            let occlusion = t.get_repeat(u, v).to_unorm().x();
+            1.0 + self.textures.occlusion_strength * (occlusion - 1.0)
+        })
+    }
+
+    /// Perturb the normal `n` by the normal texture, `tangents` are dP/du and dP/dv.
+    pub fn shading_normal(
+        &self,
+        (u, v): (f32, f32),
+        n: &Vector3,
+        tangents: Option<&(Vector3, Vector3)>,
+    ) -> Vector3 {
+        let (texture, (dpdu, dpdv)) = match (&self.textures.normal, tangents) {
+            (Some(texture), Some(tangents)) => (texture, tangents),
+            _ => return n.clone(),
+        };
+        let c = texture.get_repeat(u, v).to_unorm();
+        let scale = self.textures.normal_scale;
+        let x = (c.x() * 2.0 - 1.0) * scale;
+        let y = (c.y() * 2.0 - 1.0) * scale;
+        let z = c.z() * 2.0 - 1.0;
+
+        // Gram-Schmidt, keeping the handedness of the uv mapping.
+        let t = dpdu - &(n * n.dot(dpdu));
+        if t.norm() < f32::EPSILON {
+            return n.clone();
+        }
+        let t = t.normalized();
+        let b = n.cross(&t);
+        let b = if b.dot(dpdv) < 0.0 { -b } else { b };
+        (t * x + b * y + n * z).normalized()
+    }
 }
 
 impl PBRMaterial {
@@ -144,12 +334,72 @@ impl PBRMaterial {
 }
 
 impl PBRMaterial {
+    /// BSDF of light from `wi` leaving to `wo`, both point away from the surface.
     pub fn eval(
         &self,
         wi: &Vector3,
         wo: &Vector3,
         n: &Vector3,
         illum_type: IlluminateType,
+    ) -> Vector3 {
+        let t = self.dielectric_weight();
+        let mut f = Vector3::new();
+        if t < 1.0 {
+            f = self.opaque_eval(wi, wo, n, illum_type) * (1.0 - t);
+        }
+        if t > 0.0 && !self.is_smooth() {
+            f += self.dielectric_eval(wi, wo, n) * t;
+        }
+        f
+    }
+
+    /// Density of `sample` returning `wi`, zero for the smooth directions.
+    pub fn pdf(&self, wi: &Vector3, wo: &Vector3, n: &Vector3) -> f32 {
+        let t = self.dielectric_weight();
+        let mut pdf = 0.0;
+        if t < 1.0 {
+            pdf += self.opaque_pdf(wi, wo, n) * (1.0 - t);
+        }
+        if t > 0.0 && !self.is_smooth() {
+            pdf += self.dielectric_pdf(wi, wo, n) * t;
+        }
+        pdf
+    }
+
+    /// Pick the direction light arrives from, `wo` points away from the surface. `u` chooses
+    /// the lobe and `u2` the direction in it, both in [0, 1).
+    pub fn sample(&self, wo: &Vector3, n: &Vector3, u: f32, u2: (f32, f32)) -> Option<BSDFSample> {
+        let t = self.dielectric_weight();
+        // `u` is stretched back to [0, 1) after each choice, for the next one.
+        let wi = if u < t {
+            let u = u / t;
+            if self.is_smooth() {
+                return self.sample_smooth_dielectric(wo, n, u);
+            }
+            self.sample_dielectric(wo, n, u, u2)?
+        } else {
+            self.sample_opaque(wo, n, (u - t) / (1.0 - t), u2)
+        };
+        let pdf = self.pdf(&wi, wo, n);
+        if pdf <= 0.0 {
+            return None;
+        }
+        // Evaluated like direct light, the path tracer weighs both against each other.
+        let f = self.eval(&wi, wo, n, IlluminateType::Direct);
+        Some(BSDFSample {
+            weight: f * (wi.dot(n).abs() / pdf),
+            wi,
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn opaque_eval(
+        &self,
+        wi: &Vector3,
+        wo: &Vector3,
+        n: &Vector3,
+        illum_type: IlluminateType,
     ) -> Vector3 {
         let check_ray_dir = n.dot(wi) * n.dot(wo);
         if check_ray_dir <= 0.0 {
@@ -169,7 +419,7 @@ impl PBRMaterial {
         // &self.albedo / PI
     }
 
-    pub fn pdf(&self, wi: &Vector3, wo: &Vector3, n: &Vector3) -> f32 {
+    fn opaque_pdf(&self, wi: &Vector3, wo: &Vector3, n: &Vector3) -> f32 {
         if n.dot(wi) * n.dot(wo) <= 0.0 {
             return 0.0;
         }
@@ -183,30 +433,37 @@ impl PBRMaterial {
         } else {
             1.0 / (4.0 * h_dot_wo.abs())
         };
-        ph * po
+        let diffuse = wi.dot(n).abs() / PI;
+        let p_specular = self.specular_probability();
+        ph * po * p_specular + diffuse * (1.0 - p_specular)
     }
 
-    pub fn sample(&self, wi: &Vector3, n: &Vector3) -> Vector3 {
-        // self._diffse_sample(n)
-        self.importance_sample_ggx(wi, n)
+    // Metals only reflect off the microfacets, other surfaces mostly scatter diffusely.
+    fn specular_probability(&self) -> f32 {
+        0.5 + 0.5 * self.metalness
     }
 
-    fn _diffse_sample(&self, n: &Vector3) -> Vector3 {
-        let mut rng = rand::thread_rng();
-        let x1 = rng.gen_range(0.0f32..1.0);
-        let x2 = rng.gen_range(0.0f32..1.0);
-        let z = (1.0 - 2.0 * x1).abs();
-        let r = (1.0 - z * z).sqrt();
+    fn sample_opaque(&self, wi: &Vector3, n: &Vector3, u: f32, u2: (f32, f32)) -> Vector3 {
+        if u < self.specular_probability() {
+            self.importance_sample_ggx(wi, n, u2)
+        } else {
+            self.diffuse_sample(wi, n, u2)
+        }
+    }
+
+    // Cosine-weighted direction on the side of `wi`.
+    fn diffuse_sample(&self, wi: &Vector3, n: &Vector3, (x1, x2): (f32, f32)) -> Vector3 {
+        let r = x1.sqrt();
         let phi = 2.0 * PI * x2;
+        let z = (1.0 - x1).sqrt();
         let locay_ray = vector3([r * phi.cos(), r * phi.sin(), z]);
-        Self::to_world(&locay_ray, n)
+        let n = if wi.dot(n) < 0.0 { -n } else { n.clone() };
+        Self::to_world(&locay_ray, &n)
     }
 
-    fn importance_sample_ggx(&self, wi: &Vector3, n: &Vector3) -> Vector3 {
-        let mut rng = rand::thread_rng();
-        let x1 = rng.gen_range(0.0f32..1.0);
-        let x2 = rng.gen_range(0.0f32..1.0);
-        let a2 = self.roughness.powi(4);
+    fn importance_sample_ggx(&self, wi: &Vector3, n: &Vector3, (x1, x2): (f32, f32)) -> Vector3 {
+        // Same distribution as `ggx`, so `opaque_pdf` is the density of these samples.
+        let a2 = self.roughness.powi(2);
         let phi = 2.0 * PI * x1;
         let cos_theta = ((1.0 - x2) / (1.0 + (a2 - 1.0) * x2)).sqrt();
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
@@ -234,6 +491,190 @@ impl PBRMaterial {
     }
 }
 
+// Dielectric lobe, rough transmission after Walter et al. 2007 with GGX microfacets.
+impl PBRMaterial {
+    fn dielectric_weight(&self) -> f32 {
+        self.transmission * (1.0 - self.metalness)
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.roughness <= SMOOTH_ROUGHNESS
+    }
+
+    fn alpha(&self) -> f32 {
+        self.roughness * self.roughness
+    }
+
+    // The normal on the side of `wo` and the relative IOR of the other side, rays can leave from inside.
+    fn dielectric_side(&self, wo: &Vector3, n: &Vector3) -> Option<(Vector3, f32)> {
+        let cos_o = n.dot(wo);
+        if cos_o > 0.0 {
+            Some((n.clone(), self.refraction))
+        } else if cos_o < 0.0 {
+            Some((-n, 1.0 / self.refraction))
+        } else {
+            None
+        }
+    }
+
+    // Microfacet normal of a reflection or a refraction, with the cosines to it.
+    fn dielectric_half_vector(
+        wi: &Vector3,
+        wo: &Vector3,
+        n: &Vector3,
+        eta: f32,
+    ) -> Option<(Vector3, f32, f32)> {
+        let reflect = n.dot(wi) > 0.0;
+        let h = if reflect { wi + wo } else { wo + &(wi * eta) };
+        if h.norm() == 0.0 {
+            return None;
+        }
+        let h = h.normalized();
+        let h = if h.dot(n) < 0.0 { -h } else { h };
+        let (wo_h, wi_h) = (wo.dot(&h), wi.dot(&h));
+        // Both directions must see the front of the microfacet, or its back when refracted.
+        let valid = wo_h > 0.0 && if reflect { wi_h > 0.0 } else { wi_h < 0.0 };
+        valid.then_some((h, wo_h, wi_h))
+    }
+
+    fn dielectric_eval(&self, wi: &Vector3, wo: &Vector3, n: &Vector3) -> Vector3 {
+        let (n, eta) = match self.dielectric_side(wo, n) {
+            Some(side) => side,
+            None => return Vector3::new(),
+        };
+        let (cos_i, cos_o) = (n.dot(wi), n.dot(wo));
+        let (h, wo_h, wi_h) = match Self::dielectric_half_vector(wi, wo, &n, eta) {
+            Some(half_vector) if cos_i != 0.0 => half_vector,
+            _ => return Vector3::new(),
+        };
+        let alpha = self.alpha();
+        let d = ggx(alpha, n.dot(&h));
+        let g = smith_g1(alpha, cos_i) * smith_g1(alpha, cos_o);
+        let f = fresnel_dielectric(wo_h, eta);
+        if cos_i > 0.0 {
+            Vector3::from(f * d * g / (4.0 * cos_i * cos_o))
+        } else {
+            // Radiance is scaled by 1 / eta², which cancels the eta² of the Jacobian.
+            let denom = wo_h + eta * wi_h;
+            &self.albedo
+                * ((1.0 - f) * d * g * (wi_h * wo_h / (cos_i * cos_o)).abs() / denom.powi(2))
+        }
+    }
+
+    fn dielectric_pdf(&self, wi: &Vector3, wo: &Vector3, n: &Vector3) -> f32 {
+        let (n, eta) = match self.dielectric_side(wo, n) {
+            Some(side) => side,
+            None => return 0.0,
+        };
+        let (h, wo_h, wi_h) = match Self::dielectric_half_vector(wi, wo, &n, eta) {
+            Some(half_vector) => half_vector,
+            None => return 0.0,
+        };
+        let cos_h = n.dot(&h);
+        let pdf_h = ggx(self.alpha(), cos_h) * cos_h;
+        let f = fresnel_dielectric(wo_h, eta);
+        if n.dot(wi) > 0.0 {
+            f * pdf_h / (4.0 * wo_h)
+        } else {
+            let denom = wo_h + eta * wi_h;
+            (1.0 - f) * pdf_h * eta * eta * wi_h.abs() / denom.powi(2)
+        }
+    }
+
+    fn sample_dielectric(
+        &self,
+        wo: &Vector3,
+        n: &Vector3,
+        u: f32,
+        (x1, x2): (f32, f32),
+    ) -> Option<Vector3> {
+        let (n, eta) = self.dielectric_side(wo, n)?;
+        let a2 = self.alpha().powi(2);
+        let phi = 2.0 * PI * x1;
+        let cos_theta = ((1.0 - x2) / (1.0 + (a2 - 1.0) * x2)).sqrt();
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+        let h = Self::to_world(
+            &vector3([phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta]),
+            &n,
+        );
+
+        let wo_h = wo.dot(&h);
+        if wo_h <= 0.0 {
+            return None;
+        }
+        if u < fresnel_dielectric(wo_h, eta) {
+            let wi = (&(&h * (2.0 * wo_h)) - wo).normalized();
+            (wi.dot(&n) > 0.0).then_some(wi)
+        } else {
+            refract(wo, &h, eta).filter(|wi| wi.dot(&n) < 0.0)
+        }
+    }
+
+    fn sample_smooth_dielectric(&self, wo: &Vector3, n: &Vector3, u: f32) -> Option<BSDFSample> {
+        let (n, eta) = self.dielectric_side(wo, n)?;
+        let cos_o = n.dot(wo);
+        let f = fresnel_dielectric(cos_o, eta);
+        let sample = if u < f {
+            BSDFSample {
+                wi: (&(&n * (2.0 * cos_o)) - wo).normalized(),
+                weight: Vector3::from(1.0),
+                pdf: f,
+                delta: true,
+            }
+        } else {
+            BSDFSample {
+                wi: refract(wo, &n, eta)?,
+                weight: &self.albedo / (eta * eta),
+                pdf: 1.0 - f,
+                delta: true,
+            }
+        };
+        Some(sample)
+    }
+}
+
+// Below this roughness the dielectric is a perfect mirror and window.
+const SMOOTH_ROUGHNESS: f32 = 0.05;
+
+fn ggx(alpha: f32, cos_h: f32) -> f32 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let denom = cos_h * cos_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * denom * denom)
+}
+
+fn smith_g1(alpha: f32, cos: f32) -> f32 {
+    let cos2 = (cos * cos).min(1.0);
+    let tan2 = (1.0 - cos2) / cos2;
+    2.0 / (1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+/// Unpolarized Fresnel reflectance, `eta` is the IOR behind the surface over the one in front.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Refract `wo` through a surface with normal `n` on its side, None on total internal reflection.
+pub fn refract(wo: &Vector3, n: &Vector3, eta: f32) -> Option<Vector3> {
+    let cos_i = wo.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((-wo / eta + n * (cos_i / eta - cos_t)).normalized())
+}
+
 fn mix(v0: &Vector3, v1: &Vector3, alpha: f32) -> Vector3 {
     v0 * (1.0 - alpha) + v1 * alpha
 }
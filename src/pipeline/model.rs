@@ -1,9 +1,11 @@
+use crate::algebra::matrix_new::Matrix4;
 use crate::algebra::vector_new::{vector3, vector4, Vector3, Vector4};
 use crate::pipeline::material::{MaterialNew, OptionEmissiveMaterial, PBRMaterial, PhongMaterial};
+use crate::pipeline::scene_graph::SceneGraph;
+use crate::pipeline::texture::Texture;
+use crate::pipeline::vertex_shader::Varyings;
 use crate::{interpolate, interpolate_triangle};
-use rand::prelude::SliceRandom;
-use rand::Rng;
-use std::sync::Arc;
+use std::{fmt::Write, path::Path, sync::Arc};
 use tobj;
 
 use crate::ray_tracing::path_tracing::HitResult;
@@ -15,6 +17,7 @@ pub struct Vertex {
     pub normal: Option<Vector4>,
     pub texture_coordinate: Option<(f32, f32)>,
     pub w_reciprocal: Option<f32>,
+    pub varyings: Varyings,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +25,8 @@ pub struct Model {
     pub indices: Vec<[u32; 3]>,
     pub vertexs: Vec<Vertex>,
     pub material: Option<Arc<MaterialNew>>,
+    // Modeling matrix of every instance, the mesh is drawn once per matrix.
+    pub instances: Vec<Matrix4>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,16 +46,25 @@ impl TriangulatedModel {
     pub fn has_emit(&self) -> bool {
         self.emit().is_some()
     }
-    pub fn sample(&self) -> (HitResult, f32) {
-        let pdf = 1.0 / self.area();
-        let mut rng = rand::thread_rng();
+    /// A point spread uniformly over the surface, with its pdf per unit area. `u` picks the
+    /// triangle by area and `u2` the point on it.
+    pub fn sample(&self, u: f32, u2: (f32, f32)) -> (HitResult, f32) {
+        let area = self.area();
+        let pdf = 1.0 / area;
+        let target = u * area;
+        let mut sum = 0.0;
         let chosen_triangle = self
             .triangles
-            .choose(&mut rng)
+            .iter()
+            .find(|triangle| {
+                sum += triangle.area;
+                target < sum
+            })
+            .or(self.triangles.last())
             .expect("model has no triangle.");
         let material = chosen_triangle.material.clone();
 
-        let (position, normal) = chosen_triangle.sample_position();
+        let (position, normal) = chosen_triangle.sample_position(u2);
 
         let hit_result = HitResult {
             position,
@@ -67,6 +81,8 @@ impl TriangulatedModel {
                     }
                 }),
             material,
+            texture_coordinate: None,
+            tangents: None,
         };
 
         (hit_result, pdf)
@@ -88,13 +104,8 @@ impl Triangle {
     pub fn get_barycenter(&self) -> Vector4 {
         const C: f32 = 1.0f32 / 3.0f32;
         let mut r = Vector4::new();
-        let iter = {
-            let i = self.vertexs.iter();
-            let j = self.vertexs.iter().cycle().skip(1);
-            i.zip(j)
-        };
-        for (i, j) in iter {
-            r = r + (&i.position - &j.position) * C;
+        for v in self.vertexs.iter() {
+            r = r + &v.position * C;
         }
         r
     }
@@ -108,12 +119,29 @@ impl Triangle {
         self.area = ab.cross(&ac).norm() / 2.0;
     }
 
-    pub fn sample_position(&self) -> (Vector3, Vector3) {
-        let x = rand::thread_rng().gen_range(0.0f32..=1.0).sqrt();
-        let y = rand::thread_rng().gen_range(0.0f32..=1.0);
+    /// Derivatives dP/du and dP/dv of the world position, `None` without texture coordinates.
+    pub fn tangents(&self) -> Option<(Vector3, Vector3)> {
+        let uv = |i: usize| self.vertexs[i].texture_coordinate;
+        let ((u0, v0), (u1, v1), (u2, v2)) = (uv(0)?, uv(1)?, uv(2)?);
+        let (du1, dv1, du2, dv2) = (u1 - u0, v1 - v0, u2 - u0, v2 - v0);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let p = |i: usize| Vector3::from(&self.vertexs[i].world_position);
+        let e1 = &p(1) - &p(0);
+        let e2 = &p(2) - &p(0);
+        let dpdu = (&e1 * dv2 - &e2 * dv1) / det;
+        let dpdv = (&e2 * du1 - &e1 * du2) / det;
+        Some((dpdu, dpdv))
+    }
+
+    /// A point spread uniformly over the triangle from two numbers in [0, 1), with its normal.
+    pub fn sample_position(&self, (u, v): (f32, f32)) -> (Vector3, Vector3) {
+        let x = u.sqrt();
         let a = 1.0 - x;
-        let b = x * (1.0 - y);
-        let c = x * y;
+        let b = x * (1.0 - v);
+        let c = x * v;
 
         let position = Vector3::from(&interpolate_triangle!(self, position; (a,b,c)));
         let normal = Vector3::from(&interpolate!(self, normal; (a, b, c)).normalized());
@@ -128,19 +156,42 @@ impl Model {
             indices: Vec::new(),
             vertexs: Vec::new(),
             material: None,
+            instances: vec![Matrix4::identity()],
+        }
+    }
+
+    /// Load an `.obj`, `.gltf` or `.glb` file, choosing the loader by extension.
+    pub fn load(path: &str) -> Result<Vec<Self>, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        if !Path::new(path).is_file() {
+            return Err(format!("Cannot find model file `{}`.", path));
+        }
+        match extension.as_deref() {
+            Some("obj") => Self::load_obj(path),
+            Some("gltf") | Some("glb") => Self::load_gltf(path),
+            _ => Err(format!(
+                "Unsupported model `{}`, expected a `.obj`, `.gltf` or `.glb` file.",
+                path
+            )),
         }
     }
 
     pub fn from_obj(path: &str) -> Vec<Self> {
-        let obj = tobj::load_obj(path, true);
-        assert!(obj.is_ok());
-        let (models, meterials) = obj.unwrap();
+        Self::load_obj(path).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn load_obj(path: &str) -> Result<Vec<Self>, String> {
+        let (models, meterials) =
+            tobj::load_obj(path, true).map_err(|err| format!("Load `{}` failed: {}", path, err))?;
 
         fn to_vector3f(c: &[f32; 3]) -> Vector3 {
             vector3(c.clone())
         }
 
-        models
+        Ok(models
             .iter()
             .map(|model| {
                 let mesh = &model.mesh;
@@ -205,6 +256,7 @@ impl Model {
                         // color: None,
                         // material: material,
                         w_reciprocal: None,
+                        varyings: Varyings::new(),
                     })
                     .collect();
 
@@ -217,6 +269,32 @@ impl Model {
                     indices,
                     vertexs,
                     material,
+                    instances: vec![Matrix4::identity()],
+                }
+            })
+            .collect())
+    }
+
+    pub fn instances(mut self, instances: Vec<Matrix4>) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    /// Vertices of one instance in world space, normals use the inverse transpose of `matrix`.
+    pub fn instance_vertexs(&self, matrix: &Matrix4) -> Vec<Vertex> {
+        let normal_matrix = matrix.inverse_matrix().transpose();
+        self.vertexs
+            .iter()
+            .map(|v| {
+                let position = matrix * &v.position;
+                Vertex {
+                    world_position: position.clone(),
+                    position,
+                    normal: v.normal.as_ref().map(|n| {
+                        let n = Vector3::from(&(&normal_matrix * n)).normalized();
+                        Vector4::vector_from(&n)
+                    }),
+                    ..v.clone()
                 }
             })
             .collect()
@@ -240,95 +318,112 @@ impl Model {
 
 impl Model {
     pub fn from_gltf(path: &str) -> Vec<Self> {
-        use crate::algebra::matrix_new::Matrix4;
-        let (gltf, buffers, _) = gltf::import(path).unwrap();
-
-        let nodes = gltf.scenes().flat_map(|scene| scene.nodes()).map(|node| {
-            let attrs = node
-                .mesh()
-                .into_iter()
-                .flat_map(|mesh| mesh.primitives())
-                .map(|primitive| {
-                    let material = primitive.material();
-                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                    let indices = reader
-                        .read_indices()
-                        .expect("Indices no found.")
-                        .into_u32()
-                        // .map(|i| i as usize)
-                        .collect::<Vec<_>>();
-                    let indices = indices
-                        .chunks(3)
-                        .map(|c| [c[0], c[1], c[2]])
-                        .collect::<Vec<_>>();
-
-                    let positions = reader
-                        .read_positions()
-                        .expect("Positions not found.")
-                        .map(|p| Vector4::from(&vector3(p)))
-                        // .map(|p| transform * &p)
-                        .collect::<Vec<_>>();
-
-                    let normals = reader
-                        .read_normals()
-                        .expect("Normals not found.")
-                        .map(|n| Vector4::from(&vector3(n)))
-                        // .map(|n| transform * &n)
-                        .collect::<Vec<_>>();
-                    ((positions, normals, indices), material)
-                });
-            attrs
-        });
+        Self::load_gltf(path).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        let transforms = gltf
-            .scenes()
-            .flat_map(|scene| scene.nodes())
-            .map(|node| Matrix4::from(node.transform().matrix()).transpose())
-            .cycle();
-
-        let models = nodes
-            .zip(transforms)
-            .flat_map(|(mesh_attr, transform)| {
-                let models = mesh_attr
-                    .map(|((positions, normals, indices), material)| {
-                        let positions = positions
-                            .iter()
-                            // .map(|p| vector4([p.x() + x, p.y() + y, p.z() + z, 1.0]))
-                            .map(|v| &transform * v)
-                            .collect::<Vec<_>>();
-
-                        let normals = normals
-                            .iter()
-                            .map(|n| &transform * n)
-                            .map(|v| v.normalized())
-                            .collect::<Vec<_>>();
-
-                        let material = OptionEmissiveMaterial::from(&material).0.map_or_else(
-                            || MaterialNew::PBR(PBRMaterial::from(&material)),
-                            |m| MaterialNew::Emissive(m),
-                        );
-
-                        let vertexs = positions
-                            .into_iter()
-                            .zip(normals.into_iter())
-                            .map(|(position, normal)| Vertex {
-                                position: position.clone(),
-                                world_position: position.clone(),
-                                normal: Some(normal),
-                                texture_coordinate: None,
-                                w_reciprocal: None,
-                            })
-                            .collect::<Vec<_>>();
-                        Model {
-                            vertexs,
-                            indices,
-                            material: Some(Arc::new(material)),
-                        }
-                    })
-                    .collect::<Vec<_>>();
-                models
+    fn load_gltf(path: &str) -> Result<Vec<Self>, String> {
+        Ok(SceneGraph::load(path)?.models())
+    }
+
+    /// Load one primitive of a glTF mesh, vertices stay in the mesh's own space.
+    pub(crate) fn from_gltf_primitive(
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        textures: &[Arc<Texture>],
+    ) -> Result<Self, String> {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let indices = reader
+            .read_indices()
+            .ok_or("Indices not found.")?
+            .into_u32()
+            .collect::<Vec<_>>();
+        let indices = indices
+            .chunks(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect::<Vec<_>>();
+
+        let positions = reader
+            .read_positions()
+            .ok_or("Positions not found.")?
+            .map(|p| Vector4::point_from(&vector3(p)));
+        let normals = reader
+            .read_normals()
+            .ok_or("Normals not found.")?
+            .map(|n| Vector4::vector_from(&vector3(n)).normalized());
+        // glTF puts the uv origin at the top left, flip v like `.obj` texture coordinates.
+        let texture_coordinates: Vec<_> = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().map(|[u, v]| Some((u, 1.0 - v))).collect())
+            .unwrap_or_default();
+
+        let vertexs = positions
+            .zip(normals)
+            .enumerate()
+            .map(|(i, (position, normal))| Vertex {
+                position: position.clone(),
+                world_position: position,
+                normal: Some(normal),
+                texture_coordinate: texture_coordinates.get(i).copied().flatten(),
+                w_reciprocal: None,
+                varyings: Varyings::new(),
             })
             .collect::<Vec<_>>();
-        models
+
+        let material = primitive.material();
+        let material = OptionEmissiveMaterial::from(&material).0.map_or_else(
+            || MaterialNew::PBR(PBRMaterial::from_gltf(&material, textures)),
+            MaterialNew::Emissive,
+        );
+        Ok(Model {
+            vertexs,
+            indices,
+            material: Some(Arc::new(material)),
+            instances: vec![Matrix4::identity()],
+        })
+    }
+
+    /// Serialize the models' geometry as Wavefront OBJ, one object per instance.
+    pub fn to_obj(models: &[Model]) -> String {
+        let mut obj = String::new();
+        let mut offset = 1;
+        let instances = models.iter().enumerate().flat_map(|(i, model)| {
+            model.instances.iter().enumerate().map(move |(j, matrix)| {
+                let vertexs = model.instance_vertexs(matrix);
+                (format!("model_{}_{}", i, j), model, vertexs)
+            })
+        });
+        for (name, model, vertexs) in instances {
+            writeln!(obj, "o {}", name).unwrap();
+            for v in vertexs.iter() {
+                let p = &v.world_position;
+                writeln!(obj, "v {} {} {}", p.x(), p.y(), p.z()).unwrap();
+            }
+            let has_uv = vertexs.iter().all(|v| v.texture_coordinate.is_some());
+            let has_normal = vertexs.iter().all(|v| v.normal.is_some());
+            if has_uv {
+                for (u, v) in vertexs.iter().filter_map(|v| v.texture_coordinate) {
+                    writeln!(obj, "vt {} {}", u, v).unwrap();
+                }
+            }
+            if has_normal {
+                for n in vertexs.iter().filter_map(|v| v.normal.as_ref()) {
+                    writeln!(obj, "vn {} {} {}", n.x(), n.y(), n.z()).unwrap();
+                }
+            }
+            for face in model.indices.iter() {
+                let corners = face.map(|index| {
+                    let index = index as usize + offset;
+                    match (has_uv, has_normal) {
+                        (true, true) => format!("{0}/{0}/{0}", index),
+                        (true, false) => format!("{0}/{0}", index),
+                        (false, true) => format!("{0}//{0}", index),
+                        (false, false) => format!("{}", index),
+                    }
+                });
+                writeln!(obj, "f {} {} {}", corners[0], corners[1], corners[2]).unwrap();
+            }
+            offset += vertexs.len();
+        }
+        obj
     }
 }
@@ -1,19 +1,91 @@
 use core::f32;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-use crate::algebra::vector_new::vector3;
-use crate::Color;
+use crate::algebra::vector_new::{vector3, vector4, Vector4};
 
 use super::fragment_shader::FragmentShaderPayload;
+use super::framebuffer::Framebuffer;
 use super::model::TriangulatedModel;
+use super::vertex_shader::Varyings;
 use super::{fragment_shader::FragmentShader, model::Triangle};
 
+/// Side length in pixels of the square screen-space tiles triangles are binned into.
+pub const TILE_SIZE: usize = 32;
+
+/// Linear color of the pixels and samples no triangle covers.
+pub const BACKGROUND_COLOR: Vector4 = vector4([1.0, 1.0, 1.0, 1.0]);
+
 #[allow(dead_code)]
 pub struct Rasterizer {
     // pub triangles: Vec<Triangle>,
     pub models: Vec<TriangulatedModel>,
     pub width: usize,
     pub height: usize,
+    pub multisample: Multisample,
+    // `multisample.sample_count()` consecutive items per pixel.
     pub z_buffer: Vec<ZBufferItem>,
+    pub tiles: Vec<Tile>,
+}
+
+/// Number of coverage and depth samples taken in each pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multisample {
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+impl Multisample {
+    pub fn sample_count(&self) -> usize {
+        self.pattern().len()
+    }
+
+    /// Sample positions relative to the pixel's integer coordinates,
+    /// following the standard Direct3D patterns.
+    pub fn pattern(&self) -> &'static [(f32, f32)] {
+        const X1: [(f32, f32); 1] = [(0.0, 0.0)];
+        const X2: [(f32, f32); 2] = [(0.25, 0.25), (-0.25, -0.25)];
+        const X4: [(f32, f32); 4] = [
+            (-0.125, -0.375),
+            (0.375, -0.125),
+            (-0.375, 0.125),
+            (0.125, 0.375),
+        ];
+        const X8: [(f32, f32); 8] = [
+            (0.0625, -0.1875),
+            (-0.0625, 0.1875),
+            (0.3125, 0.0625),
+            (-0.1875, -0.3125),
+            (-0.3125, 0.3125),
+            (-0.4375, -0.0625),
+            (0.1875, 0.4375),
+            (0.4375, -0.4375),
+        ];
+        match self {
+            Multisample::X1 => &X1,
+            Multisample::X2 => &X2,
+            Multisample::X4 => &X4,
+            Multisample::X8 => &X8,
+        }
+    }
+}
+
+impl TryFrom<usize> for Multisample {
+    type Error = String;
+    fn try_from(samples: usize) -> Result<Self, Self::Error> {
+        match samples {
+            1 => Ok(Multisample::X1),
+            2 => Ok(Multisample::X2),
+            4 => Ok(Multisample::X4),
+            8 => Ok(Multisample::X8),
+            _ => Err(format!(
+                "Unsupported sample count {}, expected 1, 2, 4 or 8.",
+                samples
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +96,12 @@ pub struct ZBufferItem {
     pub barycenter: (f32, f32, f32),
 }
 
+impl ZBufferItem {
+    pub fn is_covered(&self) -> bool {
+        self.z < f32::MAX
+    }
+}
+
 impl Default for ZBufferItem {
     fn default() -> Self {
         Self {
@@ -35,102 +113,245 @@ impl Default for ZBufferItem {
     }
 }
 
+/// A screen-space rectangle `[x0, x1) x [y0, y1)` with the triangles overlapping it.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+    // (model_index, triangle_index) pairs in draw order.
+    pub triangles: Vec<(usize, usize)>,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    /// Cover `width x height` with `size x size` tiles row by row, the last ones are clipped.
+    pub fn grid(width: usize, height: usize, size: usize) -> Vec<Tile> {
+        let tiles_x = width.div_ceil(size);
+        let tiles_y = height.div_ceil(size);
+        (0..tiles_y)
+            .flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)))
+            .map(|(tx, ty)| Tile {
+                x0: tx * size,
+                y0: ty * size,
+                x1: ((tx + 1) * size).min(width),
+                y1: ((ty + 1) * size).min(height),
+                triangles: Vec::new(),
+            })
+            .collect()
+    }
+}
+
 #[allow(dead_code)]
 impl Rasterizer {
     pub fn new(width: usize, height: usize, models: Vec<TriangulatedModel>) -> Self {
+        Rasterizer::with_multisample(width, height, models, Multisample::X1)
+    }
+
+    pub fn with_multisample(
+        width: usize,
+        height: usize,
+        models: Vec<TriangulatedModel>,
+        multisample: Multisample,
+    ) -> Self {
+        let tiles = Rasterizer::bin_triangles(&models, width, height, multisample);
         let mut rasterizer = Self {
             models,
             width,
             height,
-            z_buffer: vec![ZBufferItem::default(); width * height],
+            multisample,
+            z_buffer: vec![ZBufferItem::default(); width * height * multisample.sample_count()],
+            tiles,
         };
         rasterizer.update_z_buffer();
         rasterizer
     }
 
     pub fn update_z_buffer(&mut self) {
-        let z_buffer = &mut self.z_buffer;
-        let z_buffer_size = z_buffer.len();
+        let tile_z_buffers = for_each_tile(&self.tiles, |tile| self.tile_z_buffer(tile));
 
-        self.models
-            .iter()
-            .enumerate()
-            .for_each(|(model_index, model)| {
-                model
-                    .triangles
-                    .iter()
-                    .enumerate()
-                    .for_each(|(triangle_index, triangle)| {
-                        let (min_x, min_y, max_x, max_y) = Rasterizer::bounding_box(triangle);
-                        for x in min_x..max_x {
-                            for y in min_y..max_y {
-                                let barycenter =
-                                    Rasterizer::barycentric_2d(x as f32, y as f32, triangle);
-                                if Rasterizer::inside_triangle_by_barycenter(barycenter) {
-                                    let index = y * self.width + x;
-                                    let z = -Rasterizer::z_interpolation(triangle, barycenter);
-                                    let barycenter =
-                                        Rasterizer::perspective_correct(triangle, barycenter);
-                                    if index < z_buffer_size && z < z_buffer[index].z {
-                                        z_buffer[index] = ZBufferItem {
-                                            z,
-                                            model_index,
-                                            triangle_index,
-                                            barycenter,
-                                        };
-                                    }
-                                }
-                            }
-                        }
-                    });
-            });
+        let samples = self.multisample.sample_count();
+        for (tile, tile_z_buffer) in self.tiles.iter().zip(tile_z_buffers) {
+            for (row, line) in tile_z_buffer.chunks(tile.width() * samples).enumerate() {
+                let start = ((tile.y0 + row) * self.width + tile.x0) * samples;
+                self.z_buffer[start..start + line.len()].clone_from_slice(line);
+            }
+        }
     }
 
-    pub fn rasterize(&mut self, shader: &Box<dyn FragmentShader>) -> Vec<Option<Color>> {
-        let z_buffer = &mut self.z_buffer;
-        let mut frame_buffer = vec![None; self.height * self.width];
-        let z_buffer_size = z_buffer.len();
+    pub fn rasterize(&mut self, shader: &Box<dyn FragmentShader>) -> Framebuffer {
+        let tile_frame_buffers = for_each_tile(&self.tiles, |tile| {
+            self.tile_frame_buffer(tile, shader.as_ref())
+        });
 
-        self.models
-            .iter()
-            .enumerate()
-            .for_each(|(model_index, model)| {
-                model
-                    .triangles
-                    .iter()
-                    .enumerate()
-                    .for_each(|(triangle_index, triangle)| {
-                        let (min_x, min_y, max_x, max_y) = Rasterizer::bounding_box(triangle);
-                        for x in min_x..max_x {
-                            for y in min_y..max_y {
-                                let index = y * self.width + x;
-                                if index >= z_buffer_size {
-                                    continue;
-                                }
-                                let z_buffer_item = &mut z_buffer[index];
-                                if z_buffer_item.model_index != model_index
-                                    || z_buffer_item.triangle_index != triangle_index
-                                {
-                                    continue;
-                                }
-                                let barycenter = z_buffer_item.barycenter.clone();
-                                let z = z_buffer_item.z;
-                                let payload = FragmentShaderPayload {
-                                    model,
-                                    triangle,
-                                    barycenter,
-                                    z,
-                                };
-                                let color = shader.shade(&payload);
-                                frame_buffer[index] = Some(color);
-                            }
+        // Screen space y points up, framebuffer rows go down.
+        let mut frame_buffer = Framebuffer::new(self.width, self.height, &BACKGROUND_COLOR);
+        for (tile, tile_frame_buffer) in self.tiles.iter().zip(tile_frame_buffers) {
+            for (row, line) in tile_frame_buffer.chunks(tile.width()).enumerate() {
+                let start = (self.height - 1 - (tile.y0 + row)) * self.width + tile.x0;
+                frame_buffer.data[start..start + line.len()].clone_from_slice(line);
+            }
+        }
+        frame_buffer
+    }
+
+    // Split the screen into tiles and record which triangles overlap each one.
+    fn bin_triangles(
+        models: &[TriangulatedModel],
+        width: usize,
+        height: usize,
+        multisample: Multisample,
+    ) -> Vec<Tile> {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let mut tiles = Tile::grid(width, height, TILE_SIZE);
+
+        for (model_index, model) in models.iter().enumerate() {
+            for (triangle_index, triangle) in model.triangles.iter().enumerate() {
+                let (min_x, min_y, max_x, max_y) =
+                    Rasterizer::sample_bounding_box(triangle, multisample);
+                let (max_x, max_y) = (max_x.min(width), max_y.min(height));
+                if min_x >= max_x || min_y >= max_y {
+                    continue;
+                }
+                for ty in min_y / TILE_SIZE..=(max_y - 1) / TILE_SIZE {
+                    for tx in min_x / TILE_SIZE..=(max_x - 1) / TILE_SIZE {
+                        tiles[ty * tiles_x + tx]
+                            .triangles
+                            .push((model_index, triangle_index));
+                    }
+                }
+            }
+        }
+        tiles
+    }
+
+    // Pixels whose samples may fall inside the triangle. Multisampled pixels
+    // reach up to half a pixel around their integer coordinates.
+    fn sample_bounding_box(
+        triangle: &Triangle,
+        multisample: Multisample,
+    ) -> (usize, usize, usize, usize) {
+        let (min_x, min_y, max_x, max_y) = Rasterizer::bounding_box(triangle);
+        if multisample == Multisample::X1 {
+            (min_x, min_y, max_x, max_y)
+        } else {
+            (
+                min_x.saturating_sub(1),
+                min_y.saturating_sub(1),
+                max_x + 1,
+                max_y + 1,
+            )
+        }
+    }
+
+    // Bounding box of the triangle clipped to the tile, in screen coordinates.
+    fn tile_bounding_box(&self, tile: &Tile, triangle: &Triangle) -> (usize, usize, usize, usize) {
+        let (min_x, min_y, max_x, max_y) =
+            Rasterizer::sample_bounding_box(triangle, self.multisample);
+        (
+            min_x.max(tile.x0),
+            min_y.max(tile.y0),
+            max_x.min(tile.x1),
+            max_y.min(tile.y1),
+        )
+    }
+
+    fn tile_z_buffer(&self, tile: &Tile) -> Vec<ZBufferItem> {
+        let pattern = self.multisample.pattern();
+        let mut z_buffer =
+            vec![ZBufferItem::default(); tile.width() * tile.height() * pattern.len()];
+
+        for &(model_index, triangle_index) in tile.triangles.iter() {
+            let triangle = &self.models[model_index].triangles[triangle_index];
+            let (min_x, min_y, max_x, max_y) = self.tile_bounding_box(tile, triangle);
+            for x in min_x..max_x {
+                for y in min_y..max_y {
+                    let pixel_index = (y - tile.y0) * tile.width() + (x - tile.x0);
+                    for (sample_index, (dx, dy)) in pattern.iter().enumerate() {
+                        let (sx, sy) = (x as f32 + dx, y as f32 + dy);
+                        let barycenter = Rasterizer::barycentric_2d(sx, sy, triangle);
+                        if !Rasterizer::inside_triangle_by_barycenter(barycenter) {
+                            continue;
                         }
-                    });
-            });
+                        let index = pixel_index * pattern.len() + sample_index;
+                        let z = -Rasterizer::z_interpolation(triangle, barycenter);
+                        let barycenter = Rasterizer::perspective_correct(triangle, barycenter);
+                        if z < z_buffer[index].z {
+                            z_buffer[index] = ZBufferItem {
+                                z,
+                                model_index,
+                                triangle_index,
+                                barycenter,
+                            };
+                        }
+                    }
+                }
+            }
+        }
+        z_buffer
+    }
 
+    // Shade every covered pixel of the tile exactly once, looking its triangle up in the
+    // z-buffer instead of sweeping the triangles' bounding boxes again.
+    fn tile_frame_buffer(&self, tile: &Tile, shader: &dyn FragmentShader) -> Vec<Vector4> {
+        let samples = self.multisample.sample_count();
+        let mut frame_buffer = Vec::with_capacity(tile.width() * tile.height());
+        for y in tile.y0..tile.y1 {
+            let start = (y * self.width + tile.x0) * samples;
+            let end = (y * self.width + tile.x1) * samples;
+            let line = &self.z_buffer[start..end];
+            frame_buffer.extend(
+                line.chunks(samples)
+                    .map(|pixel_samples| self.shade_pixel(pixel_samples, shader)),
+            );
+        }
         frame_buffer
     }
 
+    // Run the shader once for each triangle covering some samples of the pixel
+    // and resolve the samples to one color.
+    fn shade_pixel(&self, samples: &[ZBufferItem], shader: &dyn FragmentShader) -> Vector4 {
+        let mut colors: Vec<((usize, usize), Vector4, usize)> = Vec::with_capacity(1);
+        for z_buffer_item in samples.iter().filter(|item| item.is_covered()) {
+            let key = (z_buffer_item.model_index, z_buffer_item.triangle_index);
+            if let Some((_, _, count)) = colors.iter_mut().find(|(k, ..)| *k == key) {
+                *count += 1;
+                continue;
+            }
+            let model = &self.models[z_buffer_item.model_index];
+            let triangle = &model.triangles[z_buffer_item.triangle_index];
+            let payload = FragmentShaderPayload {
+                model,
+                triangle,
+                barycenter: z_buffer_item.barycenter,
+                z: z_buffer_item.z,
+                varyings: Rasterizer::interpolate_varyings(triangle, z_buffer_item.barycenter),
+            };
+            colors.push((key, shader.shade(&payload), 1));
+        }
+
+        match colors.as_slice() {
+            [(_, color, count)] if *count == samples.len() => color.clone(),
+            _ => {
+                // Resolve: average the samples, uncovered ones show the background.
+                let covered: usize = colors.iter().map(|(_, _, count)| count).sum();
+                let background = &BACKGROUND_COLOR * (samples.len() - covered) as f32;
+                let sum = colors.iter().fold(background, |sum, (_, color, count)| {
+                    sum + color * *count as f32
+                });
+                sum / samples.len() as f32
+            }
+        }
+    }
+
     fn z_interpolation(triangle: &Triangle, (alpha, beta, gamma): (f32, f32, f32)) -> f32 {
         let v0 = &triangle.vertexs[0].position;
         let v1 = &triangle.vertexs[1].position;
@@ -161,6 +382,20 @@ impl Rasterizer {
         (c1, c2, c3)
     }
 
+    // The barycenter must already be perspective corrected.
+    fn interpolate_varyings(
+        triangle: &Triangle,
+        (alpha, beta, gamma): (f32, f32, f32),
+    ) -> Varyings {
+        let v0 = &triangle.vertexs[0].varyings;
+        let v1 = &triangle.vertexs[1].varyings;
+        let v2 = &triangle.vertexs[2].varyings;
+        v0.iter()
+            .zip(v1.iter().zip(v2.iter()))
+            .map(|(a, (b, c))| a * alpha + b * beta + c * gamma)
+            .collect()
+    }
+
     fn perspective_correct(
         triangle: &Triangle,
         (alpha, beta, gamma): (f32, f32, f32),
@@ -225,3 +460,40 @@ impl Rasterizer {
         !(alpha < 0.0 || beta < 0.0 || gamma < 0.0)
     }
 }
+
+// Run `f` on every tile using all available cores and return the results in tile order.
+// Tiles are handed out one at a time so that cheap tiles don't leave threads idle.
+pub(crate) fn for_each_tile<T, F>(tiles: &[Tile], f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Tile) -> T + Sync,
+{
+    let next_tile = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(tiles.len());
+
+    let mut results = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if i >= tiles.len() {
+                            break;
+                        }
+                        results.push((i, f(&tiles[i])));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,325 @@
+use crate::{
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, Vector3},
+    },
+    pipeline::{
+        camera::{Camera, Projection},
+        light::{Light, LightKind},
+        model::Model,
+        texture::Texture,
+    },
+};
+use std::sync::Arc;
+
+/// Local transform of a node, applied as scale, then rotation, then translation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    // Unit quaternion `[x, y, z, w]`, as stored by glTF.
+    pub rotation: [f32; 4],
+    pub scale: Vector3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::from(0.0),
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: Vector3::from(1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn translation(mut self, translation: Vector3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: [f32; 4]) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn scale(mut self, scale: Vector3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> Matrix4 {
+        let (t, s) = (&self.translation, &self.scale);
+        Matrix4::translation_matrix(t.x(), t.y(), t.z())
+            * Matrix4::quaternion_matrix(self.rotation)
+            * Matrix4::scale_matrix(s.x(), s.y(), s.z())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Option<String>,
+    pub transform: Transform,
+    // Index into `SceneGraph::meshes`.
+    pub mesh: Option<usize>,
+    // Index into `SceneGraph::lights`.
+    pub light: Option<usize>,
+    // Index into `SceneGraph::cameras`.
+    pub camera: Option<usize>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// Node tree of a glTF scene, every node places its mesh with its world matrix.
+#[derive(Debug, Clone)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+    // Primitives of every mesh in the mesh's own space.
+    meshes: Vec<Vec<Model>>,
+    // Lights at the origin pointing down -Z, placed by their nodes.
+    lights: Vec<Light>,
+    // Cameras at the origin looking down -Z, placed by their nodes.
+    cameras: Vec<Camera>,
+    world_matrices: Vec<Matrix4>,
+}
+
+impl SceneGraph {
+    pub fn new(
+        nodes: Vec<Node>,
+        meshes: Vec<Vec<Model>>,
+        lights: Vec<Light>,
+        cameras: Vec<Camera>,
+    ) -> Self {
+        let roots = (0..nodes.len())
+            .filter(|&i| nodes[i].parent.is_none())
+            .collect();
+        let mut graph = Self {
+            world_matrices: vec![Matrix4::identity(); nodes.len()],
+            nodes,
+            roots,
+            meshes,
+            lights,
+            cameras,
+        };
+        graph.update_world_matrices();
+        graph
+    }
+
+    /// Load the default scene of a `.gltf` or `.glb` file, or its first scene.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let (gltf, buffers, images) =
+            gltf::import(path).map_err(|err| format!("Load `{}` failed: {}", path, err))?;
+
+        let textures = images
+            .iter()
+            .map(|image| Texture::from_gltf_image(image).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Load `{}` failed: {}", path, err))?;
+        let meshes = gltf
+            .meshes()
+            .map(|mesh| {
+                mesh.primitives()
+                    .map(|primitive| Model::from_gltf_primitive(&primitive, &buffers, &textures))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Load `{}` failed: {}", path, err))?;
+
+        let lights = gltf.lights().map_or_else(Vec::new, |lights| {
+            lights.map(|light| punctual_light(&light)).collect()
+        });
+        let cameras = gltf.cameras().map(|camera| gltf_camera(&camera)).collect();
+
+        let mut nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Node {
+                    name: node.name().map(String::from),
+                    transform: Transform {
+                        translation: vector3(translation),
+                        rotation,
+                        scale: vector3(scale),
+                    },
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    light: node.light().map(|light| light.index()),
+                    camera: node.camera().map(|camera| camera.index()),
+                    parent: None,
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        for i in 0..nodes.len() {
+            for child in nodes[i].children.clone() {
+                nodes[child].parent = Some(i);
+            }
+        }
+
+        let mut graph = Self::new(nodes, meshes, lights, cameras);
+        if let Some(scene) = gltf.default_scene().or_else(|| gltf.scenes().next()) {
+            graph.roots = scene.nodes().map(|node| node.index()).collect();
+        }
+        Ok(graph)
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    /// Nodes of the scene that have no parent.
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn meshes(&self) -> &[Vec<Model>] {
+        &self.meshes
+    }
+
+    /// Index of the first node called `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    pub fn world_matrix(&self, index: usize) -> &Matrix4 {
+        &self.world_matrices[index]
+    }
+
+    /// Move a node, its children follow it.
+    pub fn set_transform(&mut self, index: usize, transform: Transform) {
+        self.nodes[index].transform = transform;
+        let parent = match self.nodes[index].parent {
+            Some(parent) => self.world_matrices[parent].clone(),
+            None => Matrix4::identity(),
+        };
+        self.update_subtree(index, &parent);
+    }
+
+    fn update_world_matrices(&mut self) {
+        for root in self.roots.clone() {
+            self.update_subtree(root, &Matrix4::identity());
+        }
+    }
+
+    fn update_subtree(&mut self, index: usize, parent: &Matrix4) {
+        let world = parent * &self.nodes[index].transform.matrix();
+        for child in self.nodes[index].children.clone() {
+            self.update_subtree(child, &world);
+        }
+        self.world_matrices[index] = world;
+    }
+
+    /// Visible nodes in depth-first order, starting from the roots of the scene.
+    pub fn traverse(&self) -> Vec<usize> {
+        let mut visited = Vec::new();
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(index) = stack.pop() {
+            visited.push(index);
+            stack.extend(self.nodes[index].children.iter().rev());
+        }
+        visited
+    }
+
+    /// Lights of the visible nodes in world space.
+    pub fn lights(&self) -> Vec<Light> {
+        self.traverse()
+            .into_iter()
+            .filter_map(|index| {
+                let light = &self.lights[self.nodes[index].light?];
+                Some(light.transform(&self.world_matrices[index]))
+            })
+            .collect()
+    }
+
+    /// Cameras of the visible nodes in world space, named after their nodes.
+    pub fn cameras(&self) -> Vec<Camera> {
+        self.traverse()
+            .into_iter()
+            .filter_map(|index| {
+                let node = &self.nodes[index];
+                let camera = self.cameras[node.camera?].transform(&self.world_matrices[index]);
+                let name = node.name.clone().or(camera.name.clone());
+                Some(camera.name(name))
+            })
+            .collect()
+    }
+
+    /// One model per mesh primitive, instanced on every node that references the mesh.
+    pub fn models(&self) -> Vec<Model> {
+        let mut instances = vec![Vec::new(); self.meshes.len()];
+        for index in self.traverse() {
+            if let Some(mesh) = self.nodes[index].mesh {
+                instances[mesh].push(self.world_matrices[index].clone());
+            }
+        }
+        self.meshes
+            .iter()
+            .zip(instances)
+            .filter(|(_, instances)| !instances.is_empty())
+            .flat_map(|(primitives, instances)| {
+                primitives
+                    .iter()
+                    .map(move |model| model.clone().instances(instances.clone()))
+            })
+            .collect()
+    }
+}
+
+// Infinite perspective projections are cut at this distance.
+const INFINITE_FAR: f32 = 1000.0;
+
+fn gltf_camera(source: &gltf::Camera) -> Camera {
+    use gltf::camera::Projection as GLTFProjection;
+    let camera = Camera::default()
+        .eye_position(Vector3::from(0.0))
+        .gaze_direct(vector3([0.0, 0.0, -1.0]))
+        .up_direct(vector3([0.0, 1.0, 0.0]))
+        .name(source.name().map(String::from));
+    match source.projection() {
+        GLTFProjection::Perspective(perspective) => {
+            let camera = camera
+                .eye_fov(perspective.yfov())
+                .near(perspective.znear())
+                .far(perspective.zfar().unwrap_or(INFINITE_FAR));
+            // Without an aspect ratio the image's one is used.
+            match perspective.aspect_ratio() {
+                Some(aspect_ratio) => camera.aspect_ratio(aspect_ratio),
+                None => camera,
+            }
+        }
+        GLTFProjection::Orthographic(orthographic) => camera
+            .projection(Projection::Orthographic {
+                ymag: orthographic.ymag(),
+            })
+            .aspect_ratio(orthographic.xmag() / orthographic.ymag())
+            .near(orthographic.znear())
+            .far(orthographic.zfar()),
+    }
+}
+
+fn punctual_light(light: &gltf::khr_lights_punctual::Light) -> Light {
+    use gltf::khr_lights_punctual::Kind;
+    let kind = match light.kind() {
+        Kind::Point => LightKind::Point,
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+        Kind::Directional => LightKind::Directional,
+    };
+    Light::default()
+        .position(Vector3::from(0.0))
+        .direction(vector3([0.0, 0.0, -1.0]))
+        .color(vector3(light.color()))
+        .intensity(light.intensity())
+        .range(light.range())
+        .kind(kind)
+}
@@ -0,0 +1,161 @@
+use super::{
+    camera::{Camera, Projection},
+    light::{Light, LightKind},
+    model::Model,
+    rasterizer::Rasterizer,
+    transformation::Transformation,
+    vertex_shader::DefaultVertexShader,
+};
+use crate::{
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, Vector3, Vector4},
+    },
+    renderer::triangulated_models,
+};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone)]
+pub struct ShadowMapSettings {
+    // Width and height of the depth map in texels.
+    pub size: usize,
+    // Depth offset that keeps lit surfaces from shadowing themselves.
+    pub bias: f32,
+    // PCF averages (2 * pcf_radius + 1)^2 depth comparisons, 0 disables filtering.
+    pub pcf_radius: usize,
+}
+
+impl Default for ShadowMapSettings {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+}
+
+/// Depth of the scene seen from a light, rendered with the rasterizer.
+#[derive(Debug, Clone)]
+pub struct ShadowMap {
+    // Light's projection * view matrix.
+    pub light_matrix: Matrix4,
+    pub size: usize,
+    pub depth: Vec<f32>,
+    pub bias: f32,
+    pub pcf_radius: usize,
+}
+
+impl ShadowMap {
+    pub fn new(models: &[Model], light: &Light, settings: &ShadowMapSettings) -> Self {
+        let size = settings.size;
+        let camera = ShadowMap::light_camera(models, light);
+        let light_matrix =
+            Transformation::projection_transform(&camera) * Transformation::view_matrix(&camera);
+
+        let models = triangulated_models(models, &camera, &DefaultVertexShader, size, size);
+        let rasterizer = Rasterizer::new(size, size, models);
+        let depth = rasterizer.z_buffer.iter().map(|item| item.z).collect();
+
+        Self {
+            light_matrix,
+            size,
+            depth,
+            bias: settings.bias,
+            pcf_radius: settings.pcf_radius,
+        }
+    }
+
+    /// Fraction of the PCF kernel around `position` that the light reaches, in `[0, 1]`.
+    pub fn visibility(&self, position: &Vector3) -> f32 {
+        let p = &self.light_matrix * &Vector4::point_from(position);
+        if p.w() >= 0.0 {
+            // Behind the light.
+            return 1.0;
+        }
+        let p = &p / p.w();
+        let size = self.size as f32;
+        let (x, y) = ((p.x() + 1.0) * size / 2.0, (p.y() + 1.0) * size / 2.0);
+        let depth = -p.z() - self.bias;
+
+        let radius = self.pcf_radius as isize;
+        let (x, y) = (x.round() as isize, y.round() as isize);
+        let mut lit = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (sx, sy) = (x + dx, y + dy);
+                let outside =
+                    sx < 0 || sy < 0 || sx >= self.size as isize || sy >= self.size as isize;
+                if outside || depth <= self.depth[sy as usize * self.size + sx as usize] {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2 * radius + 1) * (2 * radius + 1)) as f32
+    }
+
+    // A camera that sees the bounding sphere of the scene the way the light does: orthographic
+    // along the rays of a directional light, perspective from the position of the others, over
+    // the cone of a spot light.
+    fn light_camera(models: &[Model], light: &Light) -> Camera {
+        let positions = models
+            .iter()
+            .flat_map(|model| model.instances.iter().map(|m| model.instance_vertexs(m)))
+            .flatten()
+            .map(|vertex| Vector3::from(&vertex.world_position));
+        let (min, max) = positions.fold(
+            (Vector3::from(f32::MAX), Vector3::from(f32::MIN)),
+            |(min, max), p| {
+                (
+                    vector3([min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())]),
+                    vector3([max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())]),
+                )
+            },
+        );
+        let center = (&min + &max) / 2.0;
+        let radius = (&max - &min).norm() / 2.0;
+
+        if light.kind == LightKind::Directional {
+            // Step back from the sphere so that all of it is in front of the camera.
+            let gaze = light.direction.clone();
+            return Camera::default()
+                .eye_position(&center - &(&gaze * (radius + 1.0)))
+                .up_direct(ShadowMap::up_for(&gaze))
+                .gaze_direct(gaze)
+                .projection(Projection::Orthographic { ymag: radius })
+                .aspect_ratio(1.0)
+                .near(0.5)
+                .far(2.0 * radius + 1.5);
+        }
+
+        // A light inside the scene can't see all of it, cover as much as we can.
+        const MAX_FOV: f32 = PI * 2.0 / 3.0;
+        let to_center = &center - &light.position;
+        let distance = to_center.norm();
+        let (gaze, fov) = match light.kind {
+            LightKind::Spot {
+                outer_cone_angle, ..
+            } => (light.direction.clone(), 2.0 * outer_cone_angle),
+            _ if distance > radius => (to_center.normalized(), 2.0 * (radius / distance).asin()),
+            _ => (to_center.normalized(), MAX_FOV),
+        };
+
+        Camera::default()
+            .eye_position(light.position.clone())
+            .up_direct(ShadowMap::up_for(&gaze))
+            .gaze_direct(gaze)
+            .eye_fov(fov.min(MAX_FOV))
+            .aspect_ratio(1.0)
+            .near((distance - radius).max(0.01))
+            .far(distance + radius)
+    }
+
+    // Any direction that isn't parallel to `gaze`.
+    fn up_for(gaze: &Vector3) -> Vector3 {
+        if gaze.y().abs() > 0.99 {
+            vector3([1.0, 0.0, 0.0])
+        } else {
+            vector3([0.0, 1.0, 0.0])
+        }
+    }
+}
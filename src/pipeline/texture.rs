@@ -1,4 +1,5 @@
-use image::{io::Reader as ImageReader, DynamicImage, GenericImageView};
+use image::{io::Reader as ImageReader, DynamicImage, GenericImageView, ImageBuffer};
+use std::fmt;
 
 use crate::Color;
 pub struct Texture {
@@ -7,17 +8,21 @@ pub struct Texture {
     height: f32,
 }
 
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Texture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
 impl Texture {
     pub fn from_path(path: &str) -> Result<Self, String> {
         match ImageReader::open(path) {
             Ok(image_buffer) => {
                 if let Ok(image) = image_buffer.decode() {
-                    let (width, height) = image.dimensions();
-                    Ok(Texture {
-                        image,
-                        width: width as f32,
-                        height: height as f32,
-                    })
+                    Ok(Self::from_image(image))
                 } else {
                     Err(format!("Decode `{}` failed.", path))
                 }
@@ -25,6 +30,40 @@ impl Texture {
             _ => Err(format!("Cannot open `{}`.", path)),
         }
     }
+
+    pub fn from_image(image: DynamicImage) -> Self {
+        let (width, height) = image.dimensions();
+        Texture {
+            image,
+            width: width as f32,
+            height: height as f32,
+        }
+    }
+
+    /// Wrap an image decoded by `gltf::import`, only 8 bit formats are supported.
+    pub fn from_gltf_image(data: &gltf::image::Data) -> Result<Self, String> {
+        use gltf::image::Format;
+        let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+        let image = match data.format {
+            Format::R8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+            }
+            Format::R8G8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+            }
+            Format::R8G8B8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+            }
+            Format::R8G8B8A8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+            }
+            format => return Err(format!("Unsupported image format {:?}.", format)),
+        };
+        image
+            .map(Self::from_image)
+            .ok_or_else(|| "Image data is too short.".to_string())
+    }
+
     pub fn get(&self, u: f32, v: f32) -> Color {
         //Handle some out of bound uv.
         let u = if u < 0.0 {
@@ -42,9 +81,14 @@ impl Texture {
             v
         };
 
-        let x = u * self.width - 1.0;
-        let y = self.height - 1.0 - v * self.height;
+        let x = (u * self.width).min(self.width - 1.0);
+        let y = ((1.0 - v) * self.height).min(self.height - 1.0);
         let c = self.image.get_pixel(x as u32, y as u32);
         Color::rgba(c[0], c[1], c[2], c[3])
     }
+
+    /// Like `get`, but uv outside [0, 1] repeats the image as glTF samplers do by default.
+    pub fn get_repeat(&self, u: f32, v: f32) -> Color {
+        self.get(u.rem_euclid(1.0), v.rem_euclid(1.0))
+    }
 }
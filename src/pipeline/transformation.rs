@@ -1,5 +1,5 @@
 use modeling::Modeling;
-use pipeline::camera::Camera;
+use pipeline::camera::{Camera, Projection};
 
 use crate::{
     algebra::matrix_new::{matrix4, Matrix4},
@@ -16,14 +16,20 @@ impl Transformation {
         modeling.transform_matrix
     }
 
-    pub fn view_matrix(camera: &Camera) -> Matrix4 {
-        let e = &camera.eye_position;
+    /// Right, up and backward axes of the camera, the view space X, Y and Z.
+    pub fn camera_basis(camera: &Camera) -> (Vector3, Vector3, Vector3) {
         let g = &camera.gaze_direct;
         let t = &camera.up_direct;
 
         let w: Vector3 = -(g / g.norm());
         let u: Vector3 = t.cross(&w) / t.cross(&w).norm();
         let v: Vector3 = w.cross(&u);
+        (u, v, w)
+    }
+
+    pub fn view_matrix(camera: &Camera) -> Matrix4 {
+        let e = &camera.eye_position;
+        let (u, v, w) = Transformation::camera_basis(camera);
 
         let translate_matrix = Matrix4::translation_matrix(-e.x(), -e.y(), -e.z());
 
@@ -41,7 +47,10 @@ impl Transformation {
         let n = -camera.near;
         let f = -camera.far;
 
-        let t = -n * (camera.eye_fov / 2.0).tan();
+        let t = match camera.projection {
+            Projection::Orthographic { ymag } => ymag,
+            Projection::Perspective => -n * (camera.eye_fov / 2.0).tan(),
+        };
         let b = -t;
 
         let r = t * camera.aspect_ratio;
@@ -69,6 +78,17 @@ impl Transformation {
         ortho * persp_to_ortho
     }
 
+    /// Perspective or orthographic projection, following the camera.
+    pub fn projection_transform(camera: &Camera) -> Matrix4 {
+        match camera.projection {
+            Projection::Perspective => Transformation::perspective_projection_transform(camera),
+            // Negated to keep w below zero like the perspective projection, the clipper expects it.
+            Projection::Orthographic { .. } => {
+                Transformation::orthogonal_projection_transform(camera) * -1.0
+            }
+        }
+    }
+
     pub fn viewport_transform(width: f32, height: f32) -> Matrix4 {
         matrix4([
             [width / 2.0, 0.0, 0.0, width / 2.0],
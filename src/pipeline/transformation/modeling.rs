@@ -7,7 +7,7 @@ pub struct Modeling {
 impl Modeling {
     pub fn new() -> Self {
         Modeling {
-            transform_matrix: Matrix4::unit(),
+            transform_matrix: Matrix4::identity(),
         }
     }
 
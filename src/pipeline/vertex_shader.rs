@@ -0,0 +1,50 @@
+use super::model::Vertex;
+use crate::algebra::{matrix_new::Matrix4, vector_new::Vector4};
+
+/// User-defined per-vertex outputs. Their layout is up to the vertex shader;
+/// they are clipped and perspective-correctly interpolated before reaching
+/// the fragment shader as `FragmentShaderPayload::varyings`.
+pub type Varyings = Vec<f32>;
+
+pub trait VertexShader: Send + Sync {
+    fn shade(&self, payload: &VertexShaderPayload) -> VertexShaderOutput;
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexShaderPayload<'a> {
+    // In the space of its model.
+    pub vertex: &'a Vertex,
+    // Modeling matrix of the instance being drawn.
+    pub model: &'a Matrix4,
+    pub view: &'a Matrix4,
+    pub projection: &'a Matrix4,
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexShaderOutput {
+    // Position in clip space.
+    pub position: Vector4,
+    pub varyings: Varyings,
+}
+
+/// Transforms positions by the instance's modeling matrix and the camera's
+/// view and projection matrices and outputs no varyings.
+pub struct DefaultVertexShader;
+
+impl VertexShader for DefaultVertexShader {
+    fn shade(
+        &self,
+        VertexShaderPayload {
+            vertex,
+            model,
+            view,
+            projection,
+        }: &VertexShaderPayload,
+    ) -> VertexShaderOutput {
+        let position = *projection * &(*view * &(*model * &vertex.position));
+        VertexShaderOutput {
+            position,
+            varyings: Varyings::new(),
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub mod adaptive;
 pub mod bvh;
+pub mod filter;
 pub mod path_tracing;
 pub mod ray;
+pub mod sampler;
@@ -0,0 +1,139 @@
+use crate::algebra::vector_new::{vector3, Vector3};
+use std::time::Duration;
+
+/// Running mean and variance of the luminance of the samples taken in a pixel (Welford).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PixelVariance {
+    pub count: usize,
+    pub mean: f32,
+    // Sum of the squared differences to the mean.
+    m2: f32,
+}
+
+impl PixelVariance {
+    pub fn add(&mut self, color: &Vector3) {
+        let x = luminance(color);
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Both sets of samples together (Chan et al.).
+    pub fn merge(&mut self, other: &PixelVariance) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f32 / count as f32;
+        self.m2 += other.m2 + delta * delta * (self.count * other.count) as f32 / count as f32;
+        self.count = count;
+    }
+
+    /// Unbiased variance of a single sample.
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f32).max(0.0)
+    }
+
+    /// Standard error of the mean relative to the mean, infinite before two samples.
+    pub fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        // Black pixels don't need a perfect zero.
+        (self.variance() / self.count as f32).sqrt() / self.mean.max(1e-3)
+    }
+}
+
+/// Rec. 709 luminance of a linear color.
+pub fn luminance(c: &Vector3) -> f32 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+/// Keep sampling the noisy pixels of `RayTracer::render` in rounds, until each one is within
+/// `target_error` or a budget runs out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveSampling {
+    // Relative standard error of a pixel mean to stop sampling it at.
+    pub target_error: f32,
+    // Samples of every pixel before its variance is trusted.
+    pub min_spp: usize,
+    // No pixel takes more samples.
+    pub max_spp: usize,
+    // Samples added to every noisy pixel each round.
+    pub batch: usize,
+    pub time_budget: Option<Duration>,
+    // Mean samples per pixel over the whole image.
+    pub sample_budget: Option<f32>,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            target_error: 0.02,
+            min_spp: 16,
+            max_spp: 1024,
+            batch: 16,
+            time_budget: None,
+            sample_budget: None,
+        }
+    }
+}
+
+impl AdaptiveSampling {
+    pub fn target_error(mut self, target_error: f32) -> Self {
+        self.target_error = target_error;
+        self
+    }
+    pub fn min_spp(mut self, min_spp: usize) -> Self {
+        self.min_spp = min_spp;
+        self
+    }
+    pub fn max_spp(mut self, max_spp: usize) -> Self {
+        self.max_spp = max_spp;
+        self
+    }
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch;
+        self
+    }
+    pub fn time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = Some(time_budget);
+        self
+    }
+    pub fn sample_budget(mut self, sample_budget: f32) -> Self {
+        self.sample_budget = Some(sample_budget);
+        self
+    }
+
+    /// Whether a pixel needs more samples.
+    pub fn is_noisy(&self, pixel: &PixelVariance) -> bool {
+        pixel.count < self.max_spp && pixel.relative_error() > self.target_error
+    }
+}
+
+/// Color of a sample count on a black, blue, red, yellow and white ramp, on a log scale from
+/// `min` in black to `max` in white.
+pub fn heat(count: usize, min: usize, max: usize) -> Vector3 {
+    const RAMP: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let (min, max) = (min.max(1) as f32, max.max(1) as f32);
+    let t = if max > min {
+        ((count as f32).clamp(min, max) / min).ln() / (max / min).ln()
+    } else {
+        0.0
+    };
+    let t = t * (RAMP.len() - 1) as f32;
+    let i = (t as usize).min(RAMP.len() - 2);
+    let (a, b) = (vector3(RAMP[i]), vector3(RAMP[i + 1]));
+    a * (1.0 - (t - i as f32)) + b * (t - i as f32)
+}
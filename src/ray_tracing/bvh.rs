@@ -1,4 +1,5 @@
-use crate::algebra::vector_new::Vector4;
+use crate::algebra::matrix_new::Matrix4;
+use crate::algebra::vector_new::{vector3, Vector3, Vector4};
 use crate::pipeline::model::Triangle;
 use crate::ray_tracing::ray::Ray;
 
@@ -42,6 +43,45 @@ impl From<&[Triangle]> for AABB {
 }
 
 impl AABB {
+    /// Contains nothing, the union with it is the other box.
+    pub fn empty() -> Self {
+        Self {
+            x0: f32::MAX,
+            x1: f32::MIN,
+            y0: f32::MAX,
+            y1: f32::MIN,
+            z0: f32::MAX,
+            z1: f32::MIN,
+        }
+    }
+
+    pub fn from_point(p: &Vector3) -> Self {
+        Self {
+            x0: p.x(),
+            x1: p.x(),
+            y0: p.y(),
+            y1: p.y(),
+            z0: p.z(),
+            z1: p.z(),
+        }
+    }
+
+    pub fn axis(&self, axis: usize) -> (f32, f32) {
+        match axis {
+            0 => (self.x0, self.x1),
+            1 => (self.y0, self.y1),
+            _ => (self.z0, self.z1),
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = vector3([self.x1 - self.x0, self.y1 - self.y0, self.z1 - self.z0]);
+        if d.x() < 0.0 || d.y() < 0.0 || d.z() < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn union(&self, other: &Self) -> Self {
         Self {
             x0: self.x0.min(other.x0),
@@ -54,70 +94,101 @@ impl AABB {
     }
 
     pub fn intersect_ray(&self, ray: &Ray) -> bool {
-        let s = &ray.origin;
-        let v = &ray.dir;
-        let &Self {
-            x0,
-            x1,
-            y0,
-            y1,
-            z0,
-            z1,
-        } = self;
-        let (sx, sy, sz) = (s.x(), s.y(), s.z());
-        let (vx, vy, vz) = (v.x(), v.y(), v.z());
-
-        let tx_min = (x0 - sx) / vx;
-        let tx_max = (x1 - sx) / vx;
-
-        let ty_min = (y0 - sy) / vy;
-        let ty_max = (y1 - sy) / vy;
+        self.hit_distance(ray, &inverse_direction(ray), f32::INFINITY)
+            .is_some()
+    }
 
-        let tz_min = (z0 - sz) / vz;
-        let tz_max = (z1 - sz) / vz;
+    /// Where `ray` enters the box, if it does before `t_max`. `inv_dir` is `1 / ray.dir`.
+    pub fn hit_distance(&self, ray: &Ray, inv_dir: &[f32; 3], t_max: f32) -> Option<f32> {
+        // Flat boxes of axis aligned triangles still get hit.
+        const EPSILON: f32 = f32::EPSILON * 10.0;
+        let mut t_enter = f32::MIN;
+        let mut t_exit = f32::MAX;
+        for (axis, inv) in inv_dir.iter().enumerate() {
+            let (min, max) = self.axis(axis);
+            let t0 = (min - ray.origin.0[axis]) * inv;
+            let t1 = (max - ray.origin.0[axis]) * inv;
+            t_enter = t_enter.max(t0.min(t1) - EPSILON);
+            t_exit = t_exit.min(t0.max(t1) + EPSILON);
+        }
+        (t_enter < t_exit && t_exit > 0.0 && t_enter < t_max).then_some(t_enter.max(0.0))
+    }
+}
 
-        let (tx_min, tx_max) = if tx_min > tx_max {
-            (tx_max, tx_min)
-        } else {
-            (tx_min, tx_max)
-        };
-        let (ty_min, ty_max) = if ty_min > ty_max {
-            (ty_max, ty_min)
-        } else {
-            (ty_min, ty_max)
-        };
-        let (tz_min, tz_max) = if tz_min > tz_max {
-            (tz_max, tz_min)
-        } else {
-            (tz_min, tz_max)
-        };
+fn inverse_direction(ray: &Ray) -> [f32; 3] {
+    ray.dir.0.map(|d| 1.0 / d)
+}
 
-        const EPSILON: f32 = f32::EPSILON * 10.0;
-        let tx_min = tx_min - EPSILON;
-        let ty_min = ty_min - EPSILON;
-        let tz_min = tz_min - EPSILON;
-        let tx_max = tx_max + EPSILON;
-        let ty_max = ty_max + EPSILON;
-        let tz_max = tz_max + EPSILON;
+/// What a BVH is built over, the triangles of a mesh or the instances of meshes.
+pub trait Primitive: Clone {
+    fn bounds(&self) -> AABB;
+    fn centroid(&self) -> Vector3;
+}
 
-        let t_enter = tx_min.max(ty_min).max(tz_min);
-        let t_exit = tx_max.min(ty_max).min(tz_max);
-        // println!("{}", t_enter - t_exit);
+impl Primitive for Triangle {
+    fn bounds(&self) -> AABB {
+        AABB::from(std::slice::from_ref(self))
+    }
 
-        t_enter < t_exit && t_exit > 0.0
-        // let tx0 = self.x0 - s.x()
+    fn centroid(&self) -> Vector3 {
+        Vector3::from(&self.get_barycenter())
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct BVHNode {
+#[derive(Debug, Clone)]
+pub struct BVHNode<T = Triangle> {
     pub bounding_box: AABB,
-    pub data: Option<Vec<Triangle>>,
-    pub l: Option<Box<BVHNode>>,
-    pub r: Option<Box<BVHNode>>,
+    pub data: Option<Vec<T>>,
+    pub l: Option<Box<BVHNode<T>>>,
+    pub r: Option<Box<BVHNode<T>>>,
+    // Axis the children were split along.
+    pub axis: usize,
+}
+
+impl<T> Default for BVHNode<T> {
+    fn default() -> Self {
+        Self {
+            bounding_box: AABB::default(),
+            data: None,
+            l: None,
+            r: None,
+            axis: 0,
+        }
+    }
 }
 
-impl BVHNode {
+impl<T> BVHNode<T> {
+    // Surface area weighted cost of the subtree, not yet divided by the root's area.
+    fn sah_cost(&self) -> f32 {
+        let area = self.bounding_box.surface_area();
+        match &self.data {
+            Some(triangles) => INTERSECTION_COST * area * triangles.len() as f32,
+            None => {
+                TRAVERSAL_COST * area
+                    + self.l.as_ref().map_or(0.0, |l| l.sah_cost())
+                    + self.r.as_ref().map_or(0.0, |r| r.sah_cost())
+            }
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        1 + self.l.as_ref().map_or(0, |l| l.node_count())
+            + self.r.as_ref().map_or(0, |r| r.node_count())
+    }
+
+    // Edges from this node to its deepest leaf.
+    pub fn depth(&self) -> usize {
+        match (&self.l, &self.r) {
+            (None, None) => 0,
+            (l, r) => {
+                1 + l
+                    .as_ref()
+                    .map_or(0, |l| l.depth())
+                    .max(r.as_ref().map_or(0, |r| r.depth()))
+            }
+        }
+    }
+
     pub fn sum(&self) -> usize {
         let mut s = self.data.as_ref().map_or(0, |t| t.len());
         if let Some(l) = &self.l {
@@ -130,166 +201,485 @@ impl BVHNode {
     }
 }
 
-pub struct BVHTree {
-    pub root: BVHNode,
+pub struct BVHTree<T = Triangle> {
+    pub root: BVHNode<T>,
 }
 
-impl BVHTree {
+impl<T> BVHTree<T> {
     pub fn sum(&self) -> usize {
         self.root.sum()
     }
 }
 
 impl BVHTree {
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        BVHBuilder::default().build(triangles)
+    }
+}
+
+impl<T> BVHTree<T> {
     pub fn new() -> Self {
         BVHTree {
             root: BVHNode::default(),
         }
     }
 
-    pub fn from_triangles(triangles: &[Triangle]) -> Self {
-        let funcs: [fn(&Vector4) -> f32; 3] = [
-            |v: &Vector4| v.x(),
-            |v: &Vector4| v.y(),
-            |v: &Vector4| v.z(),
-        ];
+    /// Expected cost of tracing a ray through the tree, relative to the root's surface area.
+    pub fn sah_cost(&self) -> f32 {
+        let root_area = self.root.bounding_box.surface_area();
+        if root_area <= 0.0 {
+            return self.root.data.as_ref().map_or(0.0, |t| t.len() as f32) * INTERSECTION_COST;
+        }
+        self.root.sah_cost() / root_area
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
 
-        let mut barycenters: Vec<Vector4> = triangles
+    pub fn depth(&self) -> usize {
+        self.root.depth()
+    }
+}
+
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+/// Builds a `BVHTree` by binned surface area heuristic splits.
+#[derive(Debug, Clone)]
+pub struct BVHBuilder {
+    // Nodes with this many primitives or fewer become leaves.
+    pub leaf_size: usize,
+    pub max_depth: usize,
+    pub bins: usize,
+}
+
+impl Default for BVHBuilder {
+    fn default() -> Self {
+        Self {
+            leaf_size: 4,
+            max_depth: 32,
+            bins: 16,
+        }
+    }
+}
+
+impl BVHBuilder {
+    pub fn leaf_size(mut self, leaf_size: usize) -> Self {
+        self.leaf_size = leaf_size.max(1);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn bins(mut self, bins: usize) -> Self {
+        self.bins = bins.max(2);
+        self
+    }
+
+    pub fn build<T: Primitive>(&self, primitives: &[T]) -> BVHTree<T> {
+        let bounds = primitives.iter().map(T::bounds).collect::<Vec<_>>();
+        let centroids = primitives.iter().map(T::centroid).collect::<Vec<_>>();
+        let mut indices = (0..primitives.len()).collect::<Vec<_>>();
+        let root = self.build_node(primitives, &bounds, &centroids, &mut indices, 0);
+        BVHTree { root }
+    }
+
+    fn build_node<T: Primitive>(
+        &self,
+        primitives: &[T],
+        bounds: &[AABB],
+        centroids: &[Vector3],
+        indices: &mut [usize],
+        depth: usize,
+    ) -> BVHNode<T> {
+        let bounding_box = indices
             .iter()
-            .map(|triangle| triangle.get_barycenter())
-            .collect();
-
-        let mut node_indexs: Vec<Option<usize>> = vec![None; triangles.len() * 2 + 1 + 1];
-
-        BVHTree::build_tree_leaves(
-            &mut barycenters,
-            &mut node_indexs,
-            0,
-            triangles.len() - 1,
-            1,
-            &funcs,
-            0,
-        );
-        let mut tree = Self {
-            root: BVHNode::default(),
-        };
-        BVHTree::build_tree(
-            triangles,
-            &mut node_indexs,
-            &mut tree.root,
-            1,
-            0,
-            triangles.len() - 1,
-        );
-        tree
-    }
-
-    fn build_tree(
-        triangles: &[Triangle],
-        node_indexs: &mut [Option<usize>],
-        node: &mut BVHNode,
-        i: usize,
-        l: usize,
-        r: usize,
-    ) {
-        if let Some(median) = node_indexs[i] {
-            node.data = None;
-            node.l = Some(Box::new(BVHNode::default()));
-            node.r = Some(Box::new(BVHNode::default()));
-
-            let left = node.l.as_mut().unwrap().as_mut();
-            let right = node.r.as_mut().unwrap().as_mut();
-            BVHTree::build_tree(triangles, node_indexs, left, i * 2, l, median);
-            BVHTree::build_tree(triangles, node_indexs, right, i * 2 + 1, median + 1, r);
-            node.bounding_box = left.bounding_box.union(&right.bounding_box);
+            .fold(AABB::empty(), |b, &i| b.union(&bounds[i]));
+        let split = if depth < self.max_depth && indices.len() > self.leaf_size {
+            self.find_split(&bounding_box, bounds, centroids, indices)
         } else {
-            let triangles = triangles[l..=r].to_vec();
-            let bounding_box = AABB::from(triangles.as_slice());
-            node.data = Some(triangles);
-            node.bounding_box = bounding_box;
+            None
+        };
+
+        match split {
+            Some((axis, position)) => {
+                let mut mid = 0;
+                for i in 0..indices.len() {
+                    if centroids[indices[i]].0[axis] < position {
+                        indices.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+                let (left, right) = indices.split_at_mut(mid);
+                let l = self.build_node(primitives, bounds, centroids, left, depth + 1);
+                let r = self.build_node(primitives, bounds, centroids, right, depth + 1);
+                BVHNode {
+                    bounding_box,
+                    data: None,
+                    l: Some(Box::new(l)),
+                    r: Some(Box::new(r)),
+                    axis,
+                }
+            }
+            None => BVHNode {
+                bounding_box,
+                data: Some(indices.iter().map(|&i| primitives[i].clone()).collect()),
+                l: None,
+                r: None,
+                axis: 0,
+            },
         }
     }
 
-    fn build_tree_leaves(
-        barycenters: &mut [Vector4],
-        node_indexs: &mut [Option<usize>],
-        p: usize,
-        r: usize,
-        i: usize,
-        funcs: &[fn(&Vector4) -> f32],
-        mut fi: usize,
-    ) {
-        if r - p < 5 {
-            return;
+    // The axis and centroid coordinate of the cheapest bin boundary, none if the centroids coincide.
+    fn find_split(
+        &self,
+        bounding_box: &AABB,
+        bounds: &[AABB],
+        centroids: &[Vector3],
+        indices: &[usize],
+    ) -> Option<(usize, f32)> {
+        let centroid_box = indices.iter().fold(AABB::empty(), |b, &i| {
+            b.union(&AABB::from_point(&centroids[i]))
+        });
+        let area = bounding_box.surface_area();
+        let mut best: Option<(usize, f32, f32)> = None;
+
+        for axis in 0..3 {
+            let (min, max) = centroid_box.axis(axis);
+            if max - min <= 0.0 {
+                continue;
+            }
+            let bin_of = |i: usize| {
+                let b = ((centroids[i].0[axis] - min) / (max - min) * self.bins as f32) as usize;
+                b.min(self.bins - 1)
+            };
+            let mut bins = vec![(AABB::empty(), 0usize); self.bins];
+            for &i in indices {
+                let bin = &mut bins[bin_of(i)];
+                bin.0 = bin.0.union(&bounds[i]);
+                bin.1 += 1;
+            }
+
+            // Area and count right of every boundary, then sweep from the left.
+            let mut right = vec![(0.0, 0); self.bins];
+            let (mut right_box, mut right_count) = (AABB::empty(), 0);
+            for b in (1..self.bins).rev() {
+                right_box = right_box.union(&bins[b].0);
+                right_count += bins[b].1;
+                right[b] = (right_box.surface_area(), right_count);
+            }
+            let (mut left_box, mut left_count) = (AABB::empty(), 0);
+            for b in 1..self.bins {
+                left_box = left_box.union(&bins[b - 1].0);
+                left_count += bins[b - 1].1;
+                let (right_area, right_count) = right[b];
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * (left_box.surface_area() * left_count as f32
+                            + right_area * right_count as f32)
+                        / area;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    let position = min + (max - min) * b as f32 / self.bins as f32;
+                    best = Some((axis, position, cost));
+                }
+            }
         }
-        let median_index = BVHTree::quick_select(barycenters, p, r, (r - p) / 2, funcs[fi]);
-        node_indexs[i] = Some(median_index);
-        fi = (fi + 1) % funcs.len();
-        // Build left child tree.
-        BVHTree::build_tree_leaves(barycenters, node_indexs, p, median_index, i * 2, funcs, fi);
-        // Build right child tree.
-        BVHTree::build_tree_leaves(
-            barycenters,
-            node_indexs,
-            median_index,
-            r,
-            i * 2 + 1,
-            funcs,
-            fi,
-        );
-    }
-
-    fn quick_select(
-        barycenters: &mut [Vector4],
-        p: usize,
-        r: usize,
-        i: usize,
-        f: impl Fn(&Vector4) -> f32,
-    ) -> usize {
-        if p == r {
-            return p;
+
+        best.map(|(axis, position, _)| (axis, position))
+    }
+}
+
+/// A BVH flattened into one array in depth first order, the first child of a node follows it.
+pub struct LinearBVH<T = Triangle> {
+    pub nodes: Vec<LinearBVHNode>,
+    // Primitives of every leaf, next to each other.
+    pub primitives: Vec<T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinearBVHNode {
+    pub bounding_box: AABB,
+    // First primitive of a leaf, or the second child of an interior node.
+    pub offset: u32,
+    // Zero for interior nodes.
+    pub count: u32,
+    pub axis: u8,
+}
+
+impl LinearBVHNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+impl<T> From<BVHTree<T>> for LinearBVH<T> {
+    fn from(tree: BVHTree<T>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(tree.node_count()),
+            primitives: Vec::with_capacity(tree.sum()),
+        };
+        // An empty leaf would read as an interior node.
+        if tree.sum() > 0 {
+            bvh.flatten(tree.root);
         }
-        let q = BVHTree::randomized_partition(barycenters, p, r, &f);
-        let k = q - p + 1;
+        bvh
+    }
+}
 
-        if i == k {
-            q
-        } else if i < k {
-            return BVHTree::quick_select(barycenters, p, q - 1, i, f);
-        } else {
-            return BVHTree::quick_select(barycenters, q + 1, r, i - k, f);
+impl LinearBVH {
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        Self::from(BVHTree::from_triangles(triangles))
+    }
+
+    /// Nearest triangle hit between `t_min` and `t_max`, with its index, distance and barycenter.
+    pub fn nearest_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(usize, f32, (f32, f32, f32))> {
+        self.traverse_triangles(ray, t_min, t_max, false)
+    }
+
+    /// Whether anything lies between `t_min` and `t_max`, for shadow rays.
+    pub fn any_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.traverse_triangles(ray, t_min, t_max, true).is_some()
+    }
+
+    fn traverse_triangles(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        any_hit: bool,
+    ) -> Option<(usize, f32, (f32, f32, f32))> {
+        self.traverse(ray, t_min, t_max, any_hit, |triangle, _| {
+            ray.intersect_triangle_distance(triangle)
+        })
+    }
+}
+
+impl<T> LinearBVH<T> {
+    fn flatten(&mut self, node: BVHNode<T>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(LinearBVHNode {
+            bounding_box: node.bounding_box,
+            offset: self.primitives.len() as u32,
+            count: 0,
+            axis: node.axis as u8,
+        });
+        match node.data {
+            Some(mut primitives) => {
+                self.nodes[index].count = primitives.len() as u32;
+                self.primitives.append(&mut primitives);
+            }
+            None => {
+                if let Some(l) = node.l {
+                    self.flatten(*l);
+                }
+                if let Some(r) = node.r {
+                    self.nodes[index].offset = self.flatten(*r) as u32;
+                }
+            }
         }
+        index
     }
 
-    fn randomized_partition(
-        barycenters: &mut [Vector4],
-        p: usize,
-        r: usize,
-        f: impl Fn(&Vector4) -> f32,
-    ) -> usize {
-        use rand::Rng;
-        let i = rand::thread_rng().gen_range(p..r);
-        barycenters.swap(i, r);
-        return BVHTree::partition(barycenters, p, r, f);
-    }
-
-    fn partition(
-        barycenters: &mut [Vector4],
-        p: usize,
-        r: usize,
-        f: impl Fn(&Vector4) -> f32,
-    ) -> usize {
-        // let x =
-        let mut i = p as isize - 1;
-        for j in p..r {
-            let m = f(&barycenters[j]);
-            let n = f(&barycenters[r]);
-            if m < n {
-                i += 1;
-                barycenters.swap(i as usize, j);
+    // Visits the near child first and skips nodes behind the nearest hit so far. `intersect`
+    // gives the distance to a primitive, given the nearest one so far, and what else the caller
+    // wants to know of the hit.
+    fn traverse<H>(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        mut t_max: f32,
+        any_hit: bool,
+        mut intersect: impl FnMut(&T, f32) -> Option<(f32, H)>,
+    ) -> Option<(usize, f32, H)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_dir = inverse_direction(ray);
+        let mut nearest = None;
+        let mut stack = Vec::with_capacity(64);
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node
+                .bounding_box
+                .hit_distance(ray, &inv_dir, t_max)
+                .is_some()
+            {
+                if node.is_leaf() {
+                    let start = node.offset as usize;
+                    for i in start..start + node.count as usize {
+                        if let Some((t, hit)) = intersect(&self.primitives[i], t_max) {
+                            if t > t_min && t < t_max {
+                                t_max = t;
+                                nearest = Some((i, t, hit));
+                                if any_hit {
+                                    return nearest;
+                                }
+                            }
+                        }
+                    }
+                } else {
+                    let (near, far) = if inv_dir[node.axis as usize] < 0.0 {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack.push(far);
+                    current = near;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => return nearest,
             }
         }
-        barycenters.swap((i + 1) as usize, r);
-        (i + 1) as usize
+    }
+}
+
+/// A mesh placed in the world by a modeling matrix. Rays are brought into the space of the mesh
+/// rather than a copy of the mesh into the world.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    // Index of the mesh in `InstancedBVH::meshes`.
+    pub mesh: usize,
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+    // Inverse transpose of `matrix`, for normals.
+    pub normal_matrix: Matrix4,
+    // The mesh in world space.
+    pub bounding_box: AABB,
+}
+
+impl Instance {
+    /// `bounds` is the bounding box of the mesh in its own space.
+    pub fn new(mesh: usize, matrix: Matrix4, bounds: &AABB) -> Self {
+        let inverse = matrix.inverse_matrix();
+        let normal_matrix = inverse.transpose();
+        let bounding_box = (0..8)
+            .map(|corner| {
+                let (x, y, z) = (bounds.axis(0), bounds.axis(1), bounds.axis(2));
+                let pick = |(min, max): (f32, f32), bit: usize| {
+                    if corner & bit == 0 {
+                        min
+                    } else {
+                        max
+                    }
+                };
+                vector3([pick(x, 1), pick(y, 2), pick(z, 4)])
+            })
+            .fold(AABB::empty(), |b, corner| {
+                b.union(&AABB::from_point(&Vector3::from(
+                    &(&matrix * &Vector4::point_from(&corner)),
+                )))
+            });
+        Self {
+            mesh,
+            matrix,
+            inverse,
+            normal_matrix,
+            bounding_box,
+        }
+    }
+
+    /// `ray` in the space of the mesh. Its direction isn't normalized, so distances along it are
+    /// the same as in the world.
+    pub fn to_mesh(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: Vector3::from(&(&self.inverse * &Vector4::point_from(&ray.origin))),
+            dir: Vector3::from(&(&self.inverse * &Vector4::vector_from(&ray.dir))),
+        }
+    }
+
+    pub fn point_to_world(&self, p: &Vector3) -> Vector3 {
+        Vector3::from(&(&self.matrix * &Vector4::point_from(p)))
+    }
+
+    pub fn vector_to_world(&self, v: &Vector3) -> Vector3 {
+        Vector3::from(&(&self.matrix * &Vector4::vector_from(v)))
+    }
+
+    pub fn normal_to_world(&self, n: &Vector3) -> Vector3 {
+        Vector3::from(&(&self.normal_matrix * &Vector4::vector_from(n))).normalized()
+    }
+
+    /// Area of a triangle of the mesh once in the world.
+    pub fn area(&self, triangle: &Triangle) -> f32 {
+        let p = |i: usize| self.point_to_world(&Vector3::from(&triangle.vertexs[i].position));
+        let (a, b, c) = (p(0), p(1), p(2));
+        (&b - &a).cross(&(&c - &a)).norm() / 2.0
+    }
+}
+
+impl Primitive for Instance {
+    fn bounds(&self) -> AABB {
+        self.bounding_box.clone()
+    }
+
+    fn centroid(&self) -> Vector3 {
+        let b = &self.bounding_box;
+        vector3([b.x0 + b.x1, b.y0 + b.y1, b.z0 + b.z1]) * 0.5
+    }
+}
+
+/// Index of the instance and of the triangle in its mesh, distance along the ray and barycenter.
+pub type InstanceHit = ((usize, usize), f32, (f32, f32, f32));
+
+/// Two levels of BVH: one over the triangles of every mesh in the space of the mesh, and one over
+/// the instances placing the meshes in the world.
+pub struct InstancedBVH {
+    pub meshes: Vec<LinearBVH>,
+    pub instances: LinearBVH<Instance>,
+}
+
+impl InstancedBVH {
+    /// `instances` pairs the index of a mesh in `meshes` with its modeling matrix.
+    pub fn new(meshes: Vec<LinearBVH>, instances: &[(usize, Matrix4)]) -> Self {
+        let instances = instances
+            .iter()
+            .filter_map(|(mesh, matrix)| {
+                let root = meshes[*mesh].nodes.first()?;
+                Some(Instance::new(*mesh, matrix.clone(), &root.bounding_box))
+            })
+            .collect::<Vec<_>>();
+        Self {
+            meshes,
+            instances: LinearBVH::from(BVHBuilder::default().leaf_size(1).build(&instances)),
+        }
+    }
+
+    /// Nearest triangle hit between `t_min` and `t_max`, the instance indexes
+    /// `instances.primitives`.
+    pub fn nearest_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<InstanceHit> {
+        self.traverse(ray, t_min, t_max, false)
+    }
+
+    /// Whether anything lies between `t_min` and `t_max`, for shadow rays.
+    pub fn any_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.traverse(ray, t_min, t_max, true).is_some()
+    }
+
+    fn traverse(&self, ray: &Ray, t_min: f32, t_max: f32, any_hit: bool) -> Option<InstanceHit> {
+        self.instances
+            .traverse(ray, t_min, t_max, any_hit, |instance, t_max| {
+                let mesh = &self.meshes[instance.mesh];
+                let (triangle, t, barycenter) =
+                    mesh.traverse_triangles(&instance.to_mesh(ray), t_min, t_max, any_hit)?;
+                Some((t, (triangle, barycenter)))
+            })
+            .map(|(instance, t, (triangle, barycenter))| ((instance, triangle), t, barycenter))
     }
 }
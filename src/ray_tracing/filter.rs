@@ -0,0 +1,156 @@
+use crate::algebra::vector_new::Vector3;
+use std::{f32::consts::PI, str::FromStr};
+
+/// Reconstruction filter of the path tracer, every sample is splatted to the pixels whose center
+/// is within `radius` of it, weighted by the filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    // Shifted down to reach zero at the radius.
+    Gaussian { radius: f32, sigma: f32 },
+    // Cubic of Mitchell and Netravali stretched over the radius, B = C = 1/3 is their choice.
+    Mitchell { radius: f32, b: f32, c: f32 },
+    // Sinc windowed by a wider sinc, with as many lobes as the radius.
+    Lanczos { radius: f32 },
+}
+
+impl Default for Filter {
+    // One sample reaches one pixel, the plain mean of its samples.
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "box" => Ok(Filter::default()),
+            "tent" => Ok(Filter::Tent { radius: 1.0 }),
+            "gaussian" => Ok(Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            }),
+            "mitchell" => Ok(Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            }),
+            "lanczos" => Ok(Filter::Lanczos { radius: 2.0 }),
+            _ => Err(format!(
+                "Unknown filter `{}`, expected `box`, `tent`, `gaussian`, `mitchell` or `lanczos`.",
+                s
+            )),
+        }
+    }
+}
+
+impl Filter {
+    /// Never under half a pixel, so every sample reaches the pixel it falls in.
+    pub fn radius(&self) -> f32 {
+        let radius = match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        };
+        radius.max(0.5)
+    }
+
+    /// The same filter over another radius, in pixels.
+    pub fn with_radius(mut self, r: f32) -> Self {
+        match &mut self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => *radius = r,
+        }
+        self
+    }
+
+    /// Weight of a sample `(x, y)` pixels away from a pixel center, zero outside the radius.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x,
+            Filter::Gaussian { sigma, .. } => {
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => mitchell(2.0 * x / radius, b, c),
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let (x2, x3) = (x * x, x * x * x);
+    let f = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    f / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+/// Filter-weighted sum of the samples splatted to a pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelSum {
+    pub color: Vector3,
+    pub weight: f32,
+}
+
+impl Default for PixelSum {
+    fn default() -> Self {
+        Self {
+            color: Vector3::new(),
+            weight: 0.0,
+        }
+    }
+}
+
+impl PixelSum {
+    pub fn add(&mut self, color: &Vector3, weight: f32) {
+        self.color += color * weight;
+        self.weight += weight;
+    }
+
+    pub fn merge(&mut self, other: &PixelSum) {
+        self.color += other.color.clone();
+        self.weight += other.weight;
+    }
+
+    /// Weighted mean of the samples, black while the weights sum to about zero or below: the
+    /// negative lobes of Mitchell and Lanczos can cancel the positive ones out.
+    pub fn mean(&self) -> Vector3 {
+        if self.weight <= 1e-6 {
+            Vector3::new()
+        } else {
+            &self.color / self.weight
+        }
+    }
+}
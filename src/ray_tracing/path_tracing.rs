@@ -1,132 +1,498 @@
 use crate::{
-    algebra::vector_new::{vector3, Vector3},
-    interpolate, interpolate_triangle,
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, Vector3, Vector4},
+    },
+    interpolate, interpolate_triangle, interpolate_uv,
     pipeline::{
+        camera::{Camera, Projection},
+        framebuffer::{Framebuffer, ToneMapping},
+        light::Light,
         material::{IlluminateType, MaterialNew, PBRMaterial},
         model::{Model, Triangle, TriangulatedModel},
+        rasterizer::{for_each_tile, Tile},
+        scene_graph::SceneGraph,
+        transformation::Transformation,
+    },
+    ray_tracing::{
+        adaptive::{heat, AdaptiveSampling, PixelVariance},
+        filter::{Filter, PixelSum},
+        ray::Ray,
+        sampler::{Sampler, SobolSampler},
     },
-    ray_tracing::ray::Ray,
     renderer::triangulated_models_and_triangles,
+    scene::Scene,
     window::pbr_window::PBRWindow,
-    Color,
 };
-use rand::Rng;
-use std::{f32::consts::PI, sync::Arc};
+use image::ImageResult;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{f32::consts::PI, ops::Range, path::Path, sync::Arc, time::Instant};
 
-use super::bvh::{BVHNode, BVHTree};
+use super::bvh::{Instance, InstancedBVH, LinearBVH};
 
 pub struct RayTracer {
-    pub objects_tree: BVHTree,
+    pub objects_tree: InstancedBVH,
+    // Every mesh in its own space, `objects_tree` places their instances in the world.
     pub objects: Vec<TriangulatedModel>,
-    pub framebuffer: Vec<Vector3>,
+    emitters: Vec<Emitter>,
+    // Punctual lights, emissive objects light the scene as well.
+    pub lights: Vec<Light>,
+    pub framebuffer: Framebuffer,
+    // Sum of every progressive pass since the camera or the scene last changed.
+    pub accumulation: Vec<PixelSum>,
+    pub passes: usize,
+    // Luminance statistics of the samples taken in every pixel, since the last render or reset.
+    pub variance: Vec<PixelVariance>,
+    // Spend samples where pixels are noisy instead of `spp` everywhere.
+    pub adaptive: Option<AdaptiveSampling>,
+    pub tone_mapping: ToneMapping,
+    pub camera: Camera,
     pub shaded_count: usize,
     pub width: usize,
     pub height: usize,
     pub spp: usize,
     pub background_color: Vector3,
+    // Random numbers of every pixel sample, cloned for each tile.
+    pub sampler: Box<dyn Sampler>,
+    pub filter: Filter,
+}
+
+// An emissive instance, `sample_light` picks one by area and then a triangle of it.
+struct Emitter {
+    // Index in `objects_tree.instances.primitives`.
+    instance: usize,
+    // World space areas of the triangles of the mesh, summed up to every one.
+    triangle_areas: Vec<f32>,
+    // Areas of the emitters summed up to this one.
+    area: f32,
 }
 
 const ELISION: f32 = 0.001;
+// Small enough that the last tiles finish close together.
+const TILE_SIZE: usize = 16;
 
 impl RayTracer {
+    /// `instances` pairs the index of a mesh in `objects` with its modeling matrix.
     pub fn new(
         width: usize,
         height: usize,
-        triangles: Vec<Triangle>,
         objects: Vec<TriangulatedModel>,
+        instances: &[(usize, Matrix4)],
         spp: usize,
     ) -> Self {
-        let objects_tree = BVHTree::from_triangles(&triangles.as_slice());
+        let meshes = objects
+            .iter()
+            .map(|object| LinearBVH::from_triangles(&object.triangles))
+            .collect();
+        let objects_tree = InstancedBVH::new(meshes, instances);
+        let mut emit_area = 0.0;
+        let emitters = objects_tree
+            .instances
+            .primitives
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| objects[instance.mesh].has_emit())
+            .filter_map(|(i, instance)| {
+                let mut area = 0.0;
+                let triangle_areas = objects[instance.mesh]
+                    .triangles
+                    .iter()
+                    .map(|triangle| {
+                        area += instance.area(triangle);
+                        area
+                    })
+                    .collect();
+                if area <= 0.0 {
+                    return None;
+                }
+                emit_area += area;
+                Some(Emitter {
+                    instance: i,
+                    triangle_areas,
+                    area: emit_area,
+                })
+            })
+            .collect();
         let ray_tracer = Self {
             objects,
             objects_tree,
-            framebuffer: vec![Vector3::new(); width * height],
+            emitters,
+            lights: Vec::new(),
+            framebuffer: Framebuffer::new(width, height, &Vector4::new()),
+            accumulation: vec![PixelSum::default(); width * height],
+            passes: 0,
+            variance: vec![PixelVariance::default(); width * height],
+            adaptive: None,
+            tone_mapping: ToneMapping::default(),
+            camera: Camera::default()
+                .eye_position(vector3([0.0, 1.0, 3.4]))
+                .eye_fov(PI / 4.0)
+                .aspect_ratio(width as f32 / height as f32),
             shaded_count: 0,
             width,
             height,
             spp,
             background_color: vector3([0.27, 0.27, 0.27]),
+            sampler: Box::new(SobolSampler::new(0)),
+            filter: Filter::default(),
         };
         ray_tracer
     }
 
+    /// Primary ray through the center of a pixel, following the camera like `Transformation::view_matrix`.
     pub fn pixel_to_ray(&self, x: usize, y: usize) -> Ray {
-        const FOV: f32 = PI / 4.0;
-        let scale: f32 = (FOV / 2.0).tan();
+        self.film_to_ray((x as f32 + 0.5, y as f32 + 0.5))
+    }
 
+    /// Primary ray through a point of the image in pixels, (0, 0) is the top left corner.
+    pub fn film_to_ray(&self, (x, y): (f32, f32)) -> Ray {
+        let camera = &self.camera;
+        let (u, v, w) = Transformation::camera_basis(camera);
         let (width, height) = (self.width as f32, self.height as f32);
-        let (x, y) = (x as f32, y as f32);
-        let aspect_radio = width / height;
 
-        let x = (2.0 * (x) / width - 1.0) * scale * aspect_radio;
-        let y = (1.0 - 2.0 * (y) / height) * scale;
+        // Image plane coordinates in [-1, 1], y points up.
+        let x = (2.0 * x / width - 1.0) * camera.aspect_ratio;
+        let y = 1.0 - 2.0 * y / height;
 
-        let dir = vector3([x, y, -1.0]).normalized();
-        // let origin_z = self.width as f32 * 1.7;
-        let origin = vector3([0.0, 1.0, 3.4]);
+        match camera.projection {
+            Projection::Perspective => {
+                let scale: f32 = (camera.eye_fov / 2.0).tan();
+                let dir = (u * (x * scale) + v * (y * scale) - w).normalized();
+                let origin = camera.eye_position.clone();
+                Ray { origin, dir }
+            }
+            // Parallel rays start on the image plane.
+            Projection::Orthographic { ymag } => {
+                let origin = &camera.eye_position + &(u * (x * ymag) + v * (y * ymag));
+                Ray { origin, dir: -w }
+            }
+        }
+    }
 
+    /// Ray through a point of the image, the lens point is the next 2D sample of `sampler`.
+    pub fn sample_ray(&self, film: (f32, f32), sampler: &mut dyn Sampler) -> Ray {
+        let ray = self.film_to_ray(film);
+        // Taken even without a lens, so the rest of the path uses the same dimensions.
+        let st = sampler.get_2d();
+        let lens = match &self.camera.lens {
+            Some(lens) if lens.aperture > 0.0 => lens,
+            _ => return ray,
+        };
+        let (u, v, w) = Transformation::camera_basis(&self.camera);
+        let (lx, ly) = lens.sample(st);
+
+        // Every ray of the pixel meets on the focal plane.
+        let focus = &ray.origin + &(&ray.dir * (lens.focal_distance / -ray.dir.dot(&w)));
+        let origin = &ray.origin + &(u * lx + v * ly);
+        let dir = (&focus - &origin).normalized();
         Ray { origin, dir }
     }
 
-    pub fn frame_buffer<'a>(&self) -> Vec<u32> {
-        self.framebuffer
+    /// Distance along the gaze to the surface seen through a pixel, to focus the lens on it.
+    pub fn focus_distance(&self, x: usize, y: usize) -> Option<f32> {
+        let ray = self.pixel_to_ray(x, y);
+        let (_, _, w) = Transformation::camera_basis(&self.camera);
+        let hit = self.get_nearest_intersection(&ray)?;
+        Some(hit.distance * -ray.dir.dot(&w))
+    }
+
+    pub fn frame_buffer(&self) -> Vec<u32> {
+        self.framebuffer.to_bitmap(&self.tone_mapping)
+    }
+
+    pub fn from_gltf(path: &str, width: usize, height: usize, spp: usize) -> Result<Self, String> {
+        let scene = Scene::from(&SceneGraph::load(path)?);
+        Ok(RayTracer::from_scene(&scene, width, height, spp))
+    }
+
+    /// Trace a scene with its lights, models without a PBR material get an approximated one.
+    pub fn from_scene(scene: &Scene, width: usize, height: usize, spp: usize) -> Self {
+        let models = scene
+            .models
             .iter()
-            .map(|v| (&Color::from(v)).into())
-            .collect()
-    }
-
-    pub fn render(path: &str, spp: usize) {
-        use indicatif::{ProgressBar, ProgressStyle};
-        const WIDTH: usize = 800;
-        const HEIGHT: usize = 800;
-        let models = Model::from_gltf(path);
-        let (objects, triangles) = triangulated_models_and_triangles(&models, (WIDTH / 2) as f32);
-        let mut ray_tracer = RayTracer::new(WIDTH, HEIGHT, triangles, objects, spp);
-
-        println!("Rendering {}, {}x{}, {} spp...\n", path, WIDTH, HEIGHT, spp);
-        const CPU_NUM: usize = 16;
-        const LINE: usize = HEIGHT / CPU_NUM;
-        let mut framebuffer = vec![Vector3::new(); WIDTH * HEIGHT];
-        let multi_bar = indicatif::MultiProgress::new();
-        let progress_style = ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-            .progress_chars("##-");
-
-        std::thread::scope(|scope| {
-            let multi_bar = &multi_bar;
-            let ray_tracer = &ray_tracer;
-            framebuffer
-                .chunks_mut(WIDTH * HEIGHT / CPU_NUM)
-                .enumerate()
-                .for_each(|(i, s)| {
-                    let pb = multi_bar.add(ProgressBar::new((s.len() * spp) as u64));
-                    pb.set_style(progress_style.clone());
-                    scope.spawn(move || {
-                        pb.set_message(format!("thread #{}", i + 1));
-                        for _ in 0..spp {
-                            let start = i * LINE;
-                            let pixel_iter = (start..start + LINE)
-                                .flat_map(move |a| (0..WIDTH).map(move |b| (a, b)));
-                            let mut count = 0;
-                            s.iter_mut().zip(pixel_iter).for_each(|(p, (y, x))| {
-                                let ray = ray_tracer.pixel_to_ray(x, y);
-                                *p += ray_tracer.shade(&ray, 0) / spp as f32;
-                                count += 1;
-                                if count % WIDTH == 0 {
-                                    pb.inc(WIDTH as u64);
-                                }
-                            })
-                        }
-                        pb.finish_with_message("done");
-                    });
-                });
-            multi_bar.join().unwrap();
+            .cloned()
+            .map(|mut model| {
+                let material = match model.material.as_deref() {
+                    Some(MaterialNew::Phong(m)) => MaterialNew::PBR(PBRMaterial::from(m)),
+                    Some(material) => material.clone(),
+                    None => MaterialNew::PBR(PBRMaterial::new(Vector3::from(0.8), 0.0, 0.8, 1.5)),
+                };
+                model.material = Some(Arc::new(material));
+                model
+            })
+            .collect::<Vec<_>>();
+        let mut ray_tracer = RayTracer::from_models(&models, width, height, spp);
+        ray_tracer.lights = scene.lights.clone();
+        if let Some(camera) = &scene.camera {
+            ray_tracer.camera = camera.clone();
+        }
+        ray_tracer
+    }
+
+    pub fn from_models(models: &[Model], width: usize, height: usize, spp: usize) -> Self {
+        let (objects, _) = triangulated_models_and_triangles(models, (width / 2) as f32);
+        let instances = models
+            .iter()
+            .enumerate()
+            .flat_map(|(i, model)| model.instances.iter().map(move |m| (i, m.clone())))
+            .collect::<Vec<_>>();
+        RayTracer::new(width, height, objects, &instances, spp)
+    }
+
+    /// Trace the whole image with `spp` samples per pixel, or adaptively, without opening a window.
+    pub fn render(&mut self) -> &Framebuffer {
+        if let Some(adaptive) = self.adaptive.clone() {
+            return self.render_adaptive(&adaptive);
+        }
+        let (width, height, spp) = (self.width, self.height, self.spp);
+
+        println!("Rendering {}x{}, {} spp...\n", width, height, spp);
+        let pb = self.tile_progress_bar();
+        pb.set_message("tiles");
+
+        let (sums, variance) = self.trace(|_, _| 0..spp, || pb.inc(1));
+        pb.finish_with_message("done");
+
+        self.variance = variance;
+        let colors = sums.iter().map(PixelSum::mean).collect::<Vec<_>>();
+        self.framebuffer = Framebuffer::from_rgb(width, height, &colors);
+        &self.framebuffer
+    }
+
+    // `min_spp` samples in every pixel first, then rounds of `batch` more in the noisy ones.
+    fn render_adaptive(&mut self, adaptive: &AdaptiveSampling) -> &Framebuffer {
+        let (width, height) = (self.width, self.height);
+        println!(
+            "Rendering {}x{}, {} to {} spp until {}% error...\n",
+            width,
+            height,
+            adaptive.min_spp,
+            adaptive.max_spp,
+            adaptive.target_error * 100.0
+        );
+        let start = Instant::now();
+        let budget = adaptive
+            .sample_budget
+            .map(|spp| (spp * (width * height) as f32) as usize);
+        let mut sums = vec![PixelSum::default(); width * height];
+        self.variance = vec![PixelVariance::default(); width * height];
+        let mut taken = 0;
+        let pb = self.tile_progress_bar();
+        pb.set_message("round 1");
+        for round in 0.. {
+            let ranges = self
+                .variance
+                .iter()
+                .map(|pixel| match pixel.count {
+                    0 => 0..adaptive.min_spp.min(adaptive.max_spp),
+                    count if adaptive.is_noisy(pixel) => {
+                        count..(count + adaptive.batch).min(adaptive.max_spp)
+                    }
+                    count => count..count,
+                })
+                .collect::<Vec<_>>();
+            let samples = ranges.iter().map(|r| r.len()).sum::<usize>();
+            let over_budget = budget.is_some_and(|budget| taken + samples > budget);
+            if samples == 0 || (round > 0 && over_budget) {
+                break;
+            }
+
+            pb.set_position(0);
+            let (round_sums, round_variance) =
+                self.trace(|x, y| ranges[y * width + x].clone(), || pb.inc(1));
+            for (sum, round_sum) in sums.iter_mut().zip(round_sums.iter()) {
+                sum.merge(round_sum);
+            }
+            for (pixel, round_pixel) in self.variance.iter_mut().zip(round_variance.iter()) {
+                pixel.merge(round_pixel);
+            }
+            taken += samples;
+            pb.set_message(format!(
+                "round {}, {:.1} spp on average",
+                round + 2,
+                taken as f32 / (width * height) as f32
+            ));
+            if adaptive
+                .time_budget
+                .is_some_and(|time| start.elapsed() >= time)
+            {
+                break;
+            }
+        }
+        pb.finish_with_message(format!(
+            "done, {:.1} spp on average",
+            taken as f32 / (width * height) as f32
+        ));
+
+        let colors = sums.iter().map(PixelSum::mean).collect::<Vec<_>>();
+        self.framebuffer = Framebuffer::from_rgb(width, height, &colors);
+        &self.framebuffer
+    }
+
+    // One tick per tile of a pass over the image.
+    fn tile_progress_bar(&self) -> ProgressBar {
+        let tiles = self.width.div_ceil(TILE_SIZE) * self.height.div_ceil(TILE_SIZE);
+        let pb = ProgressBar::new(tiles as u64);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+                .progress_chars("##-"),
+        );
+        pb
+    }
+
+    /// Samples taken in every pixel by the last render, see `heat`.
+    pub fn sample_heatmap(&self) -> Framebuffer {
+        let counts = self.variance.iter().map(|p| p.count);
+        let min = counts.clone().min().unwrap_or(0);
+        let max = counts.max().unwrap_or(0);
+        let colors = self
+            .variance
+            .iter()
+            .map(|p| heat(p.count, min, max))
+            .collect::<Vec<_>>();
+        Framebuffer::from_rgb(self.width, self.height, &colors)
+    }
+
+    /// Add one sample per pixel to the accumulation and show the running mean.
+    pub fn render_pass(&mut self) -> &Framebuffer {
+        let pass = self.passes;
+        let (sums, variance) = self.trace(|_, _| pass..pass + 1, || ());
+        for (sum, pass) in self.accumulation.iter_mut().zip(sums.iter()) {
+            sum.merge(pass);
+        }
+        for (pixel, pass) in self.variance.iter_mut().zip(variance.iter()) {
+            pixel.merge(pass);
+        }
+        self.passes += 1;
+        let colors = self
+            .accumulation
+            .iter()
+            .map(PixelSum::mean)
+            .collect::<Vec<_>>();
+        self.framebuffer = Framebuffer::from_rgb(self.width, self.height, &colors);
+        &self.framebuffer
+    }
+
+    /// Start the progressive passes over, after the camera or the scene changed.
+    pub fn reset_accumulation(&mut self) {
+        self.accumulation.fill(PixelSum::default());
+        self.variance.fill(PixelVariance::default());
+        self.passes = 0;
+    }
+
+    pub fn yaw_camera(&mut self, angle: f32) {
+        self.camera = self.camera.yaw(angle);
+        self.reset_accumulation();
+    }
+
+    pub fn pitch_camera(&mut self, angle: f32) {
+        self.camera = self.camera.pitch(angle);
+        self.reset_accumulation();
+    }
+
+    pub fn zoom_camera(&mut self, length: f32) {
+        self.camera = self.camera.zoom(length);
+        self.reset_accumulation();
+    }
+
+    // Sums and statistics of the `samples` of every pixel, row by row from the top. `on_tile`
+    // runs after each tile.
+    fn trace(
+        &self,
+        samples: impl Fn(usize, usize) -> Range<usize> + Sync,
+        on_tile: impl Fn() + Sync,
+    ) -> (Vec<PixelSum>, Vec<PixelVariance>) {
+        let tiles = Tile::grid(self.width, self.height, TILE_SIZE);
+        let tile_results = for_each_tile(&tiles, |tile| {
+            let result = self.render_tile(tile, &samples);
+            on_tile();
+            result
         });
 
-        ray_tracer.framebuffer = framebuffer;
+        // Tiles overlap by the filter radius, in the same order every time.
+        let mut sums = vec![PixelSum::default(); self.width * self.height];
+        let mut variance = vec![PixelVariance::default(); self.width * self.height];
+        for (tile, (tile_sums, tile_variance)) in tiles.iter().zip(tile_results) {
+            let region = self.splat_region(tile);
+            for (row, line) in tile_sums.chunks(region.width()).enumerate() {
+                let start = (region.y0 + row) * self.width + region.x0;
+                for (sum, tile_sum) in sums[start..start + line.len()].iter_mut().zip(line) {
+                    sum.merge(tile_sum);
+                }
+            }
+            for (row, line) in tile_variance.chunks(tile.width()).enumerate() {
+                let start = (tile.y0 + row) * self.width + tile.x0;
+                variance[start..start + line.len()].clone_from_slice(line);
+            }
+        }
+        (sums, variance)
+    }
 
-        let mut window = PBRWindow::new(WIDTH, HEIGHT);
-        window.run(ray_tracer)
+    /// Sums of the `samples(x, y)` of every pixel of a tile, jittered inside the pixel and
+    /// splatted through the filter. They cover `splat_region`, row by row from the top, with the
+    /// statistics of the samples of every pixel of the tile.
+    pub fn render_tile(
+        &self,
+        tile: &Tile,
+        samples: &impl Fn(usize, usize) -> Range<usize>,
+    ) -> (Vec<PixelSum>, Vec<PixelVariance>) {
+        let region = self.splat_region(tile);
+        let mut sums = vec![PixelSum::default(); region.width() * region.height()];
+        let mut variance = vec![PixelVariance::default(); tile.width() * tile.height()];
+        let mut sampler = self.sampler.clone_sampler();
+        let radius = self.filter.radius();
+        // Pixels with their center in (f - radius, f + radius].
+        let first =
+            |f: f32, min: usize| ((f - radius - 0.5).floor() + 1.0).max(min as f32) as usize;
+        let last = |f: f32, max: usize| ((f + radius - 0.5).floor() + 1.0).min(max as f32) as usize;
+        for (x, y) in (tile.y0..tile.y1).flat_map(|y| (tile.x0..tile.x1).map(move |x| (x, y))) {
+            let pixel = &mut variance[(y - tile.y0) * tile.width() + x - tile.x0];
+            for sample in samples(x, y) {
+                sampler.start_pixel_sample(x, y, sample);
+                let (jx, jy) = sampler.get_2d();
+                let (fx, fy) = (x as f32 + jx, y as f32 + jy);
+                let ray = self.sample_ray((fx, fy), sampler.as_mut());
+                let color = self.shade(&ray, sampler.as_mut());
+                pixel.add(&color);
+
+                for py in first(fy, region.y0)..last(fy, region.y1) {
+                    for px in first(fx, region.x0)..last(fx, region.x1) {
+                        let weight = self
+                            .filter
+                            .evaluate(px as f32 + 0.5 - fx, py as f32 + 0.5 - fy);
+                        if weight != 0.0 {
+                            let i = (py - region.y0) * region.width() + px - region.x0;
+                            sums[i].add(&color, weight);
+                        }
+                    }
+                }
+            }
+        }
+        (sums, variance)
+    }
+
+    /// Pixels the samples of `tile` can reach through the filter.
+    pub fn splat_region(&self, tile: &Tile) -> Tile {
+        let margin = (self.filter.radius() + 0.5).ceil() as usize;
+        Tile {
+            x0: tile.x0.saturating_sub(margin),
+            y0: tile.y0.saturating_sub(margin),
+            x1: (tile.x1 + margin).min(self.width),
+            y1: (tile.y1 + margin).min(self.height),
+            triangles: Vec::new(),
+        }
+    }
+
+    /// Write the last rendered image to `path`, the format follows the extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        self.framebuffer.save(path, &self.tone_mapping)
+    }
+
+    /// Trace progressively in a window, the camera can be moved meanwhile.
+    pub fn run(self) {
+        let mut window = PBRWindow::new(self.width, self.height);
+        window.run(self)
     }
 }
 
@@ -137,9 +503,24 @@ pub struct HitResult {
     pub distance: f32,
     pub emit: Option<Vector3>,
     pub material: Option<Arc<MaterialNew>>,
+    pub texture_coordinate: Option<(f32, f32)>,
+    // dP/du and dP/dv of the hit triangle, for normal mapping.
+    pub tangents: Option<(Vector3, Vector3)>,
 }
 
 impl HitResult {
+    /// The PBR material and shading normal at the hit point, with textures applied.
+    pub fn surface(&self) -> (PBRMaterial, Vector3) {
+        let material = self.pbr_material();
+        match self.texture_coordinate {
+            Some(uv) => (
+                material.textured(uv),
+                material.shading_normal(uv, &self.normal, self.tangents.as_ref()),
+            ),
+            None => (material.clone(), self.normal.clone()),
+        }
+    }
+
     pub fn material_eval(
         &self,
         wi: &Vector3,
@@ -156,200 +537,251 @@ impl HitResult {
             _ => panic!("Only accept PBRMaterial"),
         }
     }
+
+    // `triangle` is in the space of the mesh of `instance`.
+    fn from_instance(
+        ray: &Ray,
+        instance: &Instance,
+        triangle: &Triangle,
+        barycenter: (f32, f32, f32),
+    ) -> Option<Self> {
+        let position = Vector3::from(&interpolate_triangle!(triangle, position; barycenter));
+        let position = instance.point_to_world(&position);
+        let distance = (&position - &ray.origin).norm();
+        if distance <= ELISION {
+            return None;
+        }
+        let normal = Vector3::from(&interpolate!(triangle, normal; barycenter));
+        let normal = instance.normal_to_world(&normal);
+        let material = triangle.material.clone();
+        let textured = material
+            .as_ref()
+            .and_then(|m| m.pbr_material())
+            .is_some_and(|m| !m.textures.is_empty());
+        let (texture_coordinate, tangents) = if textured {
+            (
+                triangle.vertexs[0]
+                    .texture_coordinate
+                    .map(|_| interpolate_uv!(triangle, texture_coordinate; barycenter)),
+                triangle.tangents().map(|(dpdu, dpdv)| {
+                    (
+                        instance.vector_to_world(&dpdu),
+                        instance.vector_to_world(&dpdv),
+                    )
+                }),
+            )
+        } else {
+            (None, None)
+        };
+        Some(HitResult {
+            position,
+            normal,
+            distance,
+            emit: material.as_ref().and_then(|m| {
+                m.emissive_material()
+                    .and_then(|e| Some(&e.base_color * e.intensity))
+            }),
+            material,
+            texture_coordinate,
+            tangents,
+        })
+    }
 }
 
 impl RayTracer {
-    pub fn shade(&self, ray: &Ray, depth: usize) -> Vector3 {
+    /// Radiance arriving along `ray`, every random decision of the path comes from `sampler`.
+    pub fn shade(&self, ray: &Ray, sampler: &mut dyn Sampler) -> Vector3 {
+        self.shade_path(ray, None, sampler)
+    }
+
+    // `bsdf_pdf` is the solid angle pdf `ray` was sampled with, none for camera rays and smooth
+    // bounces, which light sampling can't find.
+    fn shade_path(&self, ray: &Ray, bsdf_pdf: Option<f32>, sampler: &mut dyn Sampler) -> Vector3 {
         let intersection = self.get_nearest_intersection(ray);
 
         if let Some(intersection) = intersection {
-            if intersection.emit.is_some() {
-                if depth > 0 {
-                    return vector3([0.0, 0.0, 0.0]);
-                }
-                return vector3([1.0, 1.0, 1.0]);
+            if let Some(emit) = &intersection.emit {
+                // The previous bounce sampled this light directly as well.
+                let weight = bsdf_pdf.map_or(1.0, |pdf| {
+                    power_heuristic(pdf, self.light_pdf(ray, &intersection))
+                });
+                return emit * weight;
             }
 
             let wo = -&ray.dir;
             let p = &intersection.position;
-            let n = &intersection.normal;
+            let (material, n) = intersection.surface();
+            let n = &n;
+            // Every bounce takes the same dimensions whatever happens, so they line up across samples.
+            let (light_u, light_u2) = (sampler.get_1d(), sampler.get_2d());
+            let (bsdf_u, bsdf_u2) = (sampler.get_1d(), sampler.get_2d());
+            let ksi = sampler.get_1d();
 
             // Direct light
             let mut l_dir = Vector3::new();
-            if let Some((inter, pdf_light)) = self.sample_light() {
+            if let Some((inter, pdf_area)) = self.sample_light(light_u, light_u2) {
                 let x = &inter.position;
                 let light_n = &inter.normal;
                 let object_to_light_dir = &(x - p).normalized();
                 let light_to_object_dir = &(-object_to_light_dir);
 
-                let rray = Ray::new(p, &object_to_light_dir);
+                let distance = (x - p).norm();
 
-                if let Some(nearest_inter) = self.get_nearest_intersection(&rray) {
-                    // Light not be blocked
-                    if (&nearest_inter.position - x).norm() < ELISION {
-                        let cos_theta0 = object_to_light_dir.dot(&n);
-                        let cos_theta1 = light_to_object_dir.dot(&light_n);
-                        let material = intersection.pbr_material();
+                // Light not be blocked
+                if !self.occluded(p, object_to_light_dir, distance - ELISION) {
+                    let cos_theta0 = object_to_light_dir.dot(&n);
+                    let cos_theta1 = light_to_object_dir.dot(&light_n).abs();
+                    if let (Some(li), true) = (inter.emit, cos_theta1 > 0.0) {
                         let fr =
                             material.eval(&object_to_light_dir, &wo, n, IlluminateType::Direct);
-                        if let Some(li) = inter.emit {
-                            let pdf_light_sample = if cos_theta1 == 0.0 {
-                                0.0
-                            } else {
-                                nearest_inter.distance.powi(2) / pdf_light / cos_theta1.abs()
-                            };
-
-                            let pdf_bsdf = material.pdf(object_to_light_dir, &wo, n);
-                            let pdf = pdf_light_sample + pdf_bsdf;
-                            l_dir = li.cwise_product(&fr) * cos_theta0.abs() / pdf
-                        }
+                        let pdf_light = distance.powi(2) / cos_theta1 * pdf_area;
+                        let pdf_bsdf = material.pdf(object_to_light_dir, &wo, n);
+                        let weight = power_heuristic(pdf_light, pdf_bsdf);
+                        l_dir = li.cwise_product(&fr) * (cos_theta0.abs() * weight / pdf_light);
                     }
                 }
             }
+            l_dir += self.sample_punctual_lights(p, n, &wo, &material);
+
             // Indirect lighr
             let mut l_indir = Vector3::new();
             const P_RR: f32 = 0.9;
-            let ksi = rand::thread_rng().gen_range(0.0..=1.0f32);
             if ksi < P_RR {
-                let m = intersection.pbr_material();
-                let wi = m.sample(&wo, n);
-                let ray = Ray::new(p, &wi);
-                let fr = m.eval(&wi, &wo, n, IlluminateType::IBL);
-                let cos_theta = wi.dot(&n).abs();
-                let pdf_bsdf = m.pdf(&wi, &wo, n);
-                let pdf_bsdf_denom = if pdf_bsdf == 0.0 { 0.0 } else { 1.0 / pdf_bsdf };
-                let pdf_bsdf_denom = pdf_bsdf_denom / P_RR;
-                if pdf_bsdf > 0.0 {
-                    let fr = fr * cos_theta * pdf_bsdf_denom;
+                // Glass also sends rays through the surface, or back inside.
+                if let Some(sample) = material.sample(&wo, n, bsdf_u, bsdf_u2) {
+                    let ray = Ray::new(p, &sample.wi);
+                    let pdf = (!sample.delta).then_some(sample.pdf);
                     l_indir = self
-                        .shade(&ray, depth + 1)
-                        .clamp_max(10.0)
-                        .cwise_product(&fr);
+                        .shade_path(&ray, pdf, sampler)
+                        .cwise_product(&(sample.weight / P_RR));
                 }
             }
-            let r = l_dir + l_indir;
-            return r;
+            // Emissive PBR surfaces aren't in `emitters`, light sampling never picks them, so
+            // hitting them is the only estimate of their light and takes the full weight.
+            l_dir + l_indir + material.emission
+        } else {
+            self.background_color.clone()
         }
-        return self.background_color.clone();
     }
 
-    fn sample_light(&self) -> Option<(HitResult, f32)> {
-        let emit_area = self.objects.iter().fold(0.0f32, |acc, model| {
-            acc + if model.has_emit() { model.area() } else { 0.0 }
-        });
-        let p = rand::thread_rng().gen_range(0.0f32..=1.0) * emit_area;
-        let mut emit_area = 0.0;
-        let mut sample_result = None;
-        for model in self.objects.iter() {
-            if model.has_emit() {
-                emit_area += model.area();
-                if p <= emit_area {
-                    sample_result = Some(model.sample());
-                    break;
-                }
+    /// Direct light of every punctual light at `p`, they can't be hit by rays.
+    pub fn sample_punctual_lights(
+        &self,
+        p: &Vector3,
+        n: &Vector3,
+        wo: &Vector3,
+        material: &PBRMaterial,
+    ) -> Vector3 {
+        let mut l_dir = Vector3::new();
+        for light in self.lights.iter() {
+            let (l, distance, radiance) = light.incident(p);
+            // Transmissive surfaces are lit from behind as well.
+            let cos_theta = l.dot(n);
+            if (cos_theta <= 0.0 && material.transmission == 0.0) || radiance.norm() == 0.0 {
+                continue;
+            }
+            if !self.occluded(p, &l, distance - ELISION) {
+                let fr = material.eval(&l, wo, n, IlluminateType::Direct);
+                l_dir += fr.cwise_product(&radiance) * cos_theta.abs();
             }
         }
-        sample_result
+        l_dir
     }
 
-    fn get_nearest_intersection(&self, ray: &Ray) -> Option<HitResult> {
-        self._get_nearest_intersection(ray, &self.objects_tree.root)
-        // self.slow_get_nearest_intersection(ray)
+    fn emit_area(&self) -> f32 {
+        self.emitters.last().map_or(0.0, |emitter| emitter.area)
     }
 
-    fn _slow_get_nearest_intersection(&self, ray: &Ray) -> Option<HitResult> {
-        self.objects
-            .iter()
-            .flat_map(|t| t.triangles.iter())
-            .map(|triangle| {
-                ray.intersect_triangle(triangle).and_then(|barycenter| {
-                    let position =
-                        Vector3::from(&interpolate_triangle!(triangle, position; barycenter));
-                    let normal =
-                        Vector3::from(&interpolate!(triangle, normal; barycenter)).normalized();
-                    let distance = (&position - &ray.origin).norm();
-                    if distance > ELISION {
-                        let material = triangle.material.clone();
-                        return Some(HitResult {
-                            position,
-                            normal,
-                            distance,
-                            emit: material.as_ref().and_then(|m| {
-                                m.emissive_material()
-                                    .and_then(|e| Some(&e.base_color * e.intensity))
-                            }),
-                            material,
-                        });
-                    }
-                    None
-                })
-            })
-            .fold(None, |acc, x| nearer_option_hitresult(acc, x))
+    /// A point spread uniformly over every emissive object, with its pdf per unit area. `u`
+    /// picks the object and its triangle by area and `u2` the point on it.
+    fn sample_light(&self, u: f32, u2: (f32, f32)) -> Option<(HitResult, f32)> {
+        let emit_area = self.emit_area();
+        let p = u * emit_area;
+        let k = self
+            .emitters
+            .partition_point(|emitter| emitter.area < p)
+            .min(self.emitters.len().checked_sub(1)?);
+        let emitter = &self.emitters[k];
+        // What is left of `p` picks the triangle.
+        let p = p - if k == 0 {
+            0.0
+        } else {
+            self.emitters[k - 1].area
+        };
+        let j = emitter
+            .triangle_areas
+            .partition_point(|&area| area < p)
+            .min(emitter.triangle_areas.len() - 1);
+
+        let instance = &self.objects_tree.instances.primitives[emitter.instance];
+        let triangle = &self.objects[instance.mesh].triangles[j];
+        let (position, normal) = triangle.sample_position(u2);
+        let material = triangle.material.clone();
+        let hit = HitResult {
+            position: instance.point_to_world(&position),
+            normal: instance.normal_to_world(&normal),
+            distance: 0.0,
+            emit: material
+                .as_ref()
+                .and_then(|m| m.emissive_material())
+                .map(|e| &e.base_color * e.intensity),
+            material,
+            texture_coordinate: None,
+            tangents: None,
+        };
+        Some((hit, 1.0 / emit_area))
     }
 
-    fn _get_nearest_intersection(&self, ray: &Ray, node: &BVHNode) -> Option<HitResult> {
-        if !node.bounding_box.intersect_ray(ray) {
-            return None;
+    /// Solid angle pdf of `sample_light` picking the emissive point `hit` seen along `ray`.
+    fn light_pdf(&self, ray: &Ray, hit: &HitResult) -> f32 {
+        let cos_theta = ray.dir.dot(&hit.normal).abs();
+        if cos_theta == 0.0 {
+            return 0.0;
         }
+        hit.distance.powi(2) / cos_theta / self.emit_area()
+    }
 
-        // leaf
-        if node.l.is_none() && node.r.is_none() {
-            let nearest_result = node.data.as_ref().and_then(|triangles| {
-                let nearest_result: Option<HitResult> = triangles
-                    .iter()
-                    .map(|triangle| {
-                        ray.intersect_triangle(triangle).and_then(|barycenter| {
-                            let position = Vector3::from(
-                                &interpolate_triangle!(triangle, position; barycenter),
-                            );
-                            let normal = Vector3::from(&interpolate!(triangle, normal; barycenter))
-                                .normalized();
-                            let distance = (&position - &ray.origin).norm();
-                            if distance > ELISION {
-                                let material = triangle.material.clone();
-                                return Some(HitResult {
-                                    position,
-                                    normal,
-                                    distance,
-                                    emit: material.as_ref().and_then(|m| {
-                                        m.emissive_material()
-                                            .and_then(|e| Some(&e.base_color * e.intensity))
-                                    }),
-                                    material,
-                                });
-                            } else {
-                                None
-                            }
-                        })
-                    })
-                    .fold(None, |acc, x| nearer_option_hitresult(acc, x));
-                nearest_result
-            });
-            return nearest_result;
-        }
+    pub(crate) fn get_nearest_intersection(&self, ray: &Ray) -> Option<HitResult> {
+        let ((i, j), _, barycenter) = self.objects_tree.nearest_hit(ray, ELISION, f32::INFINITY)?;
+        let instance = &self.objects_tree.instances.primitives[i];
+        let triangle = &self.objects_tree.meshes[instance.mesh].primitives[j];
+        HitResult::from_instance(ray, instance, triangle, barycenter)
+    }
 
-        let left = node
-            .l
-            .as_ref()
-            .and_then(|node| self._get_nearest_intersection(ray, node.as_ref()));
-        let right = node
-            .r
-            .as_ref()
-            .and_then(|node| self._get_nearest_intersection(ray, node.as_ref()));
+    /// Whether anything blocks the way from `p` along `dir` before `distance`.
+    pub(crate) fn occluded(&self, p: &Vector3, dir: &Vector3, distance: f32) -> bool {
+        self.objects_tree
+            .any_hit(&Ray::new(p, dir), ELISION, distance)
+    }
 
-        nearer_option_hitresult(left, right)
+    // Every triangle without the tree, to check it.
+    pub(crate) fn _slow_get_nearest_intersection(&self, ray: &Ray) -> Option<HitResult> {
+        self.objects_tree
+            .instances
+            .primitives
+            .iter()
+            .flat_map(|instance| {
+                let local_ray = instance.to_mesh(ray);
+                self.objects[instance.mesh]
+                    .triangles
+                    .iter()
+                    .filter_map(move |triangle| {
+                        local_ray
+                            .intersect_triangle(triangle)
+                            .and_then(|barycenter| {
+                                HitResult::from_instance(ray, instance, triangle, barycenter)
+                            })
+                    })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
     }
 }
 
-fn nearer_option_hitresult(r1: Option<HitResult>, r2: Option<HitResult>) -> Option<HitResult> {
-    match (&r1, &r2) {
-        (Some(h1), Some(h2)) => {
-            if h1.distance < h2.distance {
-                r1
-            } else {
-                r2
-            }
-        }
-        _ => r1.or(r2),
+/// Power heuristic weight (beta = 2) of a sample drawn with `pdf` against another strategy.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
     }
+    a / (a + b)
 }
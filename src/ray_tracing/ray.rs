@@ -15,6 +15,15 @@ impl Ray {
     }
 
     pub fn intersect_triangle(&self, triangle: &Triangle) -> Option<(f32, f32, f32)> {
+        self.intersect_triangle_distance(triangle)
+            .map(|(_, barycenter)| barycenter)
+    }
+
+    /// Distance along the ray to the triangle and the barycenter of the hit.
+    pub fn intersect_triangle_distance(
+        &self,
+        triangle: &Triangle,
+    ) -> Option<(f32, (f32, f32, f32))> {
         // Moller-Trumbore
         let p0 = Vector3::from(&triangle.vertexs[0].position);
         let p1 = Vector3::from(&triangle.vertexs[1].position);
@@ -32,7 +41,7 @@ impl Ray {
         let b1 = s1.dot(&s) / s1e1;
         let b2 = s2.dot(&self.dir) / s1e1;
         if t >= 0.0 && b1 >= 0.0 && b2 >= 0.0 && (1.0 - b1 - b2) >= 0.0 {
-            Some((1.0 - b1 - b2, b1, b2))
+            Some((t, (1.0 - b1 - b2, b1, b2)))
         } else {
             None
         }
@@ -0,0 +1,332 @@
+/// Random numbers of one pixel sample, handed out dimension after dimension.
+///
+/// The numbers only depend on the seed, the pixel, the sample index and the dimension, so the
+/// same seed traces the same image whatever thread gets the pixel.
+pub trait Sampler: Send + Sync {
+    /// Start over at the first dimension of the `sample`-th sample of pixel (x, y).
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample: usize);
+    /// Next number in [0, 1).
+    fn get_1d(&mut self) -> f32;
+    /// Next two numbers in [0, 1), stratified together.
+    fn get_2d(&mut self) -> (f32, f32);
+    fn clone_sampler(&self) -> Box<dyn Sampler>;
+}
+
+pub const SAMPLER_NAMES: [&str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+/// `spp` is the sample count stratified samplers spread their strata over.
+pub fn make_sampler(name: &str, spp: usize, seed: u64) -> Option<Box<dyn Sampler>> {
+    match name.to_lowercase().as_ref() {
+        "independent" => Some(Box::new(IndependentSampler::new(seed))),
+        "stratified" => Some(Box::new(StratifiedSampler::new(spp, seed))),
+        "halton" => Some(Box::new(HaltonSampler::new(seed))),
+        "sobol" => Some(Box::new(SobolSampler::new(seed))),
+        _ => None,
+    }
+}
+
+// Where a sampler is in the sequence of its pixel.
+#[derive(Debug, Clone, Default)]
+struct PixelSample {
+    seed: u64,
+    pixel: u64,
+    sample: u64,
+    dimension: u64,
+}
+
+impl PixelSample {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    fn start(&mut self, x: usize, y: usize, sample: usize) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.sample = sample as u64;
+        self.dimension = 0;
+    }
+
+    // Hash of the pixel and the next dimension, the same for every sample of the pixel.
+    fn next_dimension(&mut self) -> u64 {
+        let h = hash(&[self.pixel, self.dimension]);
+        self.dimension += 1;
+        h
+    }
+}
+
+/// Uniform random numbers without any stratification.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    state: PixelSample,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: PixelSample::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample: usize) {
+        self.state.start(x, y, sample);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let h = self.state.next_dimension();
+        to_unit(hash(&[h, self.state.sample]) as u32)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Jittered strata of `spp` samples, visited in a different order in every dimension.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    spp: usize,
+    state: PixelSample,
+}
+
+impl StratifiedSampler {
+    pub fn new(spp: usize, seed: u64) -> Self {
+        Self {
+            spp: spp.max(1),
+            state: PixelSample::new(seed),
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample: usize) {
+        self.state.start(x, y, sample);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let h = self.state.next_dimension();
+        let n = self.spp as u32;
+        let stratum = permutation_element(self.state.sample as u32 % n, n, h as u32);
+        let jitter = to_unit(hash(&[h, self.state.sample]) as u32);
+        (stratum as f32 + jitter) / n as f32
+    }
+
+    // A grid of at least `spp` cells.
+    fn get_2d(&mut self) -> (f32, f32) {
+        let h = self.state.next_dimension();
+        let columns = (self.spp as f32).sqrt().ceil() as u32;
+        let rows = (self.spp as u32).div_ceil(columns);
+        let cells = columns * rows;
+        let stratum = permutation_element(self.state.sample as u32 % cells, cells, h as u32);
+        let jitter = hash(&[h, self.state.sample]);
+        let (jx, jy) = (to_unit(jitter as u32), to_unit((jitter >> 32) as u32));
+        (
+            ((stratum % columns) as f32 + jx) / columns as f32,
+            ((stratum / columns) as f32 + jy) / rows as f32,
+        )
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence with Owen-scrambled digits, scrambled differently in every pixel.
+/// Dimensions past the last prime base are independent.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    state: PixelSample,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: PixelSample::new(seed),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample: usize) {
+        self.state.start(x, y, sample);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.dimension as usize;
+        let h = self.state.next_dimension();
+        match PRIMES.get(dimension) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.state.sample, h),
+            None => to_unit(hash(&[h, self.state.sample]) as u32),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// The first two Sobol dimensions with hash-based Owen scrambling, padded to more dimensions by
+/// shuffling the sample order of every one (Burley 2020).
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    state: PixelSample,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: PixelSample::new(seed),
+        }
+    }
+
+    // Owen-scrambled index of the sample in the shuffled sequence of this dimension.
+    fn next_index(&mut self) -> (u32, u64) {
+        let h = self.state.next_dimension();
+        let index = nested_uniform_scramble(self.state.sample as u32, h as u32);
+        (index, h)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample: usize) {
+        self.state.start(x, y, sample);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (index, h) = self.next_index();
+        to_unit(nested_uniform_scramble(sobol_0(index), (h >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (index, h) = self.next_index();
+        let h2 = hash(&[h]);
+        (
+            to_unit(nested_uniform_scramble(sobol_0(index), (h >> 32) as u32)),
+            to_unit(nested_uniform_scramble(sobol_1(index), h2 as u32)),
+        )
+    }
+
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// The 24 high bits as a number in [0, 1), every one of them is exact in an f32.
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Finalizer of MurmurHash3, every input bit affects every output bit.
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ mix_bits(v.wrapping_add(0x9e3779b97f4a7c15)))
+    })
+}
+
+// The `i`-th element of a random permutation of `0..n` (Kensler 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+// Digits of `a` in `base` mirrored around the point, each one permuted by a hash of the
+// digits before it.
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, seed: u64) -> f32 {
+    // Enough digits for the precision of an f32.
+    let digits = (24.0 / (base as f32).log2()).ceil() as usize + 1;
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0f64;
+    let mut reversed = 0u64;
+    for _ in 0..digits {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let digit_hash = mix_bits(seed ^ reversed) as u32;
+        let digit = permutation_element(digit, base as u32, digit_hash) as u64;
+        reversed = reversed * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    ((reversed as f64 * inv_base_m) as f32).min(1.0 - f32::EPSILON / 2.0)
+}
+
+// Van der Corput sequence in base 2.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+// Second Sobol dimension, every direction number of the polynomial x + 1 is v ^ (v >> 1).
+fn sobol_1(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut x = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    x
+}
+
+// Owen scrambling of the bits of `x` from the highest one down (Laine and Karras 2011).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
@@ -1,21 +1,26 @@
-use std::sync::Arc;
+use image::ImageResult;
+use std::{path::Path, sync::Arc};
 
+use crate::scene::Scene;
+use crate::window::FramebufferWindow;
 use crate::{
     algebra::{
         matrix_new::Matrix4,
         vector_new::{vector3, Vector3, Vector4},
     },
     pipeline::{
-        camera::Camera,
+        camera::{rotate_around_axis, Camera, Projection},
         fragment_shader::{make_shader, FragmentShader},
+        framebuffer::{Framebuffer, ToneMapping},
         light::Light,
         material::MaterialNew,
         model::{Model, Triangle, TriangulatedModel, Vertex},
-        rasterizer::Rasterizer,
+        rasterizer::{Multisample, Rasterizer},
+        shadow_map::{ShadowMap, ShadowMapSettings},
         transformation::Transformation,
+        vertex_shader::{DefaultVertexShader, VertexShader, VertexShaderPayload},
     },
 };
-use crate::{window::FramebufferWindow, Color};
 
 #[allow(dead_code)]
 pub struct Renderer {
@@ -24,8 +29,14 @@ pub struct Renderer {
     pub window: Option<FramebufferWindow>,
     pub width: usize,
     pub shader: Option<Box<dyn FragmentShader>>,
+    pub vertex_shader: Option<Box<dyn VertexShader>>,
     pub height: usize,
     pub light: Option<Light>,
+    // Lights besides the key `light`, they cast no shadows.
+    pub fill_lights: Vec<Light>,
+    pub shadow: Option<ShadowMapSettings>,
+    pub multisample: Multisample,
+    pub tone_mapping: ToneMapping,
 }
 
 #[allow(dead_code)]
@@ -36,7 +47,12 @@ impl Renderer {
             camera: None,
             window: None,
             shader: None,
+            vertex_shader: Some(Box::new(DefaultVertexShader)),
             light: None,
+            fill_lights: Vec::new(),
+            shadow: None,
+            multisample: Multisample::X1,
+            tone_mapping: ToneMapping::default(),
             width,
             height,
         }
@@ -47,9 +63,62 @@ impl Renderer {
         self
     }
 
-    pub fn shader(mut self, name: &str, path: &str) -> Self {
-        let shader = make_shader(name, path, &self);
+    pub fn shader(mut self, name: &str, texture_path: &str) -> Result<Self, String> {
+        let shader = make_shader(name, texture_path, &self)?;
         self.shader = shader;
+        self.update_shadow_map();
+        Ok(self)
+    }
+
+    pub fn shadow(mut self, settings: ShadowMapSettings) -> Self {
+        self.shadow = Some(settings);
+        self.update_shadow_map();
+        self
+    }
+
+    pub fn vertex_shader(mut self, shader: Box<dyn VertexShader>) -> Self {
+        self.vertex_shader = Some(shader);
+        self
+    }
+
+    pub fn multisample(mut self, multisample: Multisample) -> Self {
+        self.multisample = multisample;
+        self
+    }
+
+    pub fn tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    pub fn fill_lights(mut self, lights: Vec<Light>) -> Self {
+        self.fill_lights = lights;
+        self
+    }
+
+    /// Draw a scene with its camera and lights, the first light is the shadowed key light.
+    /// The Phong shaders approximate PBR materials.
+    /// Set the shader afterwards since it copies the camera and lights.
+    pub fn scene(mut self, scene: &Scene) -> Self {
+        self.models = Some(scene.models.clone());
+        if let Some(camera) = &scene.camera {
+            self.camera = Some(camera.clone());
+        }
+        if let Some((light, fill_lights)) = scene.lights.split_first() {
+            self.light = Some(light.clone());
+            self.fill_lights = fill_lights.to_vec();
+        }
         self
     }
 
@@ -64,7 +133,18 @@ impl Renderer {
         window.run(self);
     }
 
+    /// Render headlessly at the renderer's size and write the result to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        self.render_framebuffer(self.width, self.height)
+            .save(path, &self.tone_mapping)
+    }
+
     pub fn render(&self, width: usize, height: usize) -> Vec<u32> {
+        self.render_framebuffer(width, height)
+            .to_bitmap(&self.tone_mapping)
+    }
+
+    pub fn render_framebuffer(&self, width: usize, height: usize) -> Framebuffer {
         let models = self
             .models
             .as_ref()
@@ -77,41 +157,27 @@ impl Renderer {
             .shader
             .as_ref()
             .unwrap_or_else(|| panic!("No fragment shader found."));
+        let vertex_shader = self
+            .vertex_shader
+            .as_ref()
+            .unwrap_or_else(|| panic!("No vertex shader found."));
 
         //Transformation
-        let models = triangulated_models(models, camera, width, height);
+        let models = triangulated_models(models, camera, vertex_shader.as_ref(), width, height);
 
         //Rasterization && Shading
-        let mut rasterizer = Rasterizer::new(width, height, models);
-        let frame_buffer = rasterizer.rasterize(fragment_shader);
-
-        //Generate Bitmap
-        let bitmap = bitmap_from_framebuffer(&frame_buffer, width, height);
-        bitmap
+        let mut rasterizer = Rasterizer::with_multisample(width, height, models, self.multisample);
+        rasterizer.rasterize(fragment_shader)
     }
 
     pub fn yaw_camera(&mut self, angle: f32) {
-        let camera = self.camera.as_ref().unwrap();
-        let e = &camera.eye_position;
-        let p = Matrix4::rotate_around_y_matrix(angle) * Vector4::point_from(e);
-        let p = Vector3::from(&p);
-
-        let y_axis = vector3([0.0, 1.0, 0.0]);
-        let g = rotate_around_axis(&camera.gaze_direct, &y_axis, angle);
-        let u = rotate_around_axis(&camera.up_direct, &y_axis, angle);
-
-        let new_camera = camera.clone().eye_position(p).gaze_direct(g).up_direct(u);
+        let new_camera = self.camera.as_ref().unwrap().yaw(angle);
         self.shader.as_mut().unwrap().update_camera(&new_camera);
         self.camera = Some(new_camera);
     }
 
     pub fn pitch_camera(&mut self, angle: f32) {
-        let camera = self.camera.as_ref().unwrap();
-        let axis = camera.up_direct.cross(&camera.gaze_direct).normalized();
-        let e = rotate_around_axis(&camera.eye_position, &axis, angle);
-        let g = rotate_around_axis(&camera.gaze_direct, &axis, angle);
-        let u = rotate_around_axis(&camera.up_direct, &axis, angle);
-        let new_camera = camera.clone().eye_position(e).gaze_direct(g).up_direct(u);
+        let new_camera = self.camera.as_ref().unwrap().pitch(angle);
         self.shader.as_mut().unwrap().update_camera(&new_camera);
         self.camera = Some(new_camera);
     }
@@ -121,6 +187,7 @@ impl Renderer {
         let axis = vector3([0.0, 1.0, 0.0]);
         light.position = rotate_around_axis(&light.position, &axis, angle);
         self.shader.as_mut().unwrap().update_light(light);
+        self.update_shadow_map();
     }
 
     pub fn pitch_light(&mut self, angle: f32) {
@@ -129,17 +196,27 @@ impl Renderer {
         let axis = vector3([p.z(), 0.0, -p.x()]).normalized();
         light.position = rotate_around_axis(&light.position, &axis, angle);
         self.shader.as_mut().unwrap().update_light(light);
+        self.update_shadow_map();
     }
 
     pub fn zoom_camera(&mut self, length: f32) {
-        let camera = self.camera.as_ref().unwrap();
-        let g = Vector4::vector_from(&camera.gaze_direct);
-        let p: Vector4 = Vector4::point_from(&camera.eye_position) + (g * length);
-        let p = Vector3::from(&p);
-        let new_camera = camera.clone().eye_position(p);
+        let new_camera = self.camera.as_ref().unwrap().zoom(length);
         self.shader.as_mut().unwrap().update_camera(&new_camera);
         self.camera = Some(new_camera);
     }
+
+    // Render the depth of the scene from the light again and hand it to the shader.
+    fn update_shadow_map(&mut self) {
+        if let (Some(models), Some(light), Some(shader)) =
+            (&self.models, &self.light, &mut self.shader)
+        {
+            let shadow_map = self
+                .shadow
+                .as_ref()
+                .map(|settings| Arc::new(ShadowMap::new(models, light, settings)));
+            shader.update_shadow_map(shadow_map);
+        }
+    }
 }
 
 impl Default for Renderer {
@@ -149,7 +226,12 @@ impl Default for Renderer {
             camera: Some(Camera::default()),
             window: None,
             shader: None,
+            vertex_shader: Some(Box::new(DefaultVertexShader)),
             light: Some(Light::default()),
+            fill_lights: Vec::new(),
+            shadow: None,
+            multisample: Multisample::X1,
+            tone_mapping: ToneMapping::default(),
             width: 800,
             height: 800,
         }
@@ -158,27 +240,16 @@ impl Default for Renderer {
 
 /** Some functions **/
 
-fn rotate_around_axis(v: &Vector3, axis: &Vector3, angle: f32) -> Vector3 {
-    v * angle.cos() + axis.cross(v) * angle.sin() + axis * axis.dot(v) * (1.0 - angle.cos())
-}
-
 pub fn triangulated_models_and_triangles(
     models: &[Model],
     _scale: f32,
 ) -> (Vec<TriangulatedModel>, Vec<Triangle>) {
-    let triangles_len = models.iter().map(|m| m.vertexs.len() / 3).sum();
+    let triangles_len = models.iter().map(|m| m.indices.len()).sum();
     let mut result_triangles = Vec::<Triangle>::with_capacity(triangles_len);
 
+    // Meshes stay in their own space, the ray tracer places every instance of them.
     let models = models
         .iter()
-        .map(|model| model.clone())
-        .map(|mut model| {
-            model.vertexs.iter_mut().for_each(|_v| {
-                // let scale = Matrix4::scale_matrix(scale, scale, scale);
-                // v.position = &scale * &v.position;
-            });
-            model
-        })
         .map(|model| {
             let triangles = model
                 .indices
@@ -198,7 +269,7 @@ pub fn triangulated_models_and_triangles(
             result_triangles.append(&mut triangles.clone());
             TriangulatedModel {
                 triangles,
-                material: model.material,
+                material: model.material.clone(),
                 area,
             }
         })
@@ -206,46 +277,76 @@ pub fn triangulated_models_and_triangles(
     (models, result_triangles)
 }
 
-fn triangulated_models(
+pub fn triangulated_models(
     models: &[Model],
     camera: &Camera,
+    vertex_shader: &dyn VertexShader,
     width: usize,
     height: usize,
 ) -> Vec<TriangulatedModel> {
     models
         .iter()
-        .map(|model| {
-            let model = model.clone();
-            mvp_viewport_transform(model, camera, width, height)
-        })
-        .map(|mut model| {
-            homogeneous_division(&mut model.vertexs);
-            model
-        })
-        .map(|model| TriangulatedModel {
-            triangles: primitive_assembly(model.vertexs, &model.material),
-            material: model.material,
-            area: 0.0,
+        .flat_map(|model| model.instances.iter().map(move |matrix| (model, matrix)))
+        .map(|(model, matrix)| {
+            let mut vertexs =
+                mvp_viewport_transform(model, matrix, camera, vertex_shader, width, height);
+            homogeneous_division(&mut vertexs);
+            TriangulatedModel {
+                triangles: primitive_assembly(vertexs, &model.material),
+                material: model.material.clone(),
+                area: 0.0,
+            }
         })
         .collect::<Vec<_>>()
 }
 
-fn mvp_viewport_transform(mut model: Model, camera: &Camera, width: usize, height: usize) -> Model {
-    // No modeling transformation for now
+// The vertices of one instance of `model`, placed by `matrix`, in screen space.
+fn mvp_viewport_transform(
+    model: &Model,
+    matrix: &Matrix4,
+    camera: &Camera,
+    vertex_shader: &dyn VertexShader,
+    width: usize,
+    height: usize,
+) -> Vec<Vertex> {
     let view = Transformation::view_matrix(camera);
-    let projection = Transformation::perspective_projection_transform(camera);
+    let projection = Transformation::projection_transform(camera);
     let viewport = Transformation::viewport_transform(width as f32, height as f32);
+    let normal_matrix = matrix.inverse_matrix().transpose();
 
-    transform_models_vertexs(&mut model.vertexs, &view);
-    transform_models_vertexs(&mut model.vertexs, &projection);
+    let vertexs = model
+        .vertexs
+        .iter()
+        .map(|v| {
+            let output = vertex_shader.shade(&VertexShaderPayload {
+                vertex: v,
+                model: matrix,
+                view: &view,
+                projection: &projection,
+            });
+            Vertex {
+                position: output.position,
+                world_position: matrix * &v.position,
+                normal: v.normal.as_ref().map(|n| {
+                    let n = Vector3::from(&(&normal_matrix * n)).normalized();
+                    Vector4::vector_from(&n)
+                }),
+                varyings: output.varyings,
+                ..v.clone()
+            }
+        })
+        .collect::<Vec<_>>();
 
     // let mut vertexs = homogeneous_clip(model, camera);
-    let vertexs = complete_homogeneous_clip(&model);
-    let mut vertexs = back_face_cull(vertexs);
+    let vertexs = complete_homogeneous_clip(&model.indices, &vertexs);
+    // The culling test needs the eye of a perspective projection, the depth test is enough otherwise.
+    let mut vertexs = match camera.projection {
+        Projection::Perspective => back_face_cull(vertexs),
+        Projection::Orthographic { .. } => vertexs,
+    };
     triangles_w_reciprocal(&mut vertexs);
     transform_models_vertexs(&mut vertexs, &viewport);
-    model.vertexs = vertexs;
-    model
+    vertexs
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -324,6 +425,12 @@ fn interpolate_vertex(v1: &Vertex, v2: &Vertex, t: f32) -> Vertex {
             &v2.texture_coordinate,
             t
         ),
+        varyings: v1
+            .varyings
+            .iter()
+            .zip(v2.varyings.iter())
+            .map(|(a, b)| a + (b - a) * t)
+            .collect(),
     }
 }
 
@@ -348,13 +455,12 @@ fn clip_plane(plane: Plane, vertexs: Vec<Vertex>) -> Vec<Vertex> {
     vertexs
 }
 
-fn complete_homogeneous_clip(model: &Model) -> Vec<Vertex> {
-    let vertex_groups = model
-        .indices
+fn complete_homogeneous_clip(indices: &[[u32; 3]], vertexs: &[Vertex]) -> Vec<Vertex> {
+    let vertex_groups = indices
         .iter()
-        .map(|is| is.iter().map(|&i| &model.vertexs[i as usize]));
+        .map(|is| is.iter().map(|&i| &vertexs[i as usize]));
 
-    let mut new_vertexs = Vec::with_capacity(model.vertexs.len());
+    let mut new_vertexs = Vec::with_capacity(vertexs.len());
     vertex_groups.for_each(|vertexs| {
         let vertexs = vertexs.cloned().collect::<Vec<_>>();
         let vertexs = clip_plane(Plane::W, vertexs);
@@ -427,36 +533,3 @@ fn homogeneous_division(vertexs: &mut [Vertex]) {
         v.position = &v.position / v.position.w();
     })
 }
-
-fn bitmap_from_framebuffer(
-    frame_buffer: &[Option<Color>],
-    width: usize,
-    height: usize,
-) -> Vec<u32> {
-    let mut frame_buffer_bitmap: Vec<u32> = Vec::with_capacity(width * height);
-    //Background
-    let background: u32 = 100 << 24 | 255 << 16 | 255 << 8 | 255; // [255u8, 255, 255, 100];
-    frame_buffer
-        .iter()
-        .enumerate()
-        .step_by(width)
-        .rev()
-        .map(|(i, ..)| &frame_buffer[i..i + width])
-        .for_each(|line| {
-            line.iter().for_each(|c| {
-                // frame_buffer_bitmap.push(color);
-                if let Some(c) = c {
-                    let pixel: u32 = ((c.a as u32) << 24)
-                        | ((c.r as u32) << 16)
-                        | ((c.g as u32) << 8)
-                        | c.b as u32;
-                    frame_buffer_bitmap.push(pixel);
-                } else {
-                    frame_buffer_bitmap.push(background)
-                }
-                // */
-            })
-        });
-
-    frame_buffer_bitmap
-}
@@ -0,0 +1,321 @@
+use crate::{
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, Vector3},
+    },
+    pipeline::{
+        camera::Camera,
+        light::Light,
+        material::{EmissiveMaterial, MaterialNew, PBRMaterial, PhongMaterial},
+        model::Model,
+        scene_graph::SceneGraph,
+        transformation::modeling::Modeling,
+    },
+};
+use serde::Deserialize;
+use std::{path::Path, sync::Arc};
+
+/// Models, camera and lights shared by the rasterizer and the path tracer.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub models: Vec<Model>,
+    // Each pipeline keeps its own default camera when the scene has none.
+    pub camera: Option<Camera>,
+    // Cameras of the glTF files, the first one is active unless the scene file sets a camera.
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+}
+
+impl Scene {
+    /// Load a JSON scene file, model paths are relative to the file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot open scene file `{}`: {}", path, err))?;
+        let base = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        Self::from_json(&json, base).map_err(|err| format!("Load `{}` failed: {}", path, err))
+    }
+
+    /// Load a single `.obj`, `.gltf` or `.glb` file, glTF files bring their lights along.
+    pub fn from_model_file(path: &str) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let is_gltf = matches!(extension.as_deref(), Some("gltf") | Some("glb"));
+        if is_gltf && Path::new(path).is_file() {
+            return Ok(Self::from(&SceneGraph::load(path)?));
+        }
+        Ok(Self {
+            models: Model::load(path)?,
+            camera: None,
+            cameras: Vec::new(),
+            lights: Vec::new(),
+        })
+    }
+
+    /// Make a camera of the glTF files active, by its index or its name.
+    pub fn select_camera(&mut self, selector: &str) -> Result<(), String> {
+        let camera = match selector.parse::<usize>() {
+            Ok(index) => self.cameras.get(index),
+            Err(_) => self
+                .cameras
+                .iter()
+                .find(|camera| camera.name.as_deref() == Some(selector)),
+        };
+        self.camera = Some(
+            camera
+                .ok_or_else(|| {
+                    format!(
+                        "Cannot find camera `{}`, the scene has {} cameras.",
+                        selector,
+                        self.cameras.len()
+                    )
+                })?
+                .clone(),
+        );
+        Ok(())
+    }
+
+    pub fn from_json(json: &str, base: &Path) -> Result<Self, String> {
+        let description: SceneDescription =
+            serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+        let mut models = Vec::new();
+        let mut cameras = Vec::new();
+        let mut lights = description
+            .lights
+            .iter()
+            .map(|l| l.light())
+            .collect::<Vec<_>>();
+        for model in description.models.iter() {
+            let path = base.join(&model.path);
+            let path = path.to_str().ok_or("Invalid model path.")?;
+            let matrix = modeling_matrix(model.translation, model.rotation, model.scale);
+            let instances = if model.instances.is_empty() {
+                vec![matrix]
+            } else {
+                model
+                    .instances
+                    .iter()
+                    .map(|i| &matrix * &modeling_matrix(i.translation, i.rotation, i.scale))
+                    .collect()
+            };
+            let file = Self::from_model_file(path)?;
+            for light in file.lights.iter() {
+                lights.extend(instances.iter().map(|matrix| light.transform(matrix)));
+            }
+            for camera in file.cameras.iter() {
+                cameras.extend(instances.iter().map(|matrix| camera.transform(matrix)));
+            }
+            for mut m in file.models {
+                m.instances = instances
+                    .iter()
+                    .flat_map(|s| m.instances.iter().map(move |i| s * i))
+                    .collect();
+                if let Some(material) = &model.material {
+                    m.material = Some(Arc::new(material.apply(m.material.as_deref())));
+                }
+                models.push(m);
+            }
+        }
+
+        Ok(Self {
+            models,
+            camera: description
+                .camera
+                .map(|c| c.camera())
+                .or_else(|| cameras.first().cloned()),
+            cameras,
+            lights,
+        })
+    }
+}
+
+impl From<&SceneGraph> for Scene {
+    fn from(graph: &SceneGraph) -> Self {
+        let cameras = graph.cameras();
+        Self {
+            models: graph.models(),
+            camera: cameras.first().cloned(),
+            cameras,
+            lights: graph.lights(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: Option<CameraDescription>,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+    models: Vec<ModelDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    eye: Option<[f32; 3]>,
+    target: Option<[f32; 3]>,
+    up: Option<[f32; 3]>,
+    // Vertical field of view in degrees.
+    fov: Option<f32>,
+    near: Option<f32>,
+    far: Option<f32>,
+}
+
+impl CameraDescription {
+    fn camera(&self) -> Camera {
+        let mut camera = Camera::default();
+        if let Some(eye) = self.eye {
+            camera.eye_position = vector3(eye);
+        }
+        if let Some(target) = self.target {
+            camera.gaze_direct = (&vector3(target) - &camera.eye_position).normalized();
+        }
+        if let Some(up) = self.up {
+            camera.up_direct = vector3(up).normalized();
+        }
+        if let Some(fov) = self.fov {
+            camera.eye_fov = fov.to_radians();
+        }
+        camera.near = self.near.unwrap_or(camera.near);
+        camera.far = self.far.unwrap_or(camera.far);
+        camera
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDescription {
+    position: [f32; 3],
+    intensity: Option<f32>,
+}
+
+impl LightDescription {
+    fn light(&self) -> Light {
+        let light = Light::default().position(vector3(self.position));
+        match self.intensity {
+            Some(intensity) => light.intensity(intensity),
+            None => light,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDescription {
+    path: String,
+    #[serde(default)]
+    translation: [f32; 3],
+    // Euler angles in degrees, applied around X, then Y, then Z.
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: [f32; 3],
+    // Draw the mesh once per instance, each one placed relative to the model's transform.
+    #[serde(default)]
+    instances: Vec<InstanceDescription>,
+    material: Option<MaterialDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDescription {
+    #[serde(default)]
+    translation: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: [f32; 3],
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn modeling_matrix(translation: [f32; 3], rotation: [f32; 3], scale: [f32; 3]) -> Matrix4 {
+    let [sx, sy, sz] = scale;
+    let [rx, ry, rz] = rotation;
+    let [tx, ty, tz] = translation;
+    Modeling::new()
+        .scale((sx, sy, sz))
+        .rotate_around_x(rx.to_radians())
+        .rotate_around_y(ry.to_radians())
+        .rotate_around_z(rz.to_radians())
+        .translate((tx, ty, tz))
+        .transform_matrix
+}
+
+// Every field is optional and overrides the model's own material.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    // Phong
+    ambient: Option<[f32; 3]>,
+    diffuse: Option<[f32; 3]>,
+    specular: Option<[f32; 3]>,
+    // PBR
+    albedo: Option<[f32; 3]>,
+    metalness: Option<f32>,
+    roughness: Option<f32>,
+    ior: Option<f32>,
+    // Glass-like when above zero, light goes through the surface and refracts by `ior`.
+    transmission: Option<f32>,
+    // Emissive, turns the model into a light of the path tracer.
+    emission: Option<[f32; 3]>,
+    intensity: Option<f32>,
+}
+
+impl MaterialDescription {
+    fn apply(&self, material: Option<&MaterialNew>) -> MaterialNew {
+        if let Some(emission) = self.emission {
+            return MaterialNew::Emissive(EmissiveMaterial {
+                base_color: vector3(emission),
+                intensity: self.intensity.unwrap_or(1.0),
+            });
+        }
+
+        let is_pbr = self.albedo.is_some()
+            || self.metalness.is_some()
+            || self.roughness.is_some()
+            || self.ior.is_some()
+            || self.transmission.is_some();
+        let or = |v: Option<[f32; 3]>, default: &Vector3| v.map_or(default.clone(), vector3);
+        match material {
+            Some(MaterialNew::PBR(m)) => MaterialNew::PBR(self.pbr(m)),
+            Some(MaterialNew::Phong(m)) if is_pbr => {
+                MaterialNew::PBR(self.pbr(&PBRMaterial::from(m)))
+            }
+            Some(MaterialNew::Phong(m)) => MaterialNew::Phong(PhongMaterial {
+                ambient_color: or(self.ambient, &m.ambient_color),
+                diffuse_color: or(self.diffuse, &m.diffuse_color),
+                specular_color: or(self.specular, &m.specular_color),
+            }),
+            Some(MaterialNew::Emissive(m)) => MaterialNew::Emissive(EmissiveMaterial {
+                base_color: m.base_color.clone(),
+                intensity: self.intensity.unwrap_or(m.intensity),
+            }),
+            None if is_pbr => {
+                MaterialNew::PBR(self.pbr(&PBRMaterial::new(Vector3::from(0.8), 0.0, 0.8, 1.5)))
+            }
+            None => MaterialNew::Phong(PhongMaterial {
+                ambient_color: or(self.ambient, &Vector3::from(1.0)),
+                diffuse_color: or(self.diffuse, &Vector3::from(0.5)),
+                specular_color: or(self.specular, &Vector3::from(0.8)),
+            }),
+        }
+    }
+
+    fn pbr(&self, m: &PBRMaterial) -> PBRMaterial {
+        PBRMaterial::new(
+            self.albedo.map_or(m.albedo.clone(), vector3),
+            self.metalness.unwrap_or(m.metalness),
+            self.roughness.unwrap_or(m.roughness),
+            self.ior.unwrap_or(m.refraction),
+        )
+        .emission(m.emission.clone())
+        .textures(m.textures.clone())
+        .transmission(self.transmission.unwrap_or(m.transmission))
+    }
+}
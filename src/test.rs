@@ -1,12 +1,29 @@
-use crate::algebra::matrix::Matrixf;
+use crate::algebra::{matrix::Matrixf, vector_new::Vector3};
+use crate::scene::Scene;
 use generic_array::ArrayLength;
-use std::{fmt::Debug, ops::Mul};
+use std::{fmt::Debug, ops::Mul, path::Path};
 use typenum::{Prod, Unsigned};
 
+mod adaptive;
 mod algebra;
 mod algebra_new;
+mod bvh;
+mod camera;
+mod cli;
+mod dielectric;
 mod pbr;
 mod fbx;
+mod filter;
+mod framebuffer;
+mod light;
+mod material;
+mod path_tracing;
+mod rasterizer;
+mod renderer;
+mod sampler;
+mod scene;
+mod scene_graph;
+mod shadow_map;
 
 #[allow(dead_code)]
 pub fn about_equal<Row, Col>(m1: &Matrixf<Row, Col>, m2: &Matrixf<Row, Col>) -> Result<(), String>
@@ -24,3 +41,28 @@ where
     }
     Ok(())
 }
+
+pub fn assert_near(a: &Vector3, b: &Vector3, eps: f32) {
+    assert!((a - b).norm() < eps, "{:?} != {:?}", a, b);
+}
+
+/// A small cube of `material` in front of a big emissive box, only the two are in sight from the
+/// default camera.
+pub fn cube_in_light_box(material: &str) -> Scene {
+    const JSON: &str = r#"{
+    "models": [
+        {
+            "path": "cube.obj",
+            "scale": [5.0, 5.0, 5.0],
+            "material": { "emission": [1.0, 0.5, 0.25], "intensity": 2.0 }
+        },
+        {
+            "path": "cube.obj",
+            "translation": [0.6, 1.3, 0.0],
+            "scale": [0.5, 0.5, 0.5],
+            "material": MATERIAL
+        }
+    ]
+}"#;
+    Scene::from_json(&JSON.replace("MATERIAL", material), Path::new("static")).unwrap()
+}
@@ -0,0 +1,96 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    ray_tracing::{
+        adaptive::{heat, luminance, AdaptiveSampling, PixelVariance},
+        path_tracing::RayTracer,
+    },
+    test::{assert_near, cube_in_light_box},
+};
+use std::time::Duration;
+
+const ROUGH: &str = r#"{ "albedo": [0.8, 0.8, 0.8], "roughness": 0.5 }"#;
+
+fn adaptive_ray_tracer(adaptive: AdaptiveSampling) -> RayTracer {
+    // Only the rough cube and its edges are noisy.
+    let scene = cube_in_light_box(ROUGH);
+    let mut ray_tracer = RayTracer::from_scene(&scene, 24, 16, 4);
+    ray_tracer.adaptive = Some(adaptive);
+    ray_tracer
+}
+
+fn sample_counts(ray_tracer: &RayTracer) -> Vec<usize> {
+    ray_tracer.variance.iter().map(|p| p.count).collect()
+}
+
+#[test]
+fn running_variance_matches_the_samples() {
+    let values = [0.5, 2.0, 1.0, 4.0, 0.0, 3.5, 1.5];
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (values.len() - 1) as f32;
+
+    let gray = |v: f32| Vector3::from(v);
+    let mut all = PixelVariance::default();
+    assert_eq!(all.relative_error(), f32::INFINITY);
+    values.iter().for_each(|&v| all.add(&gray(v)));
+    assert_eq!(all.count, values.len());
+    assert!((all.mean - mean).abs() < 1e-5);
+    assert!((all.variance() - variance).abs() < 1e-5);
+    let error = (variance / values.len() as f32).sqrt() / mean;
+    assert!((all.relative_error() - error).abs() < 1e-5);
+
+    // Statistics of separate passes add up.
+    let (mut a, mut b) = (PixelVariance::default(), PixelVariance::default());
+    values[..3].iter().for_each(|&v| a.add(&gray(v)));
+    values[3..].iter().for_each(|&v| b.add(&gray(v)));
+    a.merge(&b);
+    a.merge(&PixelVariance::default());
+    assert_eq!(a.count, all.count);
+    assert!((a.mean - all.mean).abs() < 1e-5);
+    assert!((a.variance() - all.variance()).abs() < 1e-5);
+
+    assert!((luminance(&vector3([1.0, 1.0, 1.0])) - 1.0).abs() < 1e-6);
+    assert_eq!(heat(4, 4, 256), vector3([0.0, 0.0, 0.0]));
+    assert_eq!(heat(256, 4, 256), vector3([1.0, 1.0, 1.0]));
+    assert_near(&heat(32, 4, 256), &vector3([1.0, 0.0, 0.0]), 1e-4);
+    assert_eq!(heat(8, 8, 8), vector3([0.0, 0.0, 0.0]));
+}
+
+#[test]
+fn noisy_pixels_take_more_samples() {
+    let adaptive = AdaptiveSampling::default()
+        .target_error(0.01)
+        .min_spp(4)
+        .max_spp(64)
+        .batch(8);
+    let mut ray_tracer = adaptive_ray_tracer(adaptive.clone());
+    let framebuffer = ray_tracer.render().clone();
+    let counts = sample_counts(&ray_tracer);
+    let (light, cube) = (2 * 24 + 2, 5 * 24 + 17);
+    // The emissive wall is exact from the first samples, the lit cube isn't.
+    assert_eq!(counts[light], 4);
+    assert!(counts[cube] > 4);
+    assert!(counts.iter().all(|&c| (4..=64).contains(&c)));
+    for (pixel, count) in ray_tracer.variance.iter().zip(counts.iter()) {
+        assert!(*count == 64 || !adaptive.is_noisy(pixel));
+    }
+    assert_eq!(
+        Vector3::from(framebuffer.get(2, 2)),
+        vector3([2.0, 1.0, 0.5])
+    );
+
+    let heatmap = ray_tracer.sample_heatmap();
+    assert!(
+        luminance(&Vector3::from(heatmap.get(17, 5)))
+            > luminance(&Vector3::from(heatmap.get(2, 2)))
+    );
+
+    // Budgets stop early, the first samples of every pixel are always taken.
+    let mut ray_tracer = adaptive_ray_tracer(adaptive.clone().sample_budget(6.0));
+    ray_tracer.render();
+    let total = sample_counts(&ray_tracer).iter().sum::<usize>();
+    assert!((4 * 24 * 16..=6 * 24 * 16).contains(&total));
+    let mut ray_tracer = adaptive_ray_tracer(adaptive.time_budget(Duration::ZERO));
+    ray_tracer.render();
+    assert!(sample_counts(&ray_tracer).iter().all(|&c| c == 4));
+}
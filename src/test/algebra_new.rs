@@ -77,6 +77,24 @@ fn matrix_unit() {
     assert_eq!(m2, m1);
 }
 
+#[test]
+fn matrix_identity() {
+    let m1 = Matrix::<3>([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+    assert_eq!(Matrix::<3>::identity(), m1);
+}
+
+#[test]
+fn matrix_inverse() {
+    let m = Matrix4::translation_matrix(1.0, -2.0, 3.0)
+        * Matrix4::rotate_around_y_matrix(0.7)
+        * Matrix4::scale_matrix(2.0, 0.5, 4.0);
+    let product = &m * &m.inverse_matrix();
+    for (i, j) in Matrix4::index_iter() {
+        let expected = if i == j { 1.0 } else { 0.0 };
+        assert!((product[i][j] - expected).abs() < 1e-5);
+    }
+}
+
 #[test]
 fn normalize_vector() {
     let mut v = Vector::<3>([0.0, 0.0, 2.0]);
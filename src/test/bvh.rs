@@ -0,0 +1,173 @@
+use crate::{
+    algebra::vector_new::vector3,
+    pipeline::{model::Model, scene_graph::SceneGraph},
+    ray_tracing::{
+        bvh::{BVHBuilder, BVHNode, BVHTree, LinearBVH},
+        path_tracing::RayTracer,
+        ray::Ray,
+    },
+    renderer::triangulated_models_and_triangles,
+    scene::Scene,
+};
+use rand::Rng;
+use std::path::Path;
+
+fn bunny() -> Scene {
+    Scene::from(&SceneGraph::load("static/bunny.gltf").unwrap())
+}
+
+fn leaves(node: &BVHNode) -> Vec<&BVHNode> {
+    match (&node.l, &node.r) {
+        (None, None) => vec![node],
+        (l, r) => l.iter().chain(r.iter()).flat_map(|n| leaves(n)).collect(),
+    }
+}
+
+#[test]
+fn sah_tree_keeps_every_triangle() {
+    let (_, triangles) = triangulated_models_and_triangles(&bunny().models, 1.0);
+    let tree = BVHTree::from_triangles(&triangles);
+    assert_eq!(tree.sum(), triangles.len());
+
+    for leaf in leaves(&tree.root) {
+        let data = leaf.data.as_ref().unwrap();
+        assert!(!data.is_empty() && data.len() <= BVHBuilder::default().leaf_size);
+        // Leaves bound their triangles.
+        let b = &leaf.bounding_box;
+        for v in data.iter().flat_map(|t| t.vertexs.iter()) {
+            let p = &v.position;
+            assert!(p.x() >= b.x0 && p.x() <= b.x1);
+            assert!(p.y() >= b.y0 && p.y() <= b.y1);
+            assert!(p.z() >= b.z0 && p.z() <= b.z1);
+        }
+    }
+
+    // A single leaf tests every triangle.
+    let flat = BVHBuilder::default().max_depth(0).build(&triangles);
+    assert_eq!(flat.node_count(), 1);
+    assert_eq!(flat.sah_cost(), triangles.len() as f32);
+    assert!(tree.sah_cost() * 5.0 < flat.sah_cost());
+
+    let shallow = BVHBuilder::default().max_depth(3).build(&triangles);
+    assert_eq!(shallow.depth(), 3);
+    assert_eq!(shallow.sum(), triangles.len());
+    assert!(shallow.sah_cost() > tree.sah_cost());
+
+    let large_leaves = BVHBuilder::default().leaf_size(16).build(&triangles);
+    assert!(large_leaves.node_count() < tree.node_count());
+    assert!(BVHTree::from_triangles(&[]).sah_cost() == 0.0);
+}
+
+#[test]
+fn sah_tree_finds_nearest_hits() {
+    let ray_tracer = RayTracer::from_scene(&bunny(), 4, 4, 1);
+    let mut rng = rand::thread_rng();
+    for _ in 0..2000 {
+        let mut random = || vector3([0.0; 3].map(|_: f32| rng.gen_range(-1.0f32..1.0)));
+        let ray = Ray::new(&(random() * 2.0), &random());
+        let hit = ray_tracer.get_nearest_intersection(&ray);
+        let expected = ray_tracer._slow_get_nearest_intersection(&ray);
+        match (hit, expected) {
+            (Some(hit), Some(expected)) => assert_eq!(hit.distance, expected.distance),
+            (hit, expected) => assert_eq!(hit.is_some(), expected.is_some()),
+        }
+    }
+}
+
+#[test]
+fn flattened_tree_layout() {
+    let (_, triangles) = triangulated_models_and_triangles(&bunny().models, 1.0);
+    let tree = BVHTree::from_triangles(&triangles);
+    let node_count = tree.node_count();
+    let bvh = LinearBVH::from(tree);
+    assert_eq!(bvh.nodes.len(), node_count);
+    assert_eq!(bvh.primitives.len(), triangles.len());
+
+    // Leaves follow each other in the triangle array.
+    let mut next = 0;
+    for (i, node) in bvh.nodes.iter().enumerate() {
+        if node.is_leaf() {
+            assert_eq!(node.offset as usize, next);
+            next += node.count as usize;
+        } else {
+            assert!(node.offset as usize > i + 1);
+            let l = &bvh.nodes[i + 1].bounding_box;
+            let r = &bvh.nodes[node.offset as usize].bounding_box;
+            let union = l.union(r);
+            assert_eq!(union.axis(0), node.bounding_box.axis(0));
+            assert_eq!(union.axis(2), node.bounding_box.axis(2));
+        }
+    }
+    assert_eq!(next, triangles.len());
+
+    let empty = LinearBVH::from_triangles(&[]);
+    assert!(empty.nodes.is_empty());
+    let ray = Ray::new(&vector3([0.0; 3]), &vector3([0.0, 0.0, -1.0]));
+    assert!(empty.nearest_hit(&ray, 0.0, f32::INFINITY).is_none());
+}
+
+#[test]
+fn any_hit_stops_before_nearest() {
+    let ray_tracer = RayTracer::from_scene(&bunny(), 4, 4, 1);
+    let bvh = &ray_tracer.objects_tree;
+    let mut rng = rand::thread_rng();
+    for _ in 0..2000 {
+        let mut random = || vector3([0.0; 3].map(|_: f32| rng.gen_range(-1.0f32..1.0)));
+        let ray = Ray::new(&(random() * 2.0), &random());
+        match bvh.nearest_hit(&ray, 0.0, f32::INFINITY) {
+            Some((_, t, _)) => {
+                assert!(bvh.any_hit(&ray, 0.0, f32::INFINITY));
+                assert!(bvh.any_hit(&ray, 0.0, t * 1.001));
+                assert!(!bvh.any_hit(&ray, 0.0, t));
+            }
+            None => assert!(!bvh.any_hit(&ray, 0.0, f32::INFINITY)),
+        }
+    }
+}
+
+#[test]
+fn instances_share_one_mesh_tree() {
+    let json = r#"{
+        "models": [{
+            "path": "cube.obj",
+            "instances": [
+                { "translation": [-1.5, 0.0, 0.0], "scale": [0.5, 1.0, 0.5] },
+                { "translation": [1.0, 0.5, -0.5], "scale": [0.8, 0.4, 1.2] }
+            ]
+        }]
+    }"#;
+    let scene = Scene::from_json(json, Path::new("static")).unwrap();
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert_eq!(ray_tracer.objects_tree.meshes.len(), 1);
+    assert_eq!(ray_tracer.objects_tree.instances.primitives.len(), 2);
+
+    // Every instance baked into the world, as the tree should see them.
+    let model = &scene.models[0];
+    let triangles = model
+        .instances
+        .iter()
+        .flat_map(|matrix| {
+            Model {
+                vertexs: model.instance_vertexs(matrix),
+                instances: Vec::new(),
+                ..model.clone()
+            }
+            .triangles()
+        })
+        .collect::<Vec<_>>();
+    let mut rng = rand::thread_rng();
+    for _ in 0..2000 {
+        let mut random = || vector3([0.0; 3].map(|_: f32| rng.gen_range(-1.0f32..1.0)));
+        let ray = Ray::new(&(random() * 3.0), &random());
+        let expected = triangles
+            .iter()
+            .filter_map(|t| ray.intersect_triangle_distance(t))
+            .map(|(t, _)| t)
+            .filter(|&t| t > 0.001)
+            .min_by(f32::total_cmp);
+        match (ray_tracer.get_nearest_intersection(&ray), expected) {
+            (Some(hit), Some(t)) => assert!((hit.distance - t).abs() < 1e-3),
+            (hit, expected) => assert_eq!(hit.is_some(), expected.is_some()),
+        }
+    }
+}
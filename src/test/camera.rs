@@ -0,0 +1,309 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3, Vector4},
+    pipeline::{
+        camera::{Bokeh, Camera, Lens, Projection},
+        model::Model,
+        scene_graph::SceneGraph,
+        transformation::Transformation,
+    },
+    ray_tracing::{
+        path_tracing::RayTracer,
+        ray::Ray,
+        sampler::{Sampler, StratifiedSampler},
+    },
+    renderer::Renderer,
+    scene::Scene,
+    test::assert_near,
+};
+use std::f32::consts::PI;
+
+// Image plane coordinates of a world position, in [-1, 1] inside the view volume.
+fn ndc(camera: &Camera, position: [f32; 3]) -> Vector3 {
+    let matrix = Transformation::projection_transform(camera) * Transformation::view_matrix(camera);
+    let p = &matrix * &Vector4::point_from(&vector3(position));
+    vector3([p.x() / p.w(), p.y() / p.w(), 0.0])
+}
+
+#[test]
+fn import_gltf_cameras() {
+    let cameras = SceneGraph::load("static/cameras.gltf").unwrap().cameras();
+    assert_eq!(cameras.len(), 2);
+
+    let front = &cameras[0];
+    assert_eq!(front.name.as_deref(), Some("front"));
+    assert_eq!(front.projection, Projection::Perspective);
+    assert_near(&front.eye_position, &vector3([0.0, 1.0, 5.0]), 1e-4);
+    assert_near(&front.gaze_direct, &vector3([0.0, 0.0, -1.0]), 1e-4);
+    assert!((front.eye_fov - 0.8).abs() < 1e-6);
+    assert!((front.aspect_ratio - 1.5).abs() < 1e-6);
+    assert_eq!((front.near, front.far), (0.1, 100.0));
+
+    // The top camera hangs below `rig` and looks down.
+    let top = &cameras[1];
+    assert_eq!(top.name.as_deref(), Some("top"));
+    assert_eq!(top.projection, Projection::Orthographic { ymag: 3.0 });
+    assert_near(&top.eye_position, &vector3([1.0, 5.0, 0.0]), 1e-4);
+    assert_near(&top.gaze_direct, &vector3([0.0, -1.0, 0.0]), 1e-4);
+    assert_near(&top.up_direct, &vector3([0.0, 0.0, -1.0]), 1e-4);
+    assert!((top.aspect_ratio - 4.0 / 3.0).abs() < 1e-6);
+    assert_eq!((top.near, top.far), (0.1, 20.0));
+}
+
+#[test]
+fn select_camera() {
+    let mut scene = Scene::from_model_file("static/cameras.gltf").unwrap();
+    assert_eq!(scene.cameras.len(), 2);
+    assert_eq!(
+        scene.camera.as_ref().unwrap().name.as_deref(),
+        Some("front")
+    );
+
+    scene.select_camera("1").unwrap();
+    assert_eq!(scene.camera.as_ref().unwrap().name.as_deref(), Some("top"));
+    scene.select_camera("front").unwrap();
+    assert_eq!(
+        scene.camera.as_ref().unwrap().name.as_deref(),
+        Some("front")
+    );
+
+    assert!(scene.select_camera("2").unwrap_err().contains("2 cameras"));
+    assert!(scene
+        .select_camera("side")
+        .unwrap_err()
+        .contains("Cannot find"));
+    assert!(Scene::from_model_file("static/cube.obj")
+        .unwrap()
+        .select_camera("0")
+        .is_err());
+}
+
+#[test]
+fn orthographic_projection() {
+    let top = SceneGraph::load("static/cameras.gltf").unwrap().cameras()[1].clone();
+    // The view volume is 2 * ymag high and keeps its size at any depth.
+    assert_near(
+        &ndc(&top, [5.0, 0.0, -3.0]),
+        &vector3([1.0, 1.0, 0.0]),
+        1e-4,
+    );
+    assert_near(
+        &ndc(&top, [5.0, -1.0, -3.0]),
+        &vector3([1.0, 1.0, 0.0]),
+        1e-4,
+    );
+    assert_near(
+        &ndc(&top, [-3.0, 4.0, 3.0]),
+        &vector3([-1.0, -1.0, 0.0]),
+        1e-4,
+    );
+
+    // The floor ends at x = 3, three quarters across the image.
+    let scene = Scene::from_model_file("static/cameras.gltf").unwrap();
+    let framebuffer = Renderer::default()
+        .scene(&scene)
+        .camera(top.clone())
+        .shader("pbr-raster", "")
+        .unwrap()
+        .render_framebuffer(40, 30);
+    let background = framebuffer.get(39, 15).clone();
+    assert_ne!(framebuffer.get(5, 15), &background);
+    assert_eq!(framebuffer.get(32, 15), &background);
+
+    let front = SceneGraph::load("static/cameras.gltf").unwrap().cameras()[0].clone();
+    let half_height = (0.4f32).tan();
+    assert_near(
+        &ndc(&front, [0.0, 1.0 + half_height, 4.0]),
+        &vector3([0.0, 1.0, 0.0]),
+        1e-4,
+    );
+    assert_near(
+        &ndc(&front, [0.0, 1.0 + 2.0 * half_height, 3.0]),
+        &vector3([0.0, 1.0, 0.0]),
+        1e-4,
+    );
+}
+
+#[test]
+fn path_tracer_uses_scene_camera() {
+    let scene = Scene::from_model_file("static/cameras.gltf").unwrap();
+    let mut ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert_near(
+        &ray_tracer.camera.eye_position,
+        &vector3([0.0, 1.0, 5.0]),
+        1e-4,
+    );
+    assert!((ray_tracer.camera.eye_fov - 0.8).abs() < 1e-6);
+
+    // Orthographic rays are parallel and start on the image plane.
+    ray_tracer.camera = Camera::default()
+        .eye_position(vector3([1.0, 0.0, 5.0]))
+        .projection(Projection::Orthographic { ymag: 2.0 });
+    let (a, b) = (ray_tracer.pixel_to_ray(0, 0), ray_tracer.pixel_to_ray(2, 2));
+    assert_near(&a.dir, &b.dir, 1e-4);
+    assert_near(&a.origin, &vector3([-0.5, 1.5, 5.0]), 1e-4);
+    assert_near(&b.origin, &vector3([1.5, -0.5, 5.0]), 1e-4);
+}
+
+#[test]
+fn path_tracer_follows_view_matrix() {
+    let perspective = Camera::default()
+        .eye_position(vector3([2.0, 3.0, 4.0]))
+        .gaze_direct(vector3([-1.0, -1.0, -2.0]).normalized())
+        .up_direct(vector3([0.0, 1.0, 0.0]))
+        .eye_fov(50f32.to_radians())
+        .aspect_ratio(1.5);
+    let orthographic = perspective
+        .clone()
+        .projection(Projection::Orthographic { ymag: 2.0 });
+
+    let (width, height) = (30, 20);
+    let models = Model::load("static/cube.obj").unwrap();
+    for camera in [perspective, orthographic] {
+        let mut ray_tracer = RayTracer::from_models(&models, width, height, 1);
+        ray_tracer.camera = camera.clone();
+        for (x, y) in [(0, 0), (7, 13), (29, 19)] {
+            let ray = ray_tracer.pixel_to_ray(x, y);
+            // Points along the ray project onto the pixel's center, rows go down.
+            let center = vector3([
+                2.0 * (x as f32 + 0.5) / width as f32 - 1.0,
+                1.0 - 2.0 * (y as f32 + 0.5) / height as f32,
+                0.0,
+            ]);
+            for t in [1.0, 5.0] {
+                let p = &ray.origin + &(&ray.dir * t);
+                assert_near(&ndc(&camera, [p.x(), p.y(), p.z()]), &center, 1e-4);
+            }
+        }
+    }
+}
+
+#[test]
+fn lens_samples_cover_the_aperture() {
+    let grid = (0..32)
+        .flat_map(|i| (0..32).map(move |j| ((i as f32 + 0.5) / 32.0, (j as f32 + 0.5) / 32.0)));
+
+    let circle = Lens::default().aperture(2.0);
+    let points = grid.clone().map(|st| circle.sample(st)).collect::<Vec<_>>();
+    assert!(points.iter().all(|&(x, y)| (x * x + y * y).sqrt() <= 2.0));
+    // Uniform over the area, a quarter of the samples fall within half the radius.
+    let inner = points
+        .iter()
+        .filter(|&&(x, y)| (x * x + y * y).sqrt() < 1.0)
+        .count();
+    assert!((inner as f32 / points.len() as f32 - 0.25).abs() < 0.02);
+
+    let hexagon = Lens::default().aperture(2.0).bokeh(Bokeh::Polygon {
+        blades: 6,
+        rotation: 0.0,
+    });
+    // The far end of the first blade is a corner.
+    let (x, y) = hexagon.sample((0.999 / 6.0, 0.0));
+    assert!((x - 2.0).abs() < 1e-2 && y.abs() < 1e-4);
+    // Inside the hexagon means within the apothem of every edge.
+    let apothem = 2.0 * (PI / 6.0).cos();
+    for (x, y) in grid.map(|st| hexagon.sample(st)) {
+        for k in 0..6 {
+            let angle = (2 * k + 1) as f32 * PI / 6.0;
+            assert!(x * angle.cos() + y * angle.sin() <= apothem + 1e-4);
+        }
+    }
+}
+
+#[test]
+fn thin_lens_focuses_on_a_plane() {
+    let models = Model::load("static/cube.obj").unwrap();
+    let mut ray_tracer = RayTracer::from_models(&models, 20, 20, 16);
+    ray_tracer.camera = Camera::default()
+        .eye_position(vector3([0.0, 1.0, 5.0]))
+        .gaze_direct(vector3([0.0, -0.2, -1.0]).normalized())
+        .eye_fov(0.8);
+    let pinhole = ray_tracer.pixel_to_ray(3, 15);
+    let mut sampler = StratifiedSampler::new(16, 0);
+    sampler.start_pixel_sample(3, 15, 7);
+    assert_near(
+        &ray_tracer.sample_ray((3.5, 15.5), &mut sampler).dir,
+        &pinhole.dir,
+        1e-4,
+    );
+
+    let lens = Lens::default().aperture(0.2).focal_distance(4.0);
+    ray_tracer.camera.lens = Some(lens);
+    let (_, _, w) = Transformation::camera_basis(&ray_tracer.camera);
+    let on_plane = |ray: &Ray| {
+        let t =
+            (4.0 - (&ray.origin - &ray_tracer.camera.eye_position).dot(&-&w)) / ray.dir.dot(&-&w);
+        &ray.origin + &(&ray.dir * t)
+    };
+    let sharp = on_plane(&pinhole);
+    let rays = (0..16)
+        .map(|i| {
+            sampler.start_pixel_sample(3, 15, i);
+            ray_tracer.sample_ray((3.5, 15.5), &mut sampler)
+        })
+        .collect::<Vec<_>>();
+    for ray in rays.iter() {
+        assert!((&ray.origin - &pinhole.origin).norm() <= 0.2 + 1e-4);
+        assert_near(&on_plane(ray), &sharp, 1e-4);
+    }
+    // The lens is actually used.
+    assert!(rays
+        .iter()
+        .any(|ray| (&ray.origin - &pinhole.origin).norm() > 0.05));
+}
+
+#[test]
+fn focus_on_a_pixel() {
+    let scene = Scene::from_model_file("static/cameras.gltf").unwrap();
+    let mut ray_tracer = RayTracer::from_scene(&scene, 21, 21, 1);
+    ray_tracer.camera = Camera::default()
+        .eye_position(vector3([0.0, -0.5, 2.0]))
+        .eye_fov(0.8);
+    // The center ray meets the front face of the box.
+    assert!((ray_tracer.focus_distance(10, 10).unwrap() - 1.5).abs() < 1e-3);
+    // The distance is measured along the gaze, not the ray.
+    let floor = ray_tracer.focus_distance(10, 20).unwrap();
+    let ray = ray_tracer.pixel_to_ray(10, 20);
+    let depth = 0.5 / -ray.dir.y() * -ray.dir.z();
+    assert!((floor - depth).abs() < 1e-3);
+    assert_eq!(ray_tracer.focus_distance(10, 0), None);
+}
+
+#[test]
+fn orbit_controls() {
+    let camera = Camera::default().eye_position(vector3([0.0, 0.0, 3.0]));
+
+    // Orbits keep looking at the origin from the same distance.
+    let yawed = camera.yaw(PI / 2.0);
+    assert_near(&yawed.eye_position, &vector3([3.0, 0.0, 0.0]), 1e-4);
+    assert_near(&yawed.gaze_direct, &vector3([-1.0, 0.0, 0.0]), 1e-4);
+    let pitched = camera.pitch(0.3);
+    assert!((pitched.eye_position.norm() - 3.0).abs() < 1e-4);
+    assert_near(
+        &pitched.gaze_direct,
+        &-&pitched.eye_position.normalized(),
+        1e-4,
+    );
+    assert!(pitched.up_direct.dot(&pitched.gaze_direct).abs() < 1e-4);
+    assert_near(
+        &camera.zoom(0.5).eye_position,
+        &vector3([0.0, 0.0, 2.5]),
+        1e-4,
+    );
+
+    // Both windows move their cameras the same way.
+    let mut renderer = Renderer::default()
+        .camera(camera.clone())
+        .shader("phong-color", "")
+        .unwrap();
+    let mut ray_tracer = RayTracer::from_models(&Model::from_obj("static/cube.obj"), 4, 4, 1);
+    ray_tracer.camera = camera;
+    renderer.yaw_camera(0.2);
+    renderer.pitch_camera(-0.1);
+    renderer.zoom_camera(0.4);
+    ray_tracer.yaw_camera(0.2);
+    ray_tracer.pitch_camera(-0.1);
+    ray_tracer.zoom_camera(0.4);
+    let moved = renderer.camera.unwrap();
+    assert_near(&moved.eye_position, &ray_tracer.camera.eye_position, 1e-4);
+    assert_near(&moved.up_direct, &ray_tracer.camera.up_direct, 1e-4);
+}
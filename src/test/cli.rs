@@ -0,0 +1,252 @@
+use crate::{
+    algebra::vector_new::vector3,
+    cli::{info, parse, run, Command, RenderOptions},
+    pipeline::{
+        camera::{Bokeh, Camera},
+        framebuffer::{ToneMapping, ToneMappingOperator},
+        model::Model,
+        rasterizer::Multisample,
+    },
+    ray_tracing::{adaptive::AdaptiveSampling, filter::Filter},
+    scene::Scene,
+};
+use std::{path::Path, time::Duration};
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+fn render_options(line: &str) -> RenderOptions {
+    match parse(&args(line)) {
+        Ok(Command::View(options)) | Ok(Command::Render(options)) => options,
+        other => panic!("`{}` parsed as {:?}", line, other),
+    }
+}
+
+#[test]
+fn parse_defaults() {
+    let options = render_options("view static/spot.obj");
+    assert_eq!(options.shader, "phong-color");
+    assert_eq!((options.width, options.height), (800, 800));
+    assert_eq!(options.multisample, Multisample::X1);
+    assert!(options.shadow);
+
+    assert_eq!(render_options("view static/bunny.gltf").shader, "pbr");
+    assert_eq!(render_options("view scene.json").shader, "phong-color");
+    assert_eq!(parse(&args("render --help")), Ok(Command::Help));
+}
+
+#[test]
+fn parse_render_options() {
+    let options = render_options(
+        "render static/earth.obj -s texture -t static/earth.jpg -o out.png --size 320x240 \
+         --eye 1,-2,3 --fov 60 --light 0,5,0 --light-intensity 50 --msaa 4 --no-shadow \
+         --tone-mapping aces --exposure 2",
+    );
+    assert_eq!(options.shader, "texture");
+    assert_eq!(options.texture.as_deref(), Some("static/earth.jpg"));
+    assert_eq!(options.output.as_deref(), Some("out.png"));
+    assert_eq!((options.width, options.height), (320, 240));
+    assert_eq!(options.eye, Some(vector3([1.0, -2.0, 3.0])));
+    assert_eq!(options.light_intensity, Some(50.0));
+    assert_eq!(options.multisample, Multisample::X4);
+    assert!(!options.shadow);
+    assert_eq!(
+        options.tone_mapping,
+        ToneMapping::new(ToneMappingOperator::AcesFilmic).exposure(2.0)
+    );
+
+    let camera = options.camera(Camera::default());
+    assert_eq!(camera.eye_position, vector3([1.0, -2.0, 3.0]));
+    assert!((camera.eye_fov - 60f32.to_radians()).abs() < 1e-6);
+    assert!((camera.aspect_ratio - 320.0 / 240.0).abs() < 1e-6);
+
+    let options = render_options("render static/bunny.gltf --output out.ppm --spp 32 --camera top");
+    assert_eq!(options.spp, 32);
+    assert_eq!(options.camera.as_deref(), Some("top"));
+    assert_eq!((options.sampler.as_str(), options.seed), ("sobol", 0));
+
+    let options = render_options("render static/bunny.gltf -o out.png --sampler Halton --seed 7");
+    assert_eq!((options.sampler.as_str(), options.seed), ("halton", 7));
+    assert_eq!(options.filter(), Filter::Box { radius: 0.5 });
+
+    assert_eq!(options.adaptive(), None);
+
+    let options = render_options(
+        "render static/bunny.gltf -o out.png --adaptive 0.05 --spp 4 --max-spp 256 \
+         --time-budget 30 --heatmap heat.png",
+    );
+    assert_eq!(
+        options.adaptive(),
+        Some(
+            AdaptiveSampling::default()
+                .target_error(0.05)
+                .min_spp(4)
+                .max_spp(256)
+                .time_budget(Duration::from_secs(30))
+        )
+    );
+    assert_eq!(options.heatmap.as_deref(), Some("heat.png"));
+
+    let options =
+        render_options("render static/bunny.gltf -o out.png --filter-radius 3 --filter gaussian");
+    assert_eq!(
+        options.filter(),
+        Filter::Gaussian {
+            radius: 3.0,
+            sigma: 0.5
+        }
+    );
+
+    let options = render_options(
+        "render static/bunny.gltf -o out.png --aperture 0.1 --focus-pixel 10,20 --bokeh 6",
+    );
+    assert_eq!(options.aperture, Some(0.1));
+    assert_eq!(options.focus_pixel, Some((10, 20)));
+    assert_eq!(
+        options.bokeh,
+        Bokeh::Polygon {
+            blades: 6,
+            rotation: 0.0
+        }
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let error = |line: &str| parse(&args(line)).unwrap_err();
+
+    assert!(error("").contains("Missing command"));
+    assert!(error("draw static/spot.obj").contains("Unknown command"));
+    assert!(error("view").contains("needs a model file"));
+    assert!(error("view static/spot").contains("Unsupported model file"));
+    assert!(error("view static/spot.obj --size 800").contains("Invalid size"));
+    assert!(error("view static/spot.obj --size").contains("Missing value"));
+    assert!(error("view static/spot.obj --msaa 3").contains("Unsupported sample count"));
+    assert!(error("view static/spot.obj --shader normal").contains("Unknown shader"));
+    assert!(error("view static/spot.obj --shader texture").contains("needs a texture"));
+    assert!(error("view static/spot.obj --texture spot.bmp").contains("Unsupported texture"));
+    assert!(error("view static/spot.obj --eye 1,2").contains("expected x,y,z"));
+    assert!(error("view static/spot.obj --fov 180").contains("between 0 and 180"));
+    assert!(error("view static/spot.obj --fov NaN").contains("between 0 and 180"));
+    assert!(error("view static/spot.obj --light-intensity -1").contains("non-negative"));
+    assert!(error("view static/spot.obj --light-intensity inf").contains("non-negative"));
+    assert!(error("view static/spot.obj --exposure 0").contains("positive"));
+    assert!(error("view static/spot.obj --exposure NaN").contains("positive"));
+    assert!(error("view static/spot.obj --colour red").contains("Unknown option"));
+    assert!(error("view static/spot.obj -o out.png").contains("use `render`"));
+    assert!(error("render static/spot.obj").contains("needs an output file"));
+    assert!(error("render static/spot.obj -o out.bmp").contains("Unsupported output"));
+    assert!(error("render static/spot.obj -o out.png -s pbr").contains("needs a glTF model"));
+    assert!(error("render static/bunny.gltf -o out.png --light 0,0,1").contains("isn't supported"));
+    assert!(error("render static/bunny.gltf -o out.png --spp 0").contains("at least 1"));
+    assert!(
+        error("render static/bunny.gltf -o out.png --sampler random").contains("Unknown sampler")
+    );
+    assert!(error("render static/bunny.gltf -o out.png --filter sinc").contains("Unknown filter"));
+    assert!(
+        error("render static/bunny.gltf -o out.png --max-spp 64").contains("needs `--adaptive`")
+    );
+    assert!(error("view static/bunny.gltf --adaptive 0.05").contains("needs `render`"));
+    assert!(
+        error("view static/bunny.gltf --shader pbr --adaptive 0.02 --heatmap h.png")
+            .contains("needs `render` and `--output`")
+    );
+    for budget in ["-4", "0", "NaN"] {
+        let args = format!(
+            "render static/bunny.gltf -o out.png --adaptive 0.05 --sample-budget {}",
+            budget
+        );
+        assert!(error(&args).contains("must be positive"), "{}", budget);
+    }
+    assert!(
+        error("render static/bunny.gltf -o out.png --adaptive 0.05 --spp 32 --max-spp 16")
+            .contains("below `--spp`")
+    );
+    assert!(
+        error("render static/bunny.gltf -o out.png --filter-radius 0.1").contains("at least 0.5")
+    );
+    assert!(
+        error("render static/bunny.gltf -o out.png --filter-radius inf").contains("at least 0.5")
+    );
+    assert!(error("render static/spot.obj -o out.png --aperture 0.1").contains("pbr shader"));
+    assert!(error("render static/bunny.gltf -o out.png --focus 2").contains("needs `--aperture`"));
+    assert!(error("render static/bunny.gltf -o out.png --aperture -1").contains("non-negative"));
+    assert!(error("render static/bunny.gltf -o out.png --aperture NaN").contains("non-negative"));
+    assert!(error("render static/bunny.gltf -o out.png --focus 0").contains("positive"));
+    assert!(error("render static/bunny.gltf -o out.png --focus inf").contains("positive"));
+    assert!(
+        error("render static/bunny.gltf -o out.png --aperture 0.1 --bokeh 2")
+            .contains("Invalid bokeh")
+    );
+    assert!(
+        error("render static/bunny.gltf -o out.png --aperture 0.1 --focus-pixel 800,0")
+            .contains("outside")
+    );
+    assert!(error("convert static/spot.obj out.gltf").contains("Unsupported output"));
+    assert!(error("info static/spot.obj static/cube.obj").contains("Unexpected argument"));
+}
+
+#[test]
+fn load_errors() {
+    assert!(Model::load("static/missing.obj")
+        .unwrap_err()
+        .contains("Cannot find"));
+    assert!(Model::load("static/earth.jpg")
+        .unwrap_err()
+        .contains("Unsupported model"));
+
+    let texture = std::env::temp_dir().join("tiny-renderer-broken.png");
+    std::fs::write(&texture, "not an image").unwrap();
+    for shader in ["texture", "phong-texture"] {
+        let line = format!(
+            "render static/spot.obj -o out.png --shader {} --texture {}",
+            shader,
+            texture.display()
+        );
+        let error = run(parse(&args(&line)).unwrap()).unwrap_err();
+        assert!(error.contains("Decode"), "{}", error);
+    }
+    std::fs::remove_file(&texture).unwrap();
+}
+
+#[test]
+fn convert_to_obj() {
+    let models = Model::load("static/cube.obj").unwrap();
+    let path = std::env::temp_dir().join("tiny-renderer-convert.obj");
+    std::fs::write(&path, Model::to_obj(&models)).unwrap();
+    let converted = Model::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(converted.len(), models.len());
+    for (a, b) in converted.iter().zip(models.iter()) {
+        assert_eq!(a.indices.len(), b.indices.len());
+        let positions = |m: &Model| {
+            m.indices
+                .iter()
+                .flat_map(|f| f.map(|i| m.vertexs[i as usize].position.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(a), positions(b));
+    }
+}
+
+#[test]
+fn info_reports_the_bvh_of_every_instance() {
+    let bvh_nodes = |instances: &str| {
+        let json = format!(
+            r#"{{ "models": [{{ "path": "cube.obj", "instances": [{}] }}] }}"#,
+            instances
+        );
+        let scene = Scene::from_json(&json, Path::new("static")).unwrap();
+        let info = info("scene.json", &scene);
+        let line = info.lines().find(|l| l.starts_with("BVH: ")).unwrap();
+        line["BVH: ".len()..line.find(" nodes").unwrap()]
+            .parse::<usize>()
+            .unwrap()
+    };
+    let one = bvh_nodes(r#"{ "translation": [-3.0, 0.0, 0.0] }"#);
+    let two =
+        bvh_nodes(r#"{ "translation": [-3.0, 0.0, 0.0] }, { "translation": [3.0, 0.0, 0.0] }"#);
+    assert!(two > one, "{} nodes for two cubes, {} for one", two, one);
+}
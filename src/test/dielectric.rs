@@ -0,0 +1,167 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    pipeline::{
+        material::{
+            fresnel_dielectric, refract, BSDFSample, IlluminateType, MaterialNew, PBRMaterial,
+        },
+        model::Model,
+    },
+    ray_tracing::{path_tracing::RayTracer, ray::Ray},
+    scene::Scene,
+    test::assert_near,
+};
+use rand::Rng;
+use std::{f32::consts::PI, path::Path, sync::Arc};
+
+fn random_sample(material: &PBRMaterial, wo: &Vector3, n: &Vector3) -> Option<BSDFSample> {
+    let mut rng = rand::thread_rng();
+    material.sample(wo, n, rng.gen(), (rng.gen(), rng.gen()))
+}
+
+fn glass(roughness: f32) -> PBRMaterial {
+    PBRMaterial::new(Vector3::from(1.0), 0.0, roughness, 1.5).transmission(1.0)
+}
+
+#[test]
+fn fresnel_and_snell() {
+    assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+    assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-4);
+    assert_eq!(fresnel_dielectric(0.0, 1.5), 1.0);
+    // Beyond the critical angle of glass, about 41.8 degrees.
+    assert_eq!(fresnel_dielectric(45f32.to_radians().cos(), 1.0 / 1.5), 1.0);
+
+    let n = vector3([0.0, 1.0, 0.0]);
+    let wo = vector3([30f32.to_radians().sin(), 30f32.to_radians().cos(), 0.0]);
+    let wi = refract(&wo, &n, 1.5).unwrap();
+    assert!(wi.y() < 0.0);
+    assert!((-wi.x() * 1.5 - wo.x()).abs() < 1e-5);
+    // Leaving the glass goes back the same way.
+    assert_near(&refract(&wi, &-&n, 1.0 / 1.5).unwrap(), &wo, 1e-4);
+    let grazing = vector3([60f32.to_radians().sin(), 60f32.to_radians().cos(), 0.0]);
+    assert!(refract(&grazing, &n, 1.0 / 1.5).is_none());
+}
+
+#[test]
+fn smooth_glass_reflects_and_refracts() {
+    let material = glass(0.0);
+    let n = vector3([0.0, 1.0, 0.0]);
+    let samples = (0..2000)
+        .filter_map(|_| random_sample(&material, &n, &n))
+        .collect::<Vec<_>>();
+    assert_eq!(samples.len(), 2000);
+    assert!(samples.iter().all(|s| s.delta));
+    let reflected = samples.iter().filter(|s| s.wi.y() > 0.0).count();
+    assert!((reflected as f32 / 2000.0 - 0.04).abs() < 0.02);
+    let refracted = samples.iter().find(|s| s.wi.y() < 0.0).unwrap();
+    assert_near(&refracted.wi, &-&n, 1e-4);
+    assert_near(&refracted.weight, &Vector3::from(1.0 / 2.25), 1e-4);
+    // Smooth directions have no density.
+    assert_eq!(material.pdf(&-&n, &n, &n), 0.0);
+
+    // From the inside at a grazing angle the light stays in the glass.
+    let wo = vector3([60f32.to_radians().sin(), -(60f32.to_radians().cos()), 0.0]);
+    for _ in 0..100 {
+        let sample = random_sample(&material, &wo, &n).unwrap();
+        assert_near(&sample.wi, &vector3([-wo.x(), wo.y(), 0.0]), 1e-4);
+        assert_near(&sample.weight, &Vector3::from(1.0), 1e-4);
+    }
+}
+
+#[test]
+fn rough_glass_pdf_matches_samples() {
+    let material = glass(0.7);
+    let n = vector3([0.0, 1.0, 0.0]);
+    let mut rng = rand::thread_rng();
+    for wo in [
+        vector3([0.5, 0.75f32.sqrt(), 0.0]),
+        vector3([0.0, -1.0, 0.0]),
+    ] {
+        const N: usize = 100000;
+        let mut integral = 0.0;
+        let mut reflected = 0;
+        let mut missed = 0;
+        for _ in 0..N {
+            // The pdf integrates over the sphere to the share of valid samples.
+            let z = rng.gen_range(-1.0f32..1.0);
+            let phi = rng.gen_range(0.0..2.0 * PI);
+            let r = (1.0 - z * z).sqrt();
+            let wi = vector3([r * phi.cos(), z, r * phi.sin()]);
+            integral += material.pdf(&wi, &wo, &n) * 4.0 * PI / N as f32;
+
+            match random_sample(&material, &wo, &n) {
+                Some(sample) => {
+                    assert!(!sample.delta);
+                    let expected = material.eval(&sample.wi, &wo, &n, IlluminateType::Direct)
+                        * (sample.wi.dot(&n).abs() / sample.pdf);
+                    assert_near(&sample.weight, &expected, 1e-4);
+                    if sample.wi.dot(&n) * wo.dot(&n) > 0.0 {
+                        reflected += 1;
+                    }
+                }
+                None => missed += 1,
+            }
+        }
+        let valid = 1.0 - missed as f32 / N as f32;
+        assert!((integral - valid).abs() < 0.05, "{} != {}", integral, valid);
+        // Both lobes are sampled.
+        assert!(reflected > 0 && reflected < N - missed);
+    }
+}
+
+#[test]
+fn glass_from_gltf_and_scene_files() {
+    let models = Model::load("static/glass.gltf").unwrap();
+    let glass = models[1].material.as_ref().unwrap().pbr_material().unwrap();
+    assert_eq!(glass.transmission, 1.0);
+    assert_eq!(glass.refraction, 1.5);
+    assert_eq!(
+        models[0]
+            .material
+            .as_ref()
+            .unwrap()
+            .pbr_material()
+            .unwrap()
+            .transmission,
+        0.0
+    );
+
+    let json = r#"{ "models": [{ "path": "cube.obj", "material": { "transmission": 0.8, "ior": 1.33 } }] }"#;
+    let scene = Scene::from_json(json, Path::new("static")).unwrap();
+    let water = scene.models[0]
+        .material
+        .as_ref()
+        .unwrap()
+        .pbr_material()
+        .unwrap();
+    assert_eq!((water.transmission, water.refraction), (0.8, 1.33));
+}
+
+#[test]
+fn path_tracer_sees_through_glass() {
+    // A glass cube floating in a uniform background, nothing else.
+    let models = Model::load("static/cube.obj")
+        .unwrap()
+        .into_iter()
+        .map(|mut model| {
+            model.material = Some(Arc::new(MaterialNew::PBR(glass(0.0))));
+            model
+        })
+        .collect::<Vec<_>>();
+    let ray_tracer = RayTracer::from_models(&models, 4, 4, 1);
+    let background = ray_tracer.background_color.clone();
+    let mean = |ray: &Ray| {
+        const N: usize = 4000;
+        let mut sampler = ray_tracer.sampler.clone_sampler();
+        (0..N).fold(Vector3::new(), |sum, i| {
+            sampler.start_pixel_sample(0, 0, i);
+            sum + ray_tracer.shade(ray, sampler.as_mut())
+        }) / N as f32
+    };
+
+    // Smooth glass neither adds nor loses energy.
+    let outside = Ray::new(&vector3([0.3, 0.2, 5.0]), &vector3([0.0, 0.0, -1.0]));
+    assert!((mean(&outside).x() / background.x() - 1.0).abs() < 0.05);
+    // Radiance inside glass is higher by the IOR squared.
+    let inside = Ray::new(&vector3([0.1, 0.2, 0.0]), &vector3([0.3, 1.0, 0.2]));
+    assert!((mean(&inside).x() / background.x() - 2.25).abs() < 0.15);
+}
@@ -0,0 +1,74 @@
+use crate::algebra::vector_new::vector3;
+use crate::ray_tracing::filter::{Filter, PixelSum};
+
+const NAMES: [&str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+#[test]
+fn filters_fall_off_to_the_radius() {
+    for name in NAMES {
+        let filter = name.parse::<Filter>().unwrap();
+        let r = filter.radius();
+        assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", name);
+        assert_eq!(filter.evaluate(r + 0.01, 0.0), 0.0, "{}", name);
+        assert_eq!(filter.evaluate(0.0, -r - 0.01), 0.0, "{}", name);
+        assert_eq!(
+            filter.evaluate(0.3, -0.2),
+            filter.evaluate(-0.3, 0.2),
+            "{}",
+            name
+        );
+        // Separable, the product of both axes.
+        let product = filter.evaluate(0.3, 0.0) * filter.evaluate(0.0, 0.2);
+        let center = filter.evaluate(0.0, 0.0);
+        assert!(
+            (filter.evaluate(0.3, 0.2) * center - product).abs() < 1e-6,
+            "{}",
+            name
+        );
+    }
+    assert!("Sinc".parse::<Filter>().is_err());
+    assert_eq!("Box".parse::<Filter>(), Ok(Filter::default()));
+
+    assert_eq!(Filter::default().evaluate(0.5, -0.5), 1.0);
+    let tent = Filter::Tent { radius: 1.0 };
+    assert_eq!(tent.evaluate(0.5, 0.0), 0.5);
+    let gaussian = "gaussian".parse::<Filter>().unwrap();
+    assert_eq!(gaussian.evaluate(gaussian.radius(), 0.0), 0.0);
+    assert_eq!(gaussian.clone().with_radius(3.0).radius(), 3.0);
+    assert_eq!(Filter::Box { radius: 0.1 }.radius(), 0.5);
+
+    // Negative lobes sharpen the image.
+    let mitchell = "mitchell".parse::<Filter>().unwrap();
+    let lanczos = "lanczos".parse::<Filter>().unwrap();
+    assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+    assert!(lanczos.evaluate(1.5, 0.0) < 0.0);
+    // With B + 2C = 1 the Mitchell cubic integrates to one over its radius of 2.
+    const STEPS: usize = 400;
+    let step = 4.0 / STEPS as f32;
+    let integral = (0..STEPS * STEPS)
+        .map(|i| {
+            let x = ((i % STEPS) as f32 + 0.5) * step - 2.0;
+            let y = ((i / STEPS) as f32 + 0.5) * step - 2.0;
+            mitchell.evaluate(x, y) * step * step
+        })
+        .sum::<f32>();
+    assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+}
+
+#[test]
+fn pixel_sums_are_weighted_means() {
+    let mut sum = PixelSum::default();
+    assert_eq!(sum.mean(), vector3([0.0; 3]));
+    sum.add(&vector3([1.0, 2.0, 3.0]), 3.0);
+    let mut other = PixelSum::default();
+    other.add(&vector3([5.0, 6.0, 7.0]), 1.0);
+    sum.merge(&other);
+    assert_eq!(sum.weight, 4.0);
+    assert_eq!(sum.mean(), vector3([2.0, 3.0, 4.0]));
+
+    // Negative lobes cancelling the positive weight leave the pixel black.
+    let mut cancelled = PixelSum::default();
+    cancelled.add(&vector3([1.0, 1.0, 1.0]), 0.25);
+    cancelled.add(&vector3([3.0, 3.0, 3.0]), -0.25);
+    assert_eq!(cancelled.mean(), vector3([0.0; 3]));
+}
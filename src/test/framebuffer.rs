@@ -0,0 +1,62 @@
+use crate::{
+    algebra::vector_new::{vector3, vector4},
+    pipeline::{
+        color::{linear_to_srgb, srgb_to_linear},
+        framebuffer::{Framebuffer, ToneMapping, ToneMappingOperator},
+    },
+};
+
+#[test]
+fn srgb_round_trip() {
+    for i in 0..=255u8 {
+        let v = i as f32 / 255.0;
+        assert!((linear_to_srgb(srgb_to_linear(v)) - v).abs() < 1e-5);
+    }
+    assert_eq!(linear_to_srgb(0.0), 0.0);
+    assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn tone_mapping_operators() {
+    let hdr = vector3([1.0, 4.0, 0.0]);
+
+    let exposure = ToneMapping::default().exposure(0.5).map(&hdr);
+    assert_eq!(exposure, vector3([0.5, 2.0, 0.0]));
+
+    let reinhard = ToneMapping::new(ToneMappingOperator::Reinhard).map(&hdr);
+    assert_eq!(reinhard, vector3([0.5, 0.8, 0.0]));
+
+    let aces = ToneMapping::new(ToneMappingOperator::AcesFilmic).map(&hdr);
+    assert!(aces.x() > 0.0 && aces.x() < aces.y() && aces.y() < 1.0);
+    assert_eq!(aces.z(), 0.0);
+}
+
+#[test]
+fn encode_once_on_output() {
+    let mut framebuffer = Framebuffer::new(2, 1, &vector4([0.0, 0.0, 0.0, 1.0]));
+    framebuffer.data[1] = vector4([0.5, 2.0, 1.0, 1.0]);
+    let colors = framebuffer.to_colors(&ToneMapping::default());
+
+    assert_eq!((colors[0].r, colors[0].g, colors[0].b), (0, 0, 0));
+    // Linear 0.5 encodes to sRGB 188, overexposed channels clamp to white.
+    assert_eq!((colors[1].r, colors[1].g, colors[1].b), (188, 255, 255));
+}
+
+#[test]
+fn save_image_files() {
+    let mut framebuffer = Framebuffer::new(3, 2, &vector4([0.0, 0.0, 0.0, 1.0]));
+    framebuffer.data[1] = vector4([1.0, 0.0, 0.0, 1.0]);
+    framebuffer.data[5] = vector4([0.0, 0.0, 1.0, 1.0]);
+
+    for extension in ["png", "ppm"] {
+        let path = std::env::temp_dir().join(format!("tiny-renderer-save.{}", extension));
+        framebuffer.save(&path, &ToneMapping::default()).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(2, 1).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [0, 0, 0]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    pipeline::{
+        camera::Camera,
+        light::{Light, LightKind},
+        material::{IlluminateType, MaterialNew},
+        scene_graph::SceneGraph,
+    },
+    ray_tracing::path_tracing::RayTracer,
+    renderer::Renderer,
+    scene::Scene,
+    test::assert_near,
+};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+#[test]
+fn import_punctual_lights() {
+    let graph = SceneGraph::load("static/lights.gltf").unwrap();
+    let lights = graph.lights();
+    assert_eq!(lights.len(), 3);
+
+    let lamp = &lights[0];
+    assert_eq!(lamp.kind, LightKind::Point);
+    assert_near(&lamp.position, &vector3([0.0, 2.0, 0.0]), 1e-4);
+    assert_near(&lamp.color, &vector3([1.0, 0.9, 0.8]), 1e-4);
+    assert_eq!(lamp.intensity, 20.0);
+    assert_eq!(lamp.range, Some(10.0));
+
+    // Placed by its parent node and turned to look down.
+    let spot = &lights[1];
+    assert_eq!(
+        spot.kind,
+        LightKind::Spot {
+            inner_cone_angle: 0.3,
+            outer_cone_angle: 0.5
+        }
+    );
+    assert_near(&spot.position, &vector3([2.0, 1.5, 0.0]), 1e-4);
+    assert_near(&spot.direction, &vector3([0.0, -1.0, 0.0]), 1e-4);
+
+    let sun = &lights[2];
+    assert_eq!(sun.kind, LightKind::Directional);
+    assert_near(
+        &sun.direction,
+        &vector3([0.0, -FRAC_1_SQRT_2, -FRAC_1_SQRT_2]),
+        1e-4,
+    );
+    assert_eq!(sun.range, None);
+}
+
+#[test]
+fn light_falloff() {
+    let point = Light::default()
+        .position(vector3([0.0, 2.0, 0.0]))
+        .intensity(8.0)
+        .color(vector3([1.0, 0.5, 0.0]));
+    let (l, distance, radiance) = point.incident(&Vector3::new());
+    assert_near(&l, &vector3([0.0, 1.0, 0.0]), 1e-4);
+    assert_eq!(distance, 2.0);
+    assert_near(&radiance, &vector3([2.0, 1.0, 0.0]), 1e-4);
+
+    // Fades out smoothly before the range.
+    let ranged = point.clone().range(Some(4.0));
+    let (_, _, near) = ranged.incident(&Vector3::new());
+    assert!(near.x() < 2.0 && near.x() > 1.5);
+    let (_, _, far) = ranged.incident(&vector3([0.0, -3.0, 0.0]));
+    assert_eq!(far, Vector3::new());
+
+    let spot = point
+        .clone()
+        .direction(vector3([0.0, -1.0, 0.0]))
+        .kind(LightKind::Spot {
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        });
+    let radiance_at = |x: f32| spot.incident(&vector3([x, 0.0, 0.0])).2.x();
+    let inner = 2.0 * 0.2f32.tan();
+    let outer = 2.0 * 0.4f32.tan();
+    assert!((radiance_at(0.0) - 2.0).abs() < 1e-4);
+    assert!(radiance_at(inner * 0.9) > 0.0);
+    let between = radiance_at((inner + outer) / 2.0);
+    assert!(between > 0.0 && between < radiance_at(inner * 0.9));
+    assert_eq!(radiance_at(outer * 1.1), 0.0);
+
+    let sun = Light::default()
+        .kind(LightKind::Directional)
+        .direction(vector3([0.0, -1.0, 0.0]))
+        .intensity(3.0);
+    let (l, distance, radiance) = sun.incident(&vector3([10.0, 0.0, -5.0]));
+    assert_near(&l, &vector3([0.0, 1.0, 0.0]), 1e-4);
+    assert_eq!(distance, f32::INFINITY);
+    assert_near(&radiance, &Vector3::from(3.0), 1e-4);
+}
+
+#[test]
+fn path_tracer_samples_punctual_lights() {
+    let graph = SceneGraph::load("static/lights.gltf").unwrap();
+    let ray_tracer = RayTracer::from_gltf("static/lights.gltf", 4, 4, 1).unwrap();
+    assert_eq!(ray_tracer.lights.len(), 3);
+
+    let lamp = graph.lights()[0].clone();
+    let ray_tracer = RayTracer::from_scene(
+        &Scene {
+            lights: vec![lamp.clone()],
+            ..Scene::from(&graph)
+        },
+        4,
+        4,
+        1,
+    );
+    let material = match ray_tracer.objects[0].material.as_deref() {
+        Some(MaterialNew::PBR(m)) => m.clone(),
+        other => panic!("Unexpected material {:?}", other),
+    };
+    let n = vector3([0.0, 1.0, 0.0]);
+    let wo = vector3([0.0, 1.0, 0.0]);
+
+    // A lit point of the floor.
+    let p = vector3([2.0, -1.0, 1.0]);
+    let (l, _, radiance) = lamp.incident(&p);
+    let expected = material
+        .eval(&l, &wo, &n, IlluminateType::Direct)
+        .cwise_product(&radiance)
+        * l.dot(&n);
+    assert_near(
+        &ray_tracer.sample_punctual_lights(&p, &n, &wo, &material),
+        &expected,
+        1e-4,
+    );
+
+    // The box stands between the lamp and this point.
+    let p = vector3([0.7, -1.0, 0.0]);
+    assert_eq!(
+        ray_tracer.sample_punctual_lights(&p, &n, &wo, &material),
+        Vector3::new()
+    );
+}
+
+#[test]
+fn rasterizer_uses_every_light() {
+    let scene = Scene::from(&SceneGraph::load("static/lights.gltf").unwrap());
+    let renderer = Renderer::default().scene(&scene);
+    assert_eq!(
+        renderer.light.as_ref().unwrap().position,
+        scene.lights[0].position
+    );
+    assert_eq!(renderer.fill_lights.len(), 2);
+
+    let brightness = |lights: Vec<Light>| {
+        let scene = Scene {
+            lights,
+            ..scene.clone()
+        };
+        let framebuffer = Renderer::default()
+            .scene(&scene)
+            .camera(
+                Camera::default()
+                    .eye_position(vector3([0.0, 3.0, 6.0]))
+                    .gaze_direct(vector3([0.0, -0.5, -1.0]).normalized()),
+            )
+            .shader("pbr-raster", "")
+            .unwrap()
+            .render_framebuffer(32, 32);
+        Vector3::from(framebuffer.get(16, 20)).norm()
+    };
+    let key = brightness(scene.lights[..1].to_vec());
+    assert!(brightness(scene.lights.clone()) > key);
+}
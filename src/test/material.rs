@@ -0,0 +1,155 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    pipeline::{
+        camera::Camera,
+        light::Light,
+        material::{MaterialNew, PBRMaterial, PBRTextures},
+        model::Model,
+        texture::Texture,
+    },
+    ray_tracing::path_tracing::HitResult,
+    renderer::Renderer,
+    test::assert_near,
+};
+use image::{DynamicImage, ImageBuffer};
+use std::sync::Arc;
+
+fn textured_cube() -> (Model, PBRMaterial) {
+    let model = Model::from_gltf("static/textured-cube.gltf").remove(0);
+    let material = model
+        .material
+        .as_ref()
+        .unwrap()
+        .pbr_material()
+        .unwrap()
+        .clone();
+    (model, material)
+}
+
+fn pixel_texture(rgb: [u8; 3]) -> Arc<Texture> {
+    let image = ImageBuffer::from_raw(1, 1, rgb.to_vec()).unwrap();
+    Arc::new(Texture::from_image(DynamicImage::ImageRgb8(image)))
+}
+
+#[test]
+fn load_gltf_textures() {
+    let (model, material) = textured_cube();
+    assert!(model.vertexs.iter().all(|v| v.texture_coordinate.is_some()));
+    let textures = &material.textures;
+    // The base color is an external file, the others are embedded.
+    assert!(textures.base_color.is_some());
+    assert!(textures.metallic_roughness.is_some());
+    assert!(textures.normal.is_some());
+    assert!(textures.occlusion.is_some());
+    assert!(textures.emissive.is_none());
+
+    // Red, green, blue and white quadrants, uv starts at the bottom left.
+    assert_near(
+        &material.textured((0.25, 0.75)).albedo,
+        &vector3([1.0, 0.0, 0.0]),
+        1e-2,
+    );
+    assert_near(
+        &material.textured((0.75, 0.75)).albedo,
+        &vector3([0.0, 1.0, 0.0]),
+        1e-2,
+    );
+    assert_near(
+        &material.textured((0.25, 0.25)).albedo,
+        &vector3([0.0, 0.0, 1.0]),
+        1e-2,
+    );
+    assert_near(
+        &material.textured((1.75, -0.75)).albedo,
+        &vector3([1.0, 1.0, 1.0]),
+        1e-2,
+    );
+
+    let textured = material.textured((0.5, 0.5));
+    assert!((textured.metalness - 1.0).abs() < 1e-2);
+    assert!((textured.roughness - 0.5).abs() < 1e-2);
+    assert!((material.occlusion((0.5, 0.5)) - 0.5).abs() < 1e-2);
+    let n = vector3([0.0, 0.0, 1.0]);
+    let tangents = (vector3([1.0, 0.0, 0.0]), vector3([0.0, 1.0, 0.0]));
+    assert_near(
+        &material.shading_normal((0.5, 0.5), &n, Some(&tangents)),
+        &n,
+        1e-2,
+    );
+}
+
+#[test]
+fn normal_texture() {
+    let material = PBRMaterial::new(Vector3::from(0.8), 0.0, 0.5, 1.5).textures(PBRTextures {
+        normal: Some(pixel_texture([128, 255, 128])),
+        ..PBRTextures::default()
+    });
+    let n = vector3([0.0, 0.0, 1.0]);
+    // The texture's +Y follows dP/dv.
+    let tangents = (vector3([2.0, 0.0, 0.0]), vector3([0.0, 2.0, 0.0]));
+    assert_near(
+        &material.shading_normal((0.5, 0.5), &n, Some(&tangents)),
+        &vector3([0.0, 1.0, 0.0]),
+        1e-2,
+    );
+    let tangents = (vector3([2.0, 0.0, 0.0]), vector3([0.0, -2.0, 0.0]));
+    assert_near(
+        &material.shading_normal((0.5, 0.5), &n, Some(&tangents)),
+        &vector3([0.0, -1.0, 0.0]),
+        1e-2,
+    );
+    // Without uv derivatives the normal is kept.
+    assert_near(&material.shading_normal((0.5, 0.5), &n, None), &n, 1e-2);
+}
+
+#[test]
+fn path_tracer_samples_textures() {
+    let (model, material) = textured_cube();
+    let textures = PBRTextures {
+        emissive: Some(pixel_texture([255, 255, 255])),
+        ..material.textures.clone()
+    };
+    let material = material
+        .emission(vector3([2.0, 0.0, 0.0]))
+        .textures(textures);
+    let hit = HitResult {
+        position: vector3([0.5, 0.5, 1.0]),
+        normal: vector3([0.0, 0.0, 1.0]),
+        distance: 1.0,
+        emit: None,
+        material: Some(Arc::new(MaterialNew::PBR(material))),
+        texture_coordinate: Some((0.75, 0.75)),
+        tangents: model.triangles().first().unwrap().tangents(),
+    };
+    let (surface, n) = hit.surface();
+    assert_near(&surface.albedo, &vector3([0.0, 1.0, 0.0]), 1e-2);
+    assert_near(&surface.emission, &vector3([2.0, 0.0, 0.0]), 1e-2);
+    assert!((n.norm() - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn rasterizer_samples_textures() {
+    let (model, _) = textured_cube();
+    let renderer = Renderer::default()
+        .models(vec![model])
+        .camera(Camera::default().eye_position(vector3([0.0, 0.0, 5.0])))
+        .light(
+            Light::default()
+                .position(vector3([0.0, 0.0, 5.0]))
+                .intensity(50.0),
+        )
+        .shader("pbr-raster", "")
+        .unwrap();
+    let framebuffer = renderer.render_framebuffer(64, 64);
+    let color = |x, y| Vector3::from(framebuffer.get(x, y));
+    let dominant = |c: Vector3| {
+        let max = c.x().max(c.y()).max(c.z());
+        [c.x() == max, c.y() == max, c.z() == max]
+    };
+    // The +Z face shows the four quadrants of the base color texture.
+    assert_eq!(dominant(color(28, 28)), [true, false, false]);
+    assert_eq!(dominant(color(36, 28)), [false, true, false]);
+    assert_eq!(dominant(color(28, 36)), [false, false, true]);
+    let white = color(36, 36);
+    assert!(white.x() > 0.0 && (white.x() - white.z()).abs() < 1e-3);
+}
@@ -0,0 +1,205 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    pipeline::{
+        framebuffer::Framebuffer,
+        material::{IlluminateType, MaterialNew},
+    },
+    ray_tracing::{
+        filter::{Filter, PixelSum},
+        path_tracing::{power_heuristic, RayTracer},
+        ray::Ray,
+        sampler::IndependentSampler,
+    },
+    scene::Scene,
+    test::{assert_near, cube_in_light_box},
+};
+use std::{f32::consts::PI, path::Path};
+
+// A rough floor inside a big emissive box, so every direction above the floor sees the light.
+const FURNACE: &str = r#"{
+    "models": [
+        {
+            "path": "cube.obj",
+            "scale": [5.0, 5.0, 5.0],
+            "material": { "emission": [1.0, 0.5, 0.25], "intensity": 2.0 }
+        },
+        {
+            "path": "cube.obj",
+            "scale": [2.0, 0.01, 2.0],
+            "material": { "albedo": [0.8, 0.8, 0.8], "roughness": 0.5 }
+        }
+    ]
+}"#;
+
+const GREEN_LIGHT: &str = r#"{ "emission": [0.0, 1.0, 0.0], "intensity": 1.0 }"#;
+
+#[test]
+fn power_heuristic_weights() {
+    assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+    assert_eq!(power_heuristic(3.0, 1.0), 0.9);
+    assert_eq!(power_heuristic(3.0, 1.0) + power_heuristic(1.0, 3.0), 1.0);
+    assert_eq!(power_heuristic(2.0, 0.0), 1.0);
+    assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+}
+
+#[test]
+fn camera_sees_light_intensity() {
+    let scene = Scene::from_json(FURNACE, Path::new("static")).unwrap();
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    let ray = Ray::new(&vector3([0.0, 1.0, 0.0]), &vector3([0.3, 1.0, -0.2]));
+    let mut sampler = IndependentSampler::new(0);
+    assert_near(
+        &ray_tracer.shade(&ray, &mut sampler),
+        &vector3([2.0, 1.0, 0.5]),
+        1e-4,
+    );
+}
+
+#[test]
+fn light_and_bsdf_samples_converge() {
+    let scene = Scene::from_json(FURNACE, Path::new("static")).unwrap();
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    let material = match ray_tracer.objects[1].material.as_deref() {
+        Some(MaterialNew::PBR(m)) => m.clone(),
+        other => panic!("Unexpected material {:?}", other),
+    };
+    let ray = Ray::new(&vector3([0.0, 1.0, 1.0]), &vector3([0.0, -1.0, -0.8]));
+    let wo = -&ray.dir;
+    let n = vector3([0.0, 1.0, 0.0]);
+
+    // Emitted radiance times the integral of the BSDF over the hemisphere.
+    const STEPS: usize = 256;
+    let mut reflectance = Vector3::new();
+    for i in 0..STEPS {
+        let theta = (i as f32 + 0.5) / STEPS as f32 * PI / 2.0;
+        for j in 0..STEPS * 4 {
+            let phi = (j as f32 + 0.5) / (STEPS * 4) as f32 * 2.0 * PI;
+            let wi = vector3([
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ]);
+            let d_omega = theta.sin() * (PI / 2.0 / STEPS as f32) * (2.0 * PI / (STEPS * 4) as f32);
+            reflectance +=
+                material.eval(&wi, &wo, &n, IlluminateType::Direct) * (theta.cos() * d_omega);
+        }
+    }
+    let expected = vector3([2.0, 1.0, 0.5]).cwise_product(&reflectance);
+
+    const N: usize = 20000;
+    let mut sampler = ray_tracer.sampler.clone_sampler();
+    let mean = (0..N).fold(Vector3::new(), |sum, i| {
+        sampler.start_pixel_sample(0, 0, i);
+        sum + ray_tracer.shade(&ray, sampler.as_mut())
+    }) / N as f32;
+    assert!(
+        (mean.x() / expected.x() - 1.0).abs() < 0.03,
+        "{:?} != {:?}",
+        mean,
+        expected
+    );
+    assert!((mean.z() / expected.z() - 1.0).abs() < 0.03);
+}
+
+#[test]
+fn tiles_cover_any_resolution() {
+    // Only lights are in sight, so one sample per pixel gives exact colors.
+    let scene = cube_in_light_box(GREEN_LIGHT);
+    let (width, height) = (37, 23);
+    let mut ray_tracer = RayTracer::from_scene(&scene, width, height, 1);
+    let framebuffer = ray_tracer.render().clone();
+    assert_eq!(framebuffer.data.len(), width * height);
+    let mut sampler = IndependentSampler::new(0);
+    let mut shade =
+        |x: f32, y: f32| ray_tracer.shade(&ray_tracer.film_to_ray((x, y)), &mut sampler);
+    let mut checked = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, y0) = (x as f32, y as f32);
+            let expected = shade(x0 + 0.5, y0 + 0.5);
+            // Samples land anywhere in the pixel, only pixels inside one light have its color.
+            let corners = [(0.01, 0.01), (0.99, 0.01), (0.01, 0.99), (0.99, 0.99)];
+            if corners
+                .iter()
+                .all(|(dx, dy)| (&shade(x0 + dx, y0 + dy) - &expected).norm() < 1e-4)
+            {
+                assert_near(&Vector3::from(framebuffer.get(x, y)), &expected, 1e-4);
+                checked += 1;
+            }
+        }
+    }
+    assert!(checked > width * height * 3 / 4);
+    // The small cube is up and to the right.
+    let cube = Vector3::from(framebuffer.get(26, 8));
+    assert_near(&cube, &vector3([0.0, 1.0, 0.0]), 1e-4);
+}
+
+#[test]
+fn progressive_passes_restart_when_the_camera_moves() {
+    let scene = cube_in_light_box(GREEN_LIGHT);
+    let mut ray_tracer = RayTracer::from_scene(&scene, 24, 16, 4);
+    let rendered = ray_tracer.render().clone();
+    for _ in 0..4 {
+        ray_tracer.render_pass();
+    }
+    assert_eq!(ray_tracer.passes, 4);
+    for (a, b) in ray_tracer.framebuffer.data.iter().zip(rendered.data.iter()) {
+        assert_near(&Vector3::from(a), &Vector3::from(b), 1e-4);
+    }
+
+    ray_tracer.yaw_camera(0.5);
+    assert_eq!(ray_tracer.passes, 0);
+    assert!(ray_tracer
+        .accumulation
+        .iter()
+        .all(|c| *c == PixelSum::default()));
+    for _ in 0..4 {
+        ray_tracer.render_pass();
+    }
+    let passes = ray_tracer.framebuffer.clone();
+    let moved = ray_tracer.render().clone();
+    assert_ne!(moved.data, rendered.data);
+    for (a, b) in passes.data.iter().zip(moved.data.iter()) {
+        assert_near(&Vector3::from(a), &Vector3::from(b), 1e-4);
+    }
+}
+
+#[test]
+fn filters_splat_samples_around_pixels() {
+    let scene = cube_in_light_box(GREEN_LIGHT);
+    let mut ray_tracer = RayTracer::from_scene(&scene, 24, 16, 16);
+    let (orange, green) = (vector3([2.0, 1.0, 0.5]), vector3([0.0, 1.0, 0.0]));
+    // Pixels on the edges of the small cube, between both lights.
+    let edges = |framebuffer: &Framebuffer| {
+        framebuffer
+            .data
+            .iter()
+            .map(Vector3::from)
+            .filter(|c| (c - &orange).norm() > 1e-3 && (c - &green).norm() > 1e-3)
+            .count()
+    };
+    let boxed = ray_tracer.render().clone();
+    assert!(edges(&boxed) > 0);
+
+    for name in ["tent", "gaussian", "mitchell", "lanczos"] {
+        ray_tracer.filter = name.parse().unwrap();
+        let filtered = ray_tracer.render().clone();
+        // Weights are normalized, flat areas keep their color.
+        assert_near(&Vector3::from(filtered.get(2, 2)), &orange, 1e-4);
+        assert!(edges(&filtered) > edges(&boxed), "{}", name);
+    }
+}
+
+#[test]
+fn small_filters_reach_every_pixel() {
+    let scene = cube_in_light_box(GREEN_LIGHT);
+    let mut ray_tracer = RayTracer::from_scene(&scene, 24, 16, 4);
+    for filter in [Filter::Box { radius: 0.1 }, Filter::Tent { radius: 0.1 }] {
+        ray_tracer.filter = filter;
+        let framebuffer = ray_tracer.render().clone();
+        assert!(framebuffer
+            .data
+            .iter()
+            .all(|c| Vector3::from(c).norm() > 0.0));
+    }
+}
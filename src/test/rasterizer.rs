@@ -0,0 +1,173 @@
+use crate::{
+    algebra::vector_new::{vector4, Vector4},
+    pipeline::{
+        camera::Camera,
+        fragment_shader::{FragmentShader, FragmentShaderPayload},
+        light::Light,
+        model::{Triangle, TriangulatedModel, Vertex},
+        rasterizer::{Multisample, Rasterizer, BACKGROUND_COLOR, TILE_SIZE},
+        shadow_map::ShadowMap,
+        vertex_shader::Varyings,
+    },
+};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+fn screen_triangle(points: [(f32, f32, f32); 3]) -> Triangle {
+    let vertexs = points
+        .iter()
+        .map(|&(x, y, z)| Vertex {
+            position: vector4([x, y, z, 1.0]),
+            world_position: vector4([x, y, z, 1.0]),
+            normal: None,
+            texture_coordinate: None,
+            w_reciprocal: Some(1.0),
+            varyings: Varyings::new(),
+        })
+        .collect();
+    Triangle {
+        vertexs,
+        material: None,
+        area: 0.0,
+    }
+}
+
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+#[test]
+fn tiled_z_buffer() {
+    let (width, height) = (TILE_SIZE * 3 + 7, TILE_SIZE * 2 + 5);
+    let points = [
+        [(2.3, 3.7, -0.5), (90.6, 10.2, -0.5), (40.1, 60.9, -0.5)],
+        [(10.4, 50.3, -0.2), (100.7, 66.6, -0.2), (60.2, 1.1, -0.2)],
+    ];
+    let triangles = points.iter().map(|&p| screen_triangle(p)).collect();
+    let model = TriangulatedModel {
+        triangles,
+        material: None,
+        area: 0.0,
+    };
+    let rasterizer = Rasterizer::new(width, height, vec![model]);
+
+    for y in 0..height {
+        for x in 0..width {
+            let p = (x as f32, y as f32);
+            let expected = points
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| {
+                    let (a, b, c) = ((t[0].0, t[0].1), (t[1].0, t[1].1), (t[2].0, t[2].1));
+                    let (e0, e1, e2) = (edge(a, b, p), edge(b, c, p), edge(c, a, p));
+                    (e0 >= 0.0 && e1 >= 0.0 && e2 >= 0.0) || (e0 <= 0.0 && e1 <= 0.0 && e2 <= 0.0)
+                })
+                .map(|(i, t)| (i, -t[0].2))
+                .fold(
+                    None,
+                    |nearest: Option<(usize, f32)>, (i, z)| match nearest {
+                        Some((_, nearest_z)) if nearest_z <= z => nearest,
+                        _ => Some((i, z)),
+                    },
+                );
+
+            let item = &rasterizer.z_buffer[y * width + x];
+            match expected {
+                Some((triangle_index, z)) => {
+                    assert_eq!(item.triangle_index, triangle_index, "pixel ({}, {})", x, y);
+                    assert!((item.z - z).abs() < 1e-5, "pixel ({}, {})", x, y);
+                }
+                None => assert_eq!(item.z, f32::MAX, "pixel ({}, {})", x, y),
+            }
+        }
+    }
+}
+
+struct CountShader {
+    count: Arc<AtomicUsize>,
+}
+
+impl FragmentShader for CountShader {
+    fn shade(&self, _: &FragmentShaderPayload) -> Vector4 {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        vector4([0.0, 0.0, 0.0, 1.0])
+    }
+    fn update_camera(&mut self, _: &Camera) {}
+    fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
+}
+
+#[test]
+fn shade_covered_pixels_once() {
+    let (width, height) = (TILE_SIZE * 2 + 3, TILE_SIZE + 9);
+    // Two overlapping triangles, the second one behind the first.
+    let triangles = vec![
+        screen_triangle([(1.2, 2.4, -0.5), (60.8, 5.1, -0.5), (20.3, 35.6, -0.5)]),
+        screen_triangle([(5.5, 1.3, -0.9), (66.1, 38.2, -0.9), (3.7, 39.4, -0.9)]),
+    ];
+    let model = TriangulatedModel {
+        triangles,
+        material: None,
+        area: 0.0,
+    };
+    let mut rasterizer = Rasterizer::new(width, height, vec![model]);
+    let covered = rasterizer
+        .z_buffer
+        .iter()
+        .filter(|z| z.is_covered())
+        .count();
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let shader: Box<dyn FragmentShader> = Box::new(CountShader {
+        count: count.clone(),
+    });
+    let frame_buffer = rasterizer.rasterize(&shader);
+    let shaded = frame_buffer
+        .data
+        .iter()
+        .filter(|&c| c != &BACKGROUND_COLOR)
+        .count();
+
+    assert!(covered > 0);
+    assert_eq!(shaded, covered);
+    assert_eq!(count.load(Ordering::Relaxed), covered);
+}
+
+struct BlackShader;
+
+impl FragmentShader for BlackShader {
+    fn shade(&self, _: &FragmentShaderPayload) -> Vector4 {
+        vector4([0.0, 0.0, 0.0, 1.0])
+    }
+    fn update_camera(&mut self, _: &Camera) {}
+    fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
+}
+
+#[test]
+fn multisample_resolve() {
+    let (width, height) = (40, 40);
+    // Lower-right half of the screen, split by the diagonal x == y.
+    let triangle = screen_triangle([(-0.1, -0.1, -0.5), (40.1, -0.1, -0.5), (40.1, 40.1, -0.5)]);
+    let model = TriangulatedModel {
+        triangles: vec![triangle],
+        material: None,
+        area: 0.0,
+    };
+    let mut rasterizer = Rasterizer::with_multisample(width, height, vec![model], Multisample::X4);
+    assert_eq!(rasterizer.z_buffer.len(), width * height * 4);
+
+    let frame_buffer = rasterizer.rasterize(&(Box::new(BlackShader) as Box<dyn FragmentShader>));
+    // Framebuffer rows run top-down, screen space y runs upwards.
+    let pixel = |x: usize, y: usize| frame_buffer.get(x, height - 1 - y);
+
+    // Fully covered and uncovered pixels.
+    assert_eq!(pixel(30, 10).x(), 0.0);
+    assert_eq!(pixel(10, 30), &BACKGROUND_COLOR);
+    // Two of the four samples lie below the diagonal.
+    let edge = pixel(20, 20);
+    assert!((edge.x() - 0.5).abs() < 1e-6);
+    assert!((edge.w() - 1.0).abs() < 1e-6);
+}
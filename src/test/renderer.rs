@@ -0,0 +1,123 @@
+use crate::{
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, vector4, Vector3, Vector4},
+    },
+    interpolate_triangle,
+    pipeline::{
+        camera::Camera,
+        fragment_shader::{FragmentShader, FragmentShaderPayload},
+        light::Light,
+        model::Model,
+        shadow_map::ShadowMap,
+        vertex_shader::{
+            DefaultVertexShader, VertexShader, VertexShaderOutput, VertexShaderPayload,
+        },
+    },
+    renderer::Renderer,
+};
+use std::sync::{Arc, Mutex};
+
+// Pass the world position through as varyings and keep the modeling matrices it was given.
+#[derive(Default)]
+struct WorldPositionVertexShader {
+    models: Arc<Mutex<Vec<Matrix4>>>,
+}
+
+impl VertexShader for WorldPositionVertexShader {
+    fn shade(&self, payload: &VertexShaderPayload) -> VertexShaderOutput {
+        let mut models = self.models.lock().unwrap();
+        if !models.contains(payload.model) {
+            models.push(payload.model.clone());
+        }
+        let p = payload.model * &payload.vertex.position;
+        VertexShaderOutput {
+            varyings: vec![p.x(), p.y(), p.z()],
+            ..DefaultVertexShader.shade(payload)
+        }
+    }
+}
+
+// Record the number of fragments and the largest difference between the varyings
+// and the interpolated world position.
+struct CompareShader {
+    result: Arc<Mutex<(usize, f32)>>,
+}
+
+impl FragmentShader for CompareShader {
+    fn shade(
+        &self,
+        FragmentShaderPayload {
+            triangle,
+            barycenter,
+            varyings,
+            ..
+        }: &FragmentShaderPayload,
+    ) -> Vector4 {
+        let expected = Vector3::from(&interpolate_triangle!(triangle, world_position; *barycenter));
+        let error = (&expected - &vector3([varyings[0], varyings[1], varyings[2]])).norm();
+        let mut result = self.result.lock().unwrap();
+        *result = (result.0 + 1, result.1.max(error));
+        vector4([1.0, 1.0, 1.0, 1.0])
+    }
+    fn update_camera(&mut self, _: &Camera) {}
+    fn update_light(&mut self, _: &Light) {}
+    fn update_shadow_map(&mut self, _: Option<Arc<ShadowMap>>) {}
+}
+
+#[test]
+fn vertex_shader_varyings() {
+    let result = Arc::new(Mutex::new((0, 0.0)));
+    let mut renderer = Renderer::default()
+        .models(Model::from_obj("static/cube.obj"))
+        .camera(Camera::default().eye_position(vector3([0.3, 0.2, 1.5])))
+        .vertex_shader(Box::<WorldPositionVertexShader>::default());
+    renderer.shader = Some(Box::new(CompareShader {
+        result: result.clone(),
+    }));
+    renderer.render(64, 64);
+
+    let (fragments, max_error) = *result.lock().unwrap();
+    assert!(fragments > 0);
+    assert!(max_error < 1e-4);
+}
+
+#[test]
+fn vertex_shader_draws_every_instance() {
+    // Two small copies of the cube side by side.
+    let instances = vec![
+        &Matrix4::translation_matrix(-0.6, 0.0, 0.0) * &Matrix4::scale_matrix(0.4, 0.4, 0.4),
+        &Matrix4::translation_matrix(0.6, 0.0, 0.0) * &Matrix4::scale_matrix(0.4, 0.2, 0.4),
+    ];
+    let mut models = Model::from_obj("static/cube.obj");
+    models[0].instances = instances.clone();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let result = Arc::new(Mutex::new((0, 0.0)));
+    let mut renderer = Renderer::default()
+        .models(models)
+        .camera(Camera::default().eye_position(vector3([0.3, 0.2, 2.5])))
+        .vertex_shader(Box::new(WorldPositionVertexShader {
+            models: seen.clone(),
+        }));
+    renderer.shader = Some(Box::new(CompareShader {
+        result: result.clone(),
+    }));
+    renderer.render(64, 64);
+
+    // The mesh is shaded in model space once per instance, its fragments land in world space.
+    assert_eq!(*seen.lock().unwrap(), instances);
+    let (fragments, max_error) = *result.lock().unwrap();
+    assert!(fragments > 0);
+    assert!(max_error < 1e-4);
+}
+
+#[test]
+fn new_renderer_has_default_vertex_shader() {
+    let renderer = Renderer::new(32, 32)
+        .models(Model::from_obj("static/cube.obj"))
+        .camera(Camera::default().eye_position(vector3([0.3, 0.2, 1.5])))
+        .shader("z", "")
+        .unwrap();
+    let pixels = renderer.render(32, 32);
+    assert!(pixels.iter().any(|&p| p & 0xffffff != 0));
+}
@@ -0,0 +1,87 @@
+use crate::{
+    pipeline::scene_graph::SceneGraph,
+    ray_tracing::{
+        path_tracing::RayTracer,
+        sampler::{
+            make_sampler, HaltonSampler, Sampler, SobolSampler, StratifiedSampler, SAMPLER_NAMES,
+        },
+    },
+    scene::Scene,
+};
+
+// Every stratum of `0..n` is hit once.
+fn assert_stratified(values: impl Iterator<Item = usize>, n: usize) {
+    let mut hits = vec![0; n];
+    values.for_each(|i| hits[i] += 1);
+    assert!(hits.iter().all(|&h| h == 1), "{:?}", hits);
+}
+
+#[test]
+fn samples_are_in_unit_interval() {
+    for name in SAMPLER_NAMES {
+        let mut sampler = make_sampler(name, 16, 7).unwrap();
+        for sample in 0..64 {
+            sampler.start_pixel_sample(5, 9, sample);
+            // Past the prime bases of the Halton sampler as well.
+            for _ in 0..40 {
+                let u = sampler.get_1d();
+                let (s, t) = sampler.get_2d();
+                assert!([u, s, t].iter().all(|v| (0.0..1.0).contains(v)), "{}", name);
+            }
+        }
+    }
+    assert!(make_sampler("uniform", 16, 0).is_none());
+}
+
+#[test]
+fn pixel_samples_are_stratified() {
+    let samplers: [Box<dyn Sampler>; 2] = [
+        Box::new(StratifiedSampler::new(16, 3)),
+        Box::new(SobolSampler::new(3)),
+    ];
+    for mut sampler in samplers {
+        // Dimensions taken one after the other, as the path tracer does.
+        let values = (0..16)
+            .map(|sample| {
+                sampler.start_pixel_sample(2, 1, sample);
+                (0..4)
+                    .map(|_| (sampler.get_1d(), sampler.get_2d()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for dimension in 0..4 {
+            let samples = values.iter().map(|v| v[dimension]);
+            assert_stratified(samples.clone().map(|(u, _)| (u * 16.0) as usize), 16);
+            assert_stratified(
+                samples.map(|(_, (s, t))| (t * 4.0) as usize * 4 + (s * 4.0) as usize),
+                16,
+            );
+        }
+    }
+
+    let mut halton = HaltonSampler::new(3);
+    let values = (0..18)
+        .map(|sample| {
+            halton.start_pixel_sample(2, 1, sample);
+            halton.get_2d()
+        })
+        .collect::<Vec<_>>();
+    assert_stratified(values[..16].iter().map(|(s, _)| (s * 16.0) as usize), 16);
+    assert_stratified(values[..9].iter().map(|(_, t)| (t * 9.0) as usize), 9);
+    assert_stratified(values[9..18].iter().map(|(_, t)| (t * 9.0) as usize), 9);
+}
+
+#[test]
+fn same_seed_renders_same_image() {
+    let scene = Scene::from(&SceneGraph::load("static/cornell-box.gltf").unwrap());
+    let render = |name: &str, seed: u64| {
+        let mut ray_tracer = RayTracer::from_scene(&scene, 24, 24, 2);
+        ray_tracer.sampler = make_sampler(name, 2, seed).unwrap();
+        ray_tracer.render().data.clone()
+    };
+    for name in SAMPLER_NAMES {
+        let image = render(name, 1);
+        assert_eq!(image, render(name, 1), "{}", name);
+        assert_ne!(image, render(name, 2), "{}", name);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    algebra::vector_new::{vector3, Vector3},
+    interpolate_triangle,
+    pipeline::{material::MaterialNew, model::Model},
+    ray_tracing::{path_tracing::RayTracer, ray::Ray},
+    renderer::Renderer,
+    scene::Scene,
+    test::assert_near,
+};
+use rand::Rng;
+use std::path::Path;
+
+const SCENE: &str = r#"{
+    "camera": { "eye": [0.0, 2.0, 5.0], "target": [0.0, 2.0, 0.0], "fov": 60.0 },
+    "lights": [{ "position": [1.0, 2.0, 3.0], "intensity": 20.0 }],
+    "models": [
+        { "path": "cube.obj" },
+        {
+            "path": "cube.obj",
+            "translation": [3.0, 0.0, 0.0],
+            "rotation": [0.0, 0.0, 90.0],
+            "scale": [2.0, 1.0, 1.0],
+            "material": { "albedo": [1.0, 0.0, 0.0], "metalness": 1.0 }
+        },
+        { "path": "cube.obj", "material": { "emission": [1.0, 1.0, 1.0], "intensity": 4.0 } }
+    ]
+}"#;
+
+fn bounds(model: &Model) -> (Vector3, Vector3) {
+    let vertexs = model
+        .instances
+        .iter()
+        .flat_map(|m| model.instance_vertexs(m))
+        .collect::<Vec<_>>();
+    vertexs.iter().fold(
+        (vector3([f32::MAX; 3]), vector3([f32::MIN; 3])),
+        |(min, max), v| {
+            let p = &v.world_position;
+            (
+                vector3([min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())]),
+                vector3([max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())]),
+            )
+        },
+    )
+}
+
+#[test]
+fn load_scene_file() {
+    let scene = Scene::from_json(SCENE, Path::new("static")).unwrap();
+    assert_eq!(scene.models.len(), 3);
+
+    let camera = scene.camera.as_ref().unwrap();
+    assert_near(&camera.eye_position, &vector3([0.0, 2.0, 5.0]), 1e-4);
+    assert_near(&camera.gaze_direct, &vector3([0.0, 0.0, -1.0]), 1e-4);
+    assert!((camera.eye_fov - 60f32.to_radians()).abs() < 1e-6);
+    assert_eq!(scene.lights.len(), 1);
+    assert_eq!(scene.lights[0].intensity, 20.0);
+
+    // Scaled along X, rotated onto Y, then moved.
+    let (min, max) = bounds(&scene.models[1]);
+    assert_near(&min, &vector3([2.0, -2.0, -1.0]), 1e-4);
+    assert_near(&max, &vector3([4.0, 2.0, 1.0]), 1e-4);
+    let model = &scene.models[1];
+    for v in model.instance_vertexs(&model.instances[0]).iter() {
+        let n = Vector3::from(v.normal.as_ref().unwrap());
+        assert!((n.norm() - 1.0).abs() < 1e-4);
+        assert_eq!(v.normal.as_ref().unwrap().w(), 0.0);
+    }
+
+    match scene.models[1].material.as_deref() {
+        Some(MaterialNew::PBR(m)) => {
+            assert_eq!(m.albedo, vector3([1.0, 0.0, 0.0]));
+            assert_eq!(m.metalness, 1.0);
+        }
+        other => panic!("Unexpected material {:?}", other),
+    }
+    match scene.models[2].material.as_deref() {
+        Some(MaterialNew::Emissive(m)) => assert_eq!(m.intensity, 4.0),
+        other => panic!("Unexpected material {:?}", other),
+    }
+}
+
+#[test]
+fn scene_drives_both_pipelines() {
+    let scene = Scene::from_json(SCENE, Path::new("static")).unwrap();
+
+    let renderer = Renderer::default().scene(&scene);
+    let models = renderer.models.as_ref().unwrap();
+    // PBR materials are kept for the pbr-raster shader, the Phong shaders approximate them.
+    assert!(models[1]
+        .material
+        .as_ref()
+        .unwrap()
+        .pbr_material()
+        .is_some());
+    assert_eq!(renderer.light.as_ref().unwrap().intensity, 20.0);
+    assert_near(
+        &renderer.camera.as_ref().unwrap().eye_position,
+        &vector3([0.0, 2.0, 5.0]),
+        1e-4,
+    );
+
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert!(ray_tracer.objects[0]
+        .material
+        .as_ref()
+        .unwrap()
+        .pbr_material()
+        .is_some());
+    assert!(ray_tracer.objects[2].has_emit());
+    let ray = ray_tracer.pixel_to_ray(2, 2);
+    assert_near(&ray.origin, &vector3([0.0, 2.0, 5.0]), 1e-4);
+}
+
+#[test]
+fn instanced_models() {
+    let json = r#"{
+        "models": [{
+            "path": "cube.obj",
+            "translation": [0.0, 1.0, 0.0],
+            "instances": [
+                { "translation": [-2.0, 0.0, 0.0] },
+                { "translation": [2.0, 0.0, 0.0], "scale": [1.0, 3.0, 1.0] }
+            ]
+        }]
+    }"#;
+    let scene = Scene::from_json(json, Path::new("static")).unwrap();
+    let model = &scene.models[0];
+    assert_eq!(model.instances.len(), 2);
+    // The mesh itself stays in model space.
+    let cube = Model::from_obj("static/cube.obj");
+    assert_eq!(model.vertexs.len(), cube[0].vertexs.len());
+
+    let (min, max) = bounds(model);
+    assert_near(&min, &vector3([-3.0, -2.0, -1.0]), 1e-4);
+    assert_near(&max, &vector3([3.0, 4.0, 1.0]), 1e-4);
+
+    // Normals follow the inverse transpose, so they stay perpendicular to the faces.
+    let vertexs = model.instance_vertexs(&model.instances[1]);
+    for [a, b, c] in model.indices.iter() {
+        let p = |i: &u32| Vector3::from(&vertexs[*i as usize].world_position);
+        let face = (&p(b) - &p(a)).cross(&(&p(c) - &p(a))).normalized();
+        for i in [a, b, c] {
+            let n = Vector3::from(vertexs[*i as usize].normal.as_ref().unwrap());
+            assert!(n.dot(&face).abs() > 1.0 - 1e-4, "{:?} != {:?}", n, face);
+        }
+    }
+
+    // The ray tracer keeps one copy of the mesh and traces both instances of it.
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert_eq!(ray_tracer.objects.len(), 1);
+    assert_eq!(ray_tracer.objects[0].triangles.len(), cube[0].indices.len());
+    assert_eq!(ray_tracer.objects_tree.instances.primitives.len(), 2);
+}
+
+#[test]
+fn instances_trace_like_world_copies() {
+    let json = r#"{
+        "models": [{
+            "path": "cube.obj",
+            "instances": [
+                { "translation": [-1.5, 0.0, 0.0], "scale": [0.5, 1.0, 0.5] },
+                { "translation": [1.0, 0.5, -0.5], "scale": [0.8, 0.4, 1.2] }
+            ]
+        }]
+    }"#;
+    let scene = Scene::from_json(json, Path::new("static")).unwrap();
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert_eq!(ray_tracer.objects_tree.meshes.len(), 1);
+
+    // Every instance baked into the world, as the ray tracer should see them.
+    let model = &scene.models[0];
+    let triangles = model
+        .instances
+        .iter()
+        .flat_map(|matrix| {
+            Model {
+                vertexs: model.instance_vertexs(matrix),
+                instances: Vec::new(),
+                ..model.clone()
+            }
+            .triangles()
+        })
+        .collect::<Vec<_>>();
+    let mut rng = rand::thread_rng();
+    for _ in 0..2000 {
+        let mut random = || vector3([0.0; 3].map(|_: f32| rng.gen_range(-1.0f32..1.0)));
+        let ray = Ray::new(&(random() * 3.0), &random());
+        let expected = triangles
+            .iter()
+            .filter_map(|t| {
+                let barycenter = ray.intersect_triangle(t)?;
+                let p = Vector3::from(&interpolate_triangle!(t, position; barycenter));
+                Some((&p - &ray.origin).norm())
+            })
+            .filter(|&t| t > 0.001)
+            .min_by(f32::total_cmp);
+        match (ray_tracer.get_nearest_intersection(&ray), expected) {
+            (Some(hit), Some(t)) => assert!((hit.distance - t).abs() < 1e-3),
+            (hit, expected) => assert_eq!(hit.is_some(), expected.is_some()),
+        }
+    }
+}
+
+#[test]
+fn scene_errors() {
+    let error = |json: &str| Scene::from_json(json, Path::new("static")).unwrap_err();
+    assert!(error(r#"{ "models": [{ "path": "missing.obj" }] }"#).contains("Cannot find"));
+    assert!(
+        error(r#"{ "models": [{ "path": "cube.obj", "colour": 1 }] }"#).contains("unknown field")
+    );
+    assert!(
+        error(r#"{ "models": [{ "path": "cube.obj", "scale": 2 }] }"#).contains("invalid type")
+    );
+    assert!(Scene::load("static/missing.json")
+        .unwrap_err()
+        .contains("Cannot open"));
+}
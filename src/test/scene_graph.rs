@@ -0,0 +1,89 @@
+use crate::{
+    algebra::vector_new::{vector3, vector4, Vector3},
+    pipeline::{model::Model, scene_graph::SceneGraph},
+    ray_tracing::path_tracing::RayTracer,
+    renderer::Renderer,
+    scene::Scene,
+    test::assert_near,
+};
+
+fn bounds(model: &Model, instance: usize) -> (Vector3, Vector3) {
+    model
+        .instance_vertexs(&model.instances[instance])
+        .iter()
+        .fold(
+            (vector3([f32::MAX; 3]), vector3([f32::MIN; 3])),
+            |(min, max), v| {
+                let p = &v.world_position;
+                (
+                    vector3([min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())]),
+                    vector3([max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())]),
+                )
+            },
+        )
+}
+
+#[test]
+fn load_node_tree() {
+    let graph = SceneGraph::load("static/nested-cubes.gltf").unwrap();
+    assert_eq!(graph.nodes().len(), 4);
+    assert_eq!(graph.roots(), &[0, 3]);
+    let (base, arm, hand) = (
+        graph.find("base").unwrap(),
+        graph.find("arm").unwrap(),
+        graph.find("hand").unwrap(),
+    );
+    assert_eq!(graph.node(base).children, vec![arm]);
+    assert_eq!(graph.node(hand).parent, Some(arm));
+    assert_eq!(graph.node(base).mesh, None);
+    assert_eq!(graph.traverse(), vec![base, arm, hand, 3]);
+
+    // Scaled by the arm, rotated onto Y, then moved by the arm and the base.
+    let origin = graph.world_matrix(hand) * &vector4([0.0, 0.0, 0.0, 1.0]);
+    assert_near(&Vector3::from(&origin), &vector3([1.0, 0.5, 0.0]), 1e-4);
+}
+
+#[test]
+fn meshes_are_instanced_by_node() {
+    let graph = SceneGraph::load("static/nested-cubes.gltf").unwrap();
+    let models = graph.models();
+    // One mesh shared by three nodes.
+    assert_eq!(models.len(), 1);
+    assert_eq!(models[0].instances.len(), 3);
+    assert_eq!(models[0].vertexs.len(), 24);
+
+    let (min, max) = bounds(&models[0], 1);
+    assert_near(&min, &vector3([0.5, 0.0, -0.5]), 1e-4);
+    assert_near(&max, &vector3([1.5, 1.0, 0.5]), 1e-4);
+
+    let cornell_box = Model::from_gltf("static/cornell-box.gltf");
+    assert_eq!(cornell_box.len(), 8);
+}
+
+#[test]
+fn move_nodes_after_loading() {
+    let mut graph = SceneGraph::load("static/nested-cubes.gltf").unwrap();
+    let base = graph.find("base").unwrap();
+    let transform = graph
+        .node(base)
+        .transform
+        .clone()
+        .translation(vector3([0.0, 2.5, 0.0]));
+    graph.set_transform(base, transform);
+
+    // Children follow their parent, other roots stay in place.
+    let models = graph.models();
+    let (min, max) = bounds(&models[0], 1);
+    assert_near(&min, &vector3([0.5, 3.0, -0.5]), 1e-4);
+    assert_near(&max, &vector3([1.5, 4.0, 0.5]), 1e-4);
+    let (min, max) = bounds(&models[0], 2);
+    assert_near(&min, &vector3([-3.0, -1.0, -1.0]), 1e-4);
+    assert_near(&max, &vector3([-1.0, 1.0, 1.0]), 1e-4);
+
+    let scene = Scene::from(&graph);
+    let renderer = Renderer::default().scene(&scene);
+    assert_eq!(renderer.models.as_ref().unwrap()[0].instances.len(), 3);
+    let ray_tracer = RayTracer::from_scene(&scene, 4, 4, 1);
+    assert_eq!(ray_tracer.objects.len(), 1);
+    assert_eq!(ray_tracer.objects_tree.instances.primitives.len(), 3);
+}
@@ -0,0 +1,91 @@
+use crate::{
+    algebra::{
+        matrix_new::Matrix4,
+        vector_new::{vector3, vector4},
+    },
+    pipeline::{
+        light::{Light, LightKind},
+        model::{Model, Vertex},
+        shadow_map::{ShadowMap, ShadowMapSettings},
+        vertex_shader::Varyings,
+    },
+};
+
+// A double-sided horizontal square of half-size `r` at height `y`.
+fn square(y: f32, r: f32) -> Model {
+    let vertexs = [(-r, -r), (r, -r), (r, r), (-r, r)]
+        .iter()
+        .map(|&(x, z)| Vertex {
+            position: vector4([x, y, z, 1.0]),
+            world_position: vector4([x, y, z, 1.0]),
+            normal: Some(vector4([0.0, 1.0, 0.0, 0.0])),
+            texture_coordinate: None,
+            w_reciprocal: None,
+            varyings: Varyings::new(),
+        })
+        .collect();
+    Model {
+        indices: vec![[0, 1, 2], [0, 2, 3], [2, 1, 0], [3, 2, 0]],
+        vertexs,
+        material: None,
+        instances: vec![Matrix4::identity()],
+    }
+}
+
+#[test]
+fn occluder_casts_shadow() {
+    let models = vec![square(0.0, 2.0), square(1.0, 0.5)];
+    let light = Light::default().position(vector3([0.0, 5.0, 0.0]));
+    let settings = ShadowMapSettings {
+        size: 256,
+        ..ShadowMapSettings::default()
+    };
+    let shadow_map = ShadowMap::new(&models, &light, &settings);
+
+    assert_eq!(shadow_map.visibility(&vector3([0.0, 0.0, 0.0])), 0.0);
+    assert_eq!(shadow_map.visibility(&vector3([1.5, 0.0, 1.5])), 1.0);
+    // The occluder doesn't shadow itself.
+    assert_eq!(shadow_map.visibility(&vector3([0.1, 1.0, 0.1])), 1.0);
+}
+
+#[test]
+fn directional_light_casts_parallel_shadow() {
+    let models = vec![square(0.0, 2.0), square(1.0, 0.5)];
+    // Far away and slanted, the shadow is the occluder shifted by half its height.
+    let light = Light::default()
+        .kind(LightKind::Directional)
+        .position(vector3([0.0, 100.0, 0.0]))
+        .direction(vector3([1.0, -2.0, 0.0]));
+    let settings = ShadowMapSettings {
+        size: 256,
+        ..ShadowMapSettings::default()
+    };
+    let shadow_map = ShadowMap::new(&models, &light, &settings);
+
+    assert_eq!(shadow_map.visibility(&vector3([0.5, 0.0, 0.0])), 0.0);
+    assert_eq!(shadow_map.visibility(&vector3([-0.5, 0.0, 0.0])), 1.0);
+    assert_eq!(shadow_map.visibility(&vector3([1.5, 0.0, 0.0])), 1.0);
+    assert_eq!(shadow_map.visibility(&vector3([0.1, 1.0, 0.1])), 1.0);
+}
+
+#[test]
+fn spot_light_sees_along_its_cone() {
+    let models = vec![square(0.0, 2.0), square(1.0, 0.5)];
+    // Off to the side and aimed at the occluder, the scene center isn't in front of it.
+    let light = Light::default()
+        .kind(LightKind::Spot {
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        })
+        .position(vector3([-3.0, 4.0, 0.0]))
+        .direction(vector3([3.0, -3.0, 0.0]));
+    let settings = ShadowMapSettings {
+        size: 256,
+        ..ShadowMapSettings::default()
+    };
+    let shadow_map = ShadowMap::new(&models, &light, &settings);
+
+    assert_eq!(shadow_map.visibility(&vector3([1.0, 0.0, 0.0])), 0.0);
+    assert_eq!(shadow_map.visibility(&vector3([-0.5, 0.0, 0.0])), 1.0);
+    assert_eq!(shadow_map.visibility(&vector3([0.1, 1.0, 0.1])), 1.0);
+}
@@ -1,5 +1,6 @@
 use crate::ray_tracing::path_tracing::RayTracer;
 use minifb::{Key, Window, WindowOptions};
+use std::f32::consts::PI;
 
 pub struct PBRWindow {
     window: Window,
@@ -11,12 +12,28 @@ impl PBRWindow {
         Self { window }
     }
 
-    pub fn run(&mut self, ray_tracer: RayTracer) {
+    // One sample per pixel each frame until `spp` are in, moving the camera starts over.
+    pub fn run(&mut self, mut ray_tracer: RayTracer) {
         let (width, height) = self.window.get_size();
-        let buffer = ray_tracer.frame_buffer();
         while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+            self.window.get_keys().iter().for_each(|key| match key {
+                Key::D => ray_tracer.yaw_camera(PI / 180.0),
+                Key::A => ray_tracer.yaw_camera(-PI / 180.0),
+                Key::W => ray_tracer.pitch_camera(PI / 180.0),
+                Key::S => ray_tracer.pitch_camera(-PI / 180.0),
+                Key::Up => ray_tracer.zoom_camera(0.1),
+                Key::Down => ray_tracer.zoom_camera(-0.1),
+                _ => (),
+            });
+            if ray_tracer.passes < ray_tracer.spp {
+                ray_tracer.render_pass();
+                self.window.set_title(&format!(
+                    "TinyRenderer - {}/{} spp",
+                    ray_tracer.passes, ray_tracer.spp
+                ));
+            }
             self.window
-                .update_with_buffer(&buffer, width, height)
+                .update_with_buffer(&ray_tracer.frame_buffer(), width, height)
                 .unwrap()
         }
     }